@@ -0,0 +1,567 @@
+//! Futures for controlling services and waiting for them, and a stream of status changes.
+//!
+//! The futures work with any async runtime. They poll the service status the same way as the
+//! blocking functions in the [`wait`] module do, taking their time from an [`AsyncClock`]. The
+//! calls into the service control manager are short and made directly from the future.
+//!
+//! [`TimerClock`] sleeps using a shared background thread, so it needs no runtime support. Any
+//! runtime timer can be used instead by implementing [`AsyncClock`], for example with tokio:
+//!
+//! ```rust,ignore
+//! use std::pin::Pin;
+//! use std::time::{Duration, Instant};
+//! use windows_service::asynchronous::AsyncClock;
+//!
+//! struct TokioClock;
+//!
+//! impl AsyncClock for TokioClock {
+//!     type Sleep = Pin<Box<tokio::time::Sleep>>;
+//!
+//!     fn now(&self) -> Instant {
+//!         Instant::now()
+//!     }
+//!
+//!     fn sleep(&self, duration: Duration) -> Self::Sleep {
+//!         Box::pin(tokio::time::sleep(duration))
+//!     }
+//! }
+//! ```
+//!
+//! [`StatusStream`] reports the state changes of a service as a [`Stream`], based on the
+//! notifications described in the [`notify`] module.
+//!
+//! [`wait`]: crate::wait
+//! [`notify`]: crate::notify
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, VecDeque};
+use std::ffi::OsStr;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{mpsc, Arc, Mutex, OnceLock};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use futures_core::Stream;
+
+use crate::backend::ServiceBackend;
+#[cfg(windows)]
+use crate::notify::{NotificationWorker, NotifyHandle, ServiceNotification, ServiceNotifyMask};
+#[cfg(windows)]
+use crate::service::Service;
+use crate::service::{ServiceControl, ServiceState, ServiceStatus};
+use crate::wait::{DriveStep, StateDriver, StateWaiter, WaitStep};
+use crate::{lock, Error, Result};
+
+/// A source of time for the futures in this module.
+pub trait AsyncClock {
+    /// The future returned by [`AsyncClock::sleep`].
+    type Sleep: Future<Output = ()>;
+
+    fn now(&self) -> Instant;
+
+    fn sleep(&self, duration: Duration) -> Self::Sleep;
+}
+
+/// The system clock, with sleeps driven by a background thread shared by all [`Delay`]s.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TimerClock;
+
+impl AsyncClock for TimerClock {
+    type Sleep = Delay;
+
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) -> Delay {
+        Delay {
+            deadline: Instant::now().checked_add(duration),
+        }
+    }
+}
+
+/// A future that completes at a deadline, returned by [`TimerClock::sleep`].
+///
+/// A delay too long to be represented, such as `Duration::MAX`, never completes.
+#[derive(Debug)]
+pub struct Delay {
+    deadline: Option<Instant>,
+}
+
+impl Future for Delay {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let deadline = match self.deadline {
+            Some(deadline) => deadline,
+            None => return Poll::Pending,
+        };
+        if Instant::now() >= deadline {
+            return Poll::Ready(());
+        }
+        // The timer thread lives as long as the process, so sending cannot fail.
+        let _ = lock(timer_thread()).send(Timer {
+            deadline,
+            waker: cx.waker().clone(),
+        });
+        Poll::Pending
+    }
+}
+
+struct Timer {
+    deadline: Instant,
+    waker: Waker,
+}
+
+// Ordered by the reverse deadline, so that the earliest timer is at the top of the heap.
+impl Ord for Timer {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.deadline.cmp(&self.deadline)
+    }
+}
+
+impl PartialOrd for Timer {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Timer {
+    fn eq(&self, other: &Self) -> bool {
+        self.deadline == other.deadline
+    }
+}
+
+impl Eq for Timer {}
+
+fn timer_thread() -> &'static Mutex<mpsc::Sender<Timer>> {
+    static TIMER_THREAD: OnceLock<Mutex<mpsc::Sender<Timer>>> = OnceLock::new();
+    TIMER_THREAD.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel();
+        thread::Builder::new()
+            .name("windows-service-timer".to_owned())
+            .spawn(move || run_timers(receiver))
+            .expect("failed to spawn the timer thread");
+        Mutex::new(sender)
+    })
+}
+
+fn run_timers(receiver: mpsc::Receiver<Timer>) {
+    let mut timers = BinaryHeap::new();
+    loop {
+        let now = Instant::now();
+        while timers
+            .peek()
+            .is_some_and(|timer: &Timer| timer.deadline <= now)
+        {
+            timers.pop().unwrap().waker.wake();
+        }
+
+        let timer = match timers.peek() {
+            Some(next) => receiver.recv_timeout(next.deadline - now),
+            None => receiver
+                .recv()
+                .map_err(|_| mpsc::RecvTimeoutError::Disconnected),
+        };
+        match timer {
+            Ok(timer) => timers.push(timer),
+            Err(mpsc::RecvTimeoutError::Timeout) => (),
+            Err(mpsc::RecvTimeoutError::Disconnected) => return,
+        }
+    }
+}
+
+/// Poll the status of the service until it reaches the `target` state. Returns the final status.
+///
+/// This is the async counterpart of [`wait::wait_for_state`](crate::wait::wait_for_state), and
+/// fails in the same cases.
+pub async fn wait_for_state(
+    service: &impl ServiceBackend,
+    target: ServiceState,
+    timeout: Duration,
+    clock: &impl AsyncClock,
+) -> Result<ServiceStatus> {
+    let mut waiter = StateWaiter::new(target, timeout, clock.now());
+    loop {
+        let status = service.query_status()?;
+        match waiter.observe(&status, clock.now()).map_err(Error::Wait)? {
+            WaitStep::Reached => return Ok(status),
+            WaitStep::Sleep(duration) => clock.sleep(duration).await,
+        }
+    }
+}
+
+/// Start the service if needed and wait until it is running. Returns the final status.
+///
+/// This is the async counterpart of [`wait::start_and_wait`](crate::wait::start_and_wait).
+pub async fn start_and_wait(
+    service: &impl ServiceBackend,
+    arguments: &[&OsStr],
+    timeout: Duration,
+    clock: &impl AsyncClock,
+) -> Result<ServiceStatus> {
+    drive(service, ServiceState::Running, arguments, timeout, clock).await
+}
+
+/// Stop the service if needed and wait until it is stopped. Returns the final status.
+///
+/// This is the async counterpart of [`wait::stop_and_wait`](crate::wait::stop_and_wait).
+pub async fn stop_and_wait(
+    service: &impl ServiceBackend,
+    timeout: Duration,
+    clock: &impl AsyncClock,
+) -> Result<ServiceStatus> {
+    drive(service, ServiceState::Stopped, &[], timeout, clock).await
+}
+
+/// Stop the service if it is running and start it again. Returns the final status.
+///
+/// This is the async counterpart of [`wait::restart`](crate::wait::restart).
+pub async fn restart(
+    service: &impl ServiceBackend,
+    arguments: &[&OsStr],
+    timeout: Duration,
+    clock: &impl AsyncClock,
+) -> Result<ServiceStatus> {
+    stop_and_wait(service, timeout, clock).await?;
+    start_and_wait(service, arguments, timeout, clock).await
+}
+
+async fn drive(
+    service: &impl ServiceBackend,
+    target: ServiceState,
+    arguments: &[&OsStr],
+    timeout: Duration,
+    clock: &impl AsyncClock,
+) -> Result<ServiceStatus> {
+    let mut driver = StateDriver::new(target, timeout, clock.now());
+    loop {
+        let status = service.query_status()?;
+        match driver.observe(&status, clock.now()).map_err(Error::Wait)? {
+            DriveStep::Reached => return Ok(status),
+            DriveStep::Start => service.start(arguments)?,
+            DriveStep::Control(control) => {
+                service.control(control)?;
+            }
+            DriveStep::Sleep(duration) => clock.sleep(duration).await,
+        }
+    }
+}
+
+/// A stream of the statuses a service enters.
+///
+/// Created with [`Service::into_status_stream`]. The first status is the current one. The
+/// stream ends when the notifications fail, see [`StatusStream::take_error`].
+///
+/// [`Service::into_status_stream`]: crate::service::Service::into_status_stream
+pub struct StatusStream {
+    receiver: QueueReceiver<ServiceStatus>,
+    #[cfg(windows)]
+    _worker: NotificationWorker,
+}
+
+impl StatusStream {
+    #[cfg(windows)]
+    pub(crate) fn for_service(service: Service) -> Self {
+        let (sender, receiver) = queue();
+        let handle = NotifyHandle::Service(service);
+        let worker =
+            NotificationWorker::spawn(handle, ServiceNotifyMask::ALL_STATES, move |notification| {
+                match notification {
+                    Ok(ServiceNotification::StateChanged(status)) => {
+                        sender.send(status);
+                        true
+                    }
+                    Ok(_) => true,
+                    Err(e) => {
+                        sender.close(Some(e));
+                        false
+                    }
+                }
+            });
+        StatusStream {
+            receiver,
+            _worker: worker,
+        }
+    }
+
+    /// Take the error that ended the stream, if any.
+    pub fn take_error(&mut self) -> Option<Error> {
+        lock(&self.receiver.queue).error.take()
+    }
+}
+
+impl Stream for StatusStream {
+    type Item = ServiceStatus;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<ServiceStatus>> {
+        self.receiver.poll_next(cx)
+    }
+}
+
+/// A stream of the control events delivered to a service.
+///
+/// Created with [`service_control_handler::register_stream`]. The stream ends once the system
+/// releases the event handler, after delivering [`ServiceControl::Stop`],
+/// [`ServiceControl::Shutdown`] or [`ServiceControl::Preshutdown`].
+///
+/// [`service_control_handler::register_stream`]: crate::service_control_handler::register_stream
+pub struct ControlStream {
+    receiver: QueueReceiver<ServiceControl>,
+}
+
+impl ControlStream {
+    #[cfg_attr(not(windows), allow(dead_code))]
+    pub(crate) fn new() -> (QueueSender<ServiceControl>, Self) {
+        let (sender, receiver) = queue();
+        (sender, ControlStream { receiver })
+    }
+}
+
+impl Stream for ControlStream {
+    type Item = ServiceControl;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<ServiceControl>> {
+        self.receiver.poll_next(cx)
+    }
+}
+
+struct Queue<T> {
+    items: VecDeque<T>,
+    waker: Option<Waker>,
+    closed: bool,
+    error: Option<Error>,
+}
+
+/// Create a queue whose items are received as a stream.
+#[cfg_attr(not(windows), allow(dead_code))]
+fn queue<T>() -> (QueueSender<T>, QueueReceiver<T>) {
+    let queue = Arc::new(Mutex::new(Queue {
+        items: VecDeque::new(),
+        waker: None,
+        closed: false,
+        error: None,
+    }));
+    let sender = QueueSender {
+        queue: queue.clone(),
+    };
+    (sender, QueueReceiver { queue })
+}
+
+/// The sending half of a queue, which closes the queue when dropped.
+pub(crate) struct QueueSender<T> {
+    queue: Arc<Mutex<Queue<T>>>,
+}
+
+#[cfg_attr(not(windows), allow(dead_code))]
+impl<T> QueueSender<T> {
+    pub(crate) fn send(&self, item: T) {
+        let mut queue = lock(&self.queue);
+        queue.items.push_back(item);
+        if let Some(waker) = queue.waker.take() {
+            waker.wake();
+        }
+    }
+
+    pub(crate) fn close(&self, error: Option<Error>) {
+        let mut queue = lock(&self.queue);
+        queue.closed = true;
+        if error.is_some() {
+            queue.error = error;
+        }
+        if let Some(waker) = queue.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Drop for QueueSender<T> {
+    fn drop(&mut self) {
+        self.close(None);
+    }
+}
+
+struct QueueReceiver<T> {
+    queue: Arc<Mutex<Queue<T>>>,
+}
+
+impl<T> QueueReceiver<T> {
+    fn poll_next(&self, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let mut queue = lock(&self.queue);
+        if let Some(item) = queue.items.pop_front() {
+            Poll::Ready(Some(item))
+        } else if queue.closed {
+            Poll::Ready(None)
+        } else {
+            queue.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::future::{ready, Ready};
+    use std::io;
+
+    use futures::executor::block_on;
+    use futures::stream::poll_fn;
+    use futures::StreamExt;
+
+    use super::*;
+    use crate::backend::fake::{service_info, FakeScm};
+    use crate::backend::{ScmBackend, StatusBackend};
+    use crate::service::{ServiceAccess, ServiceControlAccept, ServiceExitCode, ServiceType};
+    use crate::service_manager::ServiceManagerAccess;
+    use crate::wait::WaitError;
+
+    /// A clock whose sleeps complete immediately, advancing the time and calling `on_sleep` with
+    /// the time slept so far.
+    struct FakeClock<F> {
+        start: Instant,
+        elapsed: Cell<Duration>,
+        on_sleep: F,
+    }
+
+    impl<F: Fn(Duration)> FakeClock<F> {
+        fn new(on_sleep: F) -> Self {
+            FakeClock {
+                start: Instant::now(),
+                elapsed: Cell::new(Duration::ZERO),
+                on_sleep,
+            }
+        }
+    }
+
+    impl<F: Fn(Duration)> AsyncClock for FakeClock<F> {
+        type Sleep = Ready<()>;
+
+        fn now(&self) -> Instant {
+            self.start + self.elapsed.get()
+        }
+
+        fn sleep(&self, duration: Duration) -> Ready<()> {
+            self.elapsed.set(self.elapsed.get() + duration);
+            (self.on_sleep)(self.elapsed.get());
+            ready(())
+        }
+    }
+
+    fn status(current_state: ServiceState, checkpoint: u32) -> ServiceStatus {
+        ServiceStatus {
+            service_type: ServiceType::OWN_PROCESS,
+            current_state,
+            controls_accepted: ServiceControlAccept::STOP,
+            exit_code: ServiceExitCode::NO_ERROR,
+            checkpoint,
+            wait_hint: Duration::from_secs(3),
+            process_id: None,
+        }
+    }
+
+    #[test]
+    fn test_start_stop_and_wait() {
+        let scm = FakeScm::new();
+        scm.set_instant_transitions(false);
+        let manager = scm.connect(ServiceManagerAccess::CREATE_SERVICE);
+        let service = manager
+            .create_service(
+                &service_info("svc", vec![]),
+                ServiceAccess::START | ServiceAccess::STOP | ServiceAccess::QUERY_STATUS,
+            )
+            .unwrap();
+        let status_handle = scm.status_handle("svc").unwrap();
+
+        // The service advances its checkpoint every second and settles after 5 seconds.
+        let clock = FakeClock::new(|elapsed: Duration| {
+            let current_state = service.query_status().unwrap().current_state;
+            let next_status = match current_state {
+                ServiceState::StartPending if elapsed.as_secs() >= 5 => {
+                    status(ServiceState::Running, 0)
+                }
+                ServiceState::StopPending if elapsed.as_secs() >= 10 => {
+                    status(ServiceState::Stopped, 0)
+                }
+                _ => status(current_state, elapsed.as_secs() as u32),
+            };
+            status_handle.set_service_status(next_status).unwrap();
+        });
+
+        let timeout = Duration::from_secs(30);
+        let running = block_on(start_and_wait(&service, &[], timeout, &clock)).unwrap();
+        assert_eq!(running.current_state, ServiceState::Running);
+        assert_eq!(clock.elapsed.get(), Duration::from_secs(5));
+
+        let stopped = block_on(stop_and_wait(&service, Duration::MAX, &clock)).unwrap();
+        assert_eq!(stopped.current_state, ServiceState::Stopped);
+        assert_eq!(clock.elapsed.get(), Duration::from_secs(10));
+
+        // Nothing reports progress any more, so waiting for the service to start times out.
+        let stalled = FakeClock::new(|_| {});
+        service.start(&[]).unwrap();
+        let result = block_on(wait_for_state(
+            &service,
+            ServiceState::Running,
+            Duration::from_secs(2),
+            &stalled,
+        ));
+        assert!(matches!(
+            result,
+            Err(Error::Wait(WaitError::Timeout { .. }))
+        ));
+    }
+
+    #[test]
+    fn test_timer_clock() {
+        let start = Instant::now();
+        block_on(async {
+            // Timers complete in the order of their deadlines regardless of when they were set.
+            let long = TimerClock.sleep(Duration::from_millis(50));
+            TimerClock.sleep(Duration::from_millis(10)).await;
+            assert!(start.elapsed() < Duration::from_millis(50));
+            long.await;
+        });
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+
+    #[test]
+    fn test_queue_stream() {
+        let (sender, receiver) = queue();
+        let sent = vec![
+            status(ServiceState::StartPending, 1),
+            status(ServiceState::Running, 0),
+        ];
+        let worker = thread::spawn({
+            let statuses = sent.clone();
+            move || {
+                for status in statuses {
+                    thread::sleep(Duration::from_millis(5));
+                    sender.send(status);
+                }
+                sender.close(Some(Error::Winapi(io::Error::from_raw_os_error(1072))));
+            }
+        });
+
+        let mut stream = poll_fn(|cx| receiver.poll_next(cx));
+        let received: Vec<ServiceStatus> = block_on((&mut stream).collect());
+        worker.join().unwrap();
+        assert_eq!(received, sent);
+        assert!(block_on(stream.next()).is_none());
+        assert!(matches!(
+            lock(&receiver.queue).error.take(),
+            Some(Error::Winapi(ref e)) if e.raw_os_error() == Some(1072)
+        ));
+    }
+
+    #[test]
+    fn test_control_stream_ends_when_sender_dropped() {
+        let (sender, mut stream) = ControlStream::new();
+        sender.send(ServiceControl::Stop);
+        drop(sender);
+        assert_eq!(block_on(stream.next()), Some(ServiceControl::Stop));
+        assert_eq!(block_on(stream.next()), None);
+    }
+}
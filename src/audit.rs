@@ -0,0 +1,437 @@
+//! Auditing of installed services.
+//!
+//! When the path of a service binary contains spaces and is not quoted, the system cannot tell
+//! where the path ends and the arguments begin. It tries each prefix that ends at a space in
+//! turn, so `C:\Program Files\My App\service.exe` starts `C:\Program.exe` if it exists, then
+//! `C:\Program Files\My.exe` and only then the intended binary. If any of those candidates can be
+//! written by an unprivileged user, they can run code with the privileges of the service.
+//!
+//! [`scan_unquoted_service_paths`] looks for such services. The analysis of a single command line
+//! in [`hijack_candidates`] and [`analyze_service_path`] is pure, and checks whether candidates
+//! are writable through a [`FileSystemView`], so it can be tested without a real file system.
+//!
+//! # Example
+//!
+#![cfg_attr(windows, doc = "```rust,no_run")]
+#![cfg_attr(not(windows), doc = "```rust,ignore")]
+//! use windows_service::audit::{scan_unquoted_service_paths, LocalFileSystem};
+//! use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+//!
+//! # fn main() -> windows_service::Result<()> {
+//! let manager = ServiceManager::local_computer(
+//!     None::<&str>,
+//!     ServiceManagerAccess::CONNECT | ServiceManagerAccess::ENUMERATE_SERVICE,
+//! )?;
+//! let report = scan_unquoted_service_paths(&manager, &LocalFileSystem)?;
+//! for service in &report.vulnerable_services {
+//!     println!("{}: {}", service.name, service.executable_path.display());
+//!     for candidate in service.candidates.iter().filter(|candidate| candidate.writable) {
+//!         println!("  writable: {}", candidate.path.display());
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::ffi::{OsStr, OsString};
+#[cfg(windows)]
+use std::fs::{self, OpenOptions};
+use std::path::{Path, PathBuf};
+
+use widestring::U16String;
+
+use crate::backend::{ScmBackend, ServiceBackend};
+use crate::service::{
+    split_unquoted_executable, unquoted_executable_len, ServiceAccess, ServiceConfig, ServiceInfo,
+    ServiceType,
+};
+use crate::service_manager::{ListServiceType, ServiceActiveState};
+use crate::{shell_escape, Result};
+
+/// Access to the file system needed to tell if a hijack candidate can be planted.
+///
+/// Implemented by [`LocalFileSystem`], and by closures for testing.
+pub trait FileSystemView {
+    /// Returns `true` if the current user can write a file at `path`, either by creating it or
+    /// by overwriting an existing file.
+    fn is_writable(&self, path: &Path) -> bool;
+}
+
+impl<F: Fn(&Path) -> bool> FileSystemView for F {
+    fn is_writable(&self, path: &Path) -> bool {
+        self(path)
+    }
+}
+
+/// The local file system, as seen by the current user.
+///
+/// Whether a missing file could be created is checked by creating and removing an empty probe
+/// file in the same directory, since that is the only reliable way to account for all the access
+/// control rules. Existing files are opened for writing, but not modified.
+#[cfg(windows)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LocalFileSystem;
+
+#[cfg(windows)]
+impl FileSystemView for LocalFileSystem {
+    fn is_writable(&self, path: &Path) -> bool {
+        if path.exists() {
+            return OpenOptions::new().write(true).open(path).is_ok();
+        }
+
+        let Some(directory) = path.parent() else {
+            return false;
+        };
+        let probe = directory.join(format!(".windows-service-audit-{}.tmp", std::process::id()));
+        match OpenOptions::new().write(true).create_new(true).open(&probe) {
+            Ok(_) => {
+                let _ = fs::remove_file(&probe);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+/// The result of [`scan_unquoted_service_paths`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UnquotedPathReport {
+    /// Services with an unquoted path that has writable hijack candidates.
+    pub vulnerable_services: Vec<UnquotedServicePath>,
+
+    /// Services whose config could not be queried, usually for lack of access rights.
+    pub inaccessible_services: Vec<String>,
+}
+
+/// A service with an unquoted path containing spaces.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnquotedServicePath {
+    /// Service name
+    pub name: String,
+
+    /// The command line of the service, as stored by the system.
+    pub executable_path: PathBuf,
+
+    /// The paths that the system tries before the intended binary, in order.
+    pub candidates: Vec<HijackCandidate>,
+
+    /// The service config with the executable path split from the arguments, which quotes the
+    /// path when passed to [`Service::change_config`]. The account password is `None`, which
+    /// leaves it unchanged.
+    ///
+    /// [`Service::change_config`]: crate::service::Service::change_config
+    pub suggested_service_info: ServiceInfo,
+}
+
+impl UnquotedServicePath {
+    /// Returns `true` if any of the candidates is writable.
+    pub fn is_exploitable(&self) -> bool {
+        self.candidates.iter().any(|candidate| candidate.writable)
+    }
+}
+
+/// A path that the system tries to start before the intended service binary.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct HijackCandidate {
+    pub path: PathBuf,
+
+    /// Whether the current user can write a file at the path.
+    pub writable: bool,
+}
+
+/// Returns the paths that the system tries to start before the intended binary when the command
+/// line is not quoted.
+///
+/// The intended binary is taken to end at the first `.exe` followed by a space or the end of the
+/// command line, like in [`ServiceConfig::split_executable_path`]. Without a `.exe`, it spans the
+/// command line up to the first `"` or argument starting with `-` or `/`. Every prefix of it that
+/// ends at a space is a candidate, with `.exe` appended when the file name has no extension.
+/// Quoted command lines and paths without spaces have no candidates.
+pub fn hijack_candidates(command_line: &OsStr) -> Vec<PathBuf> {
+    const EXTENSION_SEPARATOR: u16 = '.' as u16;
+    let is_path_separator = |c: &u16| *c == '\\' as u16 || *c == '/' as u16;
+    let is_space = |c: &u16| *c == ' ' as u16 || *c == '\t' as u16;
+
+    let executable = match split_unquoted_command_line(command_line) {
+        Some((executable, _)) => U16String::from_os_str(&executable),
+        None => return Vec::new(),
+    };
+    let wide = executable.as_slice();
+
+    (1..wide.len())
+        .filter(|&end| is_space(&wide[end]) && !is_space(&wide[end - 1]))
+        .map(|end| {
+            let prefix = &wide[..end];
+            let file_name_start = prefix
+                .iter()
+                .rposition(is_path_separator)
+                .map_or(0, |i| i + 1);
+
+            let mut candidate = U16String::from_vec(prefix).to_os_string();
+            if !prefix[file_name_start..].contains(&EXTENSION_SEPARATOR) {
+                candidate.push(".exe");
+            }
+            PathBuf::from(candidate)
+        })
+        .collect()
+}
+
+/// Split an unquoted command line into the intended binary, as described in
+/// [`hijack_candidates`], and the arguments. Returns `None` if the command line is quoted.
+fn split_unquoted_command_line(command_line: &OsStr) -> Option<(OsString, OsString)> {
+    if let Some(split) = split_unquoted_executable(command_line) {
+        return Some(split);
+    }
+
+    let wide = U16String::from_os_str(command_line);
+    let wide = wide.as_slice();
+    let len = unquoted_executable_len(wide)?;
+    Some((
+        U16String::from_vec(&wide[..len]).to_os_string(),
+        U16String::from_vec(&wide[len..]).to_os_string(),
+    ))
+}
+
+/// Check the config of a single service for an unquoted path containing spaces.
+///
+/// Returns `None` for drivers, which are not started by command line, and for services with a
+/// quoted path or a path without spaces.
+pub fn analyze_service_path(
+    name: &str,
+    config: &ServiceConfig,
+    file_system: &impl FileSystemView,
+) -> Option<UnquotedServicePath> {
+    if config
+        .service_type
+        .intersects(ServiceType::KERNEL_DRIVER | ServiceType::FILE_SYSTEM_DRIVER)
+    {
+        return None;
+    }
+
+    let candidates: Vec<HijackCandidate> = hijack_candidates(config.executable_path.as_os_str())
+        .into_iter()
+        .map(|path| HijackCandidate {
+            writable: file_system.is_writable(&path),
+            path,
+        })
+        .collect();
+    if candidates.is_empty() {
+        return None;
+    }
+
+    let (executable_path, arguments) =
+        split_unquoted_command_line(config.executable_path.as_os_str())?;
+    Some(UnquotedServicePath {
+        name: name.to_owned(),
+        executable_path: config.executable_path.clone(),
+        candidates,
+        suggested_service_info: ServiceInfo {
+            name: OsString::from(name),
+            display_name: config.display_name.clone(),
+            service_type: config.service_type,
+            start_type: config.start_type,
+            error_control: config.error_control,
+            executable_path: PathBuf::from(executable_path),
+            launch_arguments: shell_escape::split_arguments(&arguments),
+            dependencies: config.dependencies.clone(),
+            account_name: config.account_name.clone(),
+            account_password: None,
+        },
+    })
+}
+
+/// Scan all Win32 services for unquoted paths with writable hijack candidates.
+///
+/// The service manager must be open with the
+/// [`ServiceManagerAccess::ENUMERATE_SERVICE`] access right.
+///
+/// [`ServiceManagerAccess::ENUMERATE_SERVICE`]:
+/// crate::service_manager::ServiceManagerAccess::ENUMERATE_SERVICE
+pub fn scan_unquoted_service_paths(
+    manager: &impl ScmBackend,
+    file_system: &impl FileSystemView,
+) -> Result<UnquotedPathReport> {
+    let mut report = UnquotedPathReport::default();
+
+    for entry in manager.get_all_services(ListServiceType::WIN32, ServiceActiveState::ALL)? {
+        let config = manager
+            .open_service(OsStr::new(&entry.name), ServiceAccess::QUERY_CONFIG)
+            .and_then(|service| service.query_config());
+        match config {
+            Ok(config) => report.vulnerable_services.extend(
+                analyze_service_path(&entry.name, &config, file_system)
+                    .filter(UnquotedServicePath::is_exploitable),
+            ),
+            Err(_) => report.inaccessible_services.push(entry.name),
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::fake::FakeScm;
+    use crate::service::{ServiceErrorControl, ServiceStartType};
+    use crate::service_manager::ServiceManagerAccess;
+
+    fn candidates(command_line: &str) -> Vec<PathBuf> {
+        hijack_candidates(OsStr::new(command_line))
+    }
+
+    fn service_info(name: &str, executable_path: &str, launch_arguments: &[&str]) -> ServiceInfo {
+        ServiceInfo {
+            name: OsString::from(name),
+            display_name: OsString::from(name),
+            service_type: ServiceType::OWN_PROCESS,
+            start_type: ServiceStartType::AutoStart,
+            error_control: ServiceErrorControl::Normal,
+            executable_path: PathBuf::from(executable_path),
+            launch_arguments: launch_arguments.iter().map(OsString::from).collect(),
+            dependencies: vec![],
+            account_name: None,
+            account_password: None,
+        }
+    }
+
+    #[test]
+    fn test_hijack_candidates() {
+        assert_eq!(
+            candidates(r"C:\Program Files\My App\service.exe -k  arg"),
+            [
+                PathBuf::from(r"C:\Program.exe"),
+                PathBuf::from(r"C:\Program Files\My.exe"),
+            ]
+        );
+        assert_eq!(
+            candidates(r"C:\Program Files\My.App Dir\service.exe"),
+            [
+                PathBuf::from(r"C:\Program.exe"),
+                PathBuf::from(r"C:\Program Files\My.App"),
+            ]
+        );
+        assert_eq!(
+            candidates(r"C:\Program Files\app\service"),
+            [PathBuf::from(r"C:\Program.exe")]
+        );
+        assert!(candidates(r#""C:\Program Files\app\service.exe" -k arg"#).is_empty());
+        assert!(candidates(r"C:\Windows\system32\svchost.exe -k netsvcs").is_empty());
+        assert_eq!(
+            candidates(r"C:\Program Files\app\svc.bin --run other.exe"),
+            [PathBuf::from(r"C:\Program.exe")]
+        );
+        assert!(candidates(r"C:\tools\svc.bin --run other.exe").is_empty());
+    }
+
+    #[test]
+    fn test_analyze_service_path() {
+        let mut config = ServiceConfig {
+            service_type: ServiceType::OWN_PROCESS,
+            start_type: ServiceStartType::AutoStart,
+            error_control: ServiceErrorControl::Normal,
+            executable_path: PathBuf::from(r"C:\Program Files\My App\service.exe --service"),
+            load_order_group: None,
+            tag_id: 0,
+            dependencies: vec![],
+            account_name: Some(OsString::from("LocalSystem")),
+            display_name: OsString::from("My service"),
+        };
+        let writable = |path: &Path| path.to_string_lossy().starts_with(r"C:\Program Files\");
+
+        let service = analyze_service_path("my_service", &config, &writable).unwrap();
+        assert!(service.is_exploitable());
+        assert_eq!(
+            service.candidates,
+            [
+                HijackCandidate {
+                    path: PathBuf::from(r"C:\Program.exe"),
+                    writable: false,
+                },
+                HijackCandidate {
+                    path: PathBuf::from(r"C:\Program Files\My.exe"),
+                    writable: true,
+                },
+            ]
+        );
+        assert_eq!(
+            service.suggested_service_info,
+            ServiceInfo {
+                display_name: OsString::from("My service"),
+                account_name: Some(OsString::from("LocalSystem")),
+                ..service_info(
+                    "my_service",
+                    r"C:\Program Files\My App\service.exe",
+                    &["--service"]
+                )
+            }
+        );
+
+        config.executable_path = PathBuf::from(r"C:\Program Files\My App\service.bin --run a.exe");
+        let service = analyze_service_path("my_service", &config, &writable).unwrap();
+        assert_eq!(service.candidates.len(), 2);
+        assert_eq!(
+            (
+                service.suggested_service_info.executable_path,
+                service.suggested_service_info.launch_arguments
+            ),
+            (
+                PathBuf::from(r"C:\Program Files\My App\service.bin"),
+                vec![OsString::from("--run"), OsString::from("a.exe")]
+            )
+        );
+
+        config.service_type = ServiceType::KERNEL_DRIVER;
+        assert_eq!(analyze_service_path("my_service", &config, &writable), None);
+    }
+
+    #[test]
+    fn test_scan_unquoted_service_paths() {
+        let scm = FakeScm::new();
+        let manager = scm.connect(ServiceManagerAccess::CREATE_SERVICE);
+        let create = |service_info: ServiceInfo| {
+            manager
+                .create_service(&service_info, ServiceAccess::QUERY_CONFIG)
+                .unwrap();
+        };
+        create(service_info(
+            "quoted",
+            r"C:\Program Files\app\service.exe",
+            &[],
+        ));
+        // A path without spaces is not quoted, so splitting it across the arguments results in an
+        // unquoted command line.
+        create(service_info(
+            "unquoted",
+            r"C:\Program",
+            &[r"Files\app\service.exe", "--service"],
+        ));
+        create(service_info(
+            "unquoted_readonly",
+            r"D:\Tools",
+            &[r"Dir\service.exe"],
+        ));
+        let writable = |path: &Path| path.to_string_lossy().starts_with(r"C:\");
+
+        let report = scan_unquoted_service_paths(
+            &scm.connect(ServiceManagerAccess::ENUMERATE_SERVICE),
+            &writable,
+        )
+        .unwrap();
+        assert_eq!(report.inaccessible_services, Vec::<String>::new());
+        assert_eq!(report.vulnerable_services.len(), 1);
+        let service = &report.vulnerable_services[0];
+        assert_eq!(service.name, "unquoted");
+        assert_eq!(
+            service.suggested_service_info,
+            ServiceInfo {
+                account_name: Some(OsString::from("LocalSystem")),
+                ..service_info(
+                    "unquoted",
+                    r"C:\Program Files\app\service.exe",
+                    &["--service"]
+                )
+            }
+        );
+    }
+}
@@ -0,0 +1,200 @@
+//! Abstractions over the service control manager.
+//!
+//! [`ServiceManager`], [`Service`] and [`ServiceStatusHandle`] talk to the system directly. The
+//! traits in this module describe the same operations, so that the code managing or implementing
+//! services can be written against the traits and exercised with the in-memory
+//! [`fake::FakeScm`] in tests, without a real service control manager.
+//!
+//! # Example
+//!
+#![cfg_attr(windows, doc = "```rust,no_run")]
+#![cfg_attr(not(windows), doc = "```rust,ignore")]
+//! use std::ffi::OsStr;
+//! use windows_service::backend::{ScmBackend, ServiceBackend};
+//! use windows_service::service::{ServiceAccess, ServiceState};
+//! use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+//!
+//! // Works with both `ServiceManager` and `FakeServiceManager`.
+//! fn ensure_started(manager: &impl ScmBackend, name: &str) -> windows_service::Result<()> {
+//!     let service = manager.open_service(
+//!         OsStr::new(name),
+//!         ServiceAccess::QUERY_STATUS | ServiceAccess::START,
+//!     )?;
+//!     if service.query_status()?.current_state == ServiceState::Stopped {
+//!         service.start(&[])?;
+//!     }
+//!     Ok(())
+//! }
+//!
+//! # fn main() -> windows_service::Result<()> {
+//! let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)?;
+//! ensure_started(&manager, "my_service")?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::ffi::OsStr;
+
+#[cfg(windows)]
+use crate::service::Service;
+use crate::service::{
+    ServiceAccess, ServiceConfig, ServiceControl, ServiceDependency, ServiceFailureActions,
+    ServiceInfo, ServiceStatus,
+};
+#[cfg(windows)]
+use crate::service_control_handler::ServiceStatusHandle;
+#[cfg(windows)]
+use crate::service_manager::ServiceManager;
+use crate::service_manager::{ListServiceType, ServiceActiveState, ServiceEntry};
+use crate::Result;
+
+pub mod fake;
+
+/// Operations on the service control manager database.
+///
+/// Implemented by [`ServiceManager`] and [`fake::FakeServiceManager`].
+pub trait ScmBackend {
+    /// The service object returned when opening or creating services.
+    type Service: ServiceBackend;
+
+    /// Open an existing service. See [`ServiceManager::open_service`].
+    fn open_service(&self, name: &OsStr, request_access: ServiceAccess) -> Result<Self::Service>;
+
+    /// Create a service. See [`ServiceManager::create_service`].
+    fn create_service(
+        &self,
+        service_info: &ServiceInfo,
+        service_access: ServiceAccess,
+    ) -> Result<Self::Service>;
+
+    /// Enumerate services. See [`ServiceManager::get_all_services`].
+    fn get_all_services(
+        &self,
+        list_service_type: ListServiceType,
+        service_active_state: ServiceActiveState,
+    ) -> Result<Vec<ServiceEntry>>;
+}
+
+/// Operations on a single service.
+///
+/// Implemented by [`Service`] and [`fake::FakeService`].
+pub trait ServiceBackend {
+    /// Start the service. See [`Service::start`].
+    fn start(&self, service_arguments: &[&OsStr]) -> Result<()>;
+
+    /// Send a control command to the service and return its status.
+    ///
+    /// Only the control commands that can be sent with `ControlService` are supported, that is
+    /// [`ServiceControl::Stop`], [`ServiceControl::Pause`], [`ServiceControl::Continue`],
+    /// [`ServiceControl::Interrogate`], [`ServiceControl::ParamChange`], the
+    /// `ServiceControl::NetBind*` family and [`ServiceControl::UserEvent`].
+    fn control(&self, control: ServiceControl) -> Result<ServiceStatus>;
+
+    /// Get the service status. See [`Service::query_status`].
+    fn query_status(&self) -> Result<ServiceStatus>;
+
+    /// Get the service config. See [`Service::query_config`].
+    fn query_config(&self) -> Result<ServiceConfig>;
+
+    /// Update the service config. See [`Service::change_config`].
+    fn change_config(&self, service_info: &ServiceInfo) -> Result<()>;
+
+    /// Replace the dependencies of the service. See [`Service::set_dependencies`].
+    fn set_dependencies(&self, dependencies: &[ServiceDependency]) -> Result<()>;
+
+    /// Get the configured failure actions. See [`Service::get_failure_actions`].
+    fn get_failure_actions(&self) -> Result<ServiceFailureActions>;
+
+    /// Update the failure actions. See [`Service::update_failure_actions`].
+    fn update_failure_actions(&self, update: ServiceFailureActions) -> Result<()>;
+
+    /// Mark the service for deletion. See [`Service::delete`].
+    fn delete(&self) -> Result<()>;
+
+    /// Get the services that depend on the service in the given state, in the order they can be
+    /// stopped. See [`Service::dependent_services`].
+    fn dependent_services(&self, service_state: ServiceActiveState) -> Result<Vec<ServiceEntry>>;
+}
+
+/// Reporting the status of a running service to the service control manager.
+///
+/// Implemented by [`ServiceStatusHandle`] and [`fake::FakeStatusHandle`].
+pub trait StatusBackend {
+    /// Report the new service status. See [`ServiceStatusHandle::set_service_status`].
+    fn set_service_status(&self, service_status: ServiceStatus) -> Result<()>;
+}
+
+#[cfg(windows)]
+impl ScmBackend for ServiceManager {
+    type Service = Service;
+
+    fn open_service(&self, name: &OsStr, request_access: ServiceAccess) -> Result<Service> {
+        ServiceManager::open_service(self, name, request_access)
+    }
+
+    fn create_service(
+        &self,
+        service_info: &ServiceInfo,
+        service_access: ServiceAccess,
+    ) -> Result<Service> {
+        ServiceManager::create_service(self, service_info, service_access)
+    }
+
+    fn get_all_services(
+        &self,
+        list_service_type: ListServiceType,
+        service_active_state: ServiceActiveState,
+    ) -> Result<Vec<ServiceEntry>> {
+        ServiceManager::get_all_services(self, list_service_type, service_active_state)
+    }
+}
+
+#[cfg(windows)]
+impl ServiceBackend for Service {
+    fn start(&self, service_arguments: &[&OsStr]) -> Result<()> {
+        Service::start(self, service_arguments)
+    }
+
+    fn control(&self, control: ServiceControl) -> Result<ServiceStatus> {
+        self.send_control_command(control)
+    }
+
+    fn query_status(&self) -> Result<ServiceStatus> {
+        Service::query_status(self)
+    }
+
+    fn query_config(&self) -> Result<ServiceConfig> {
+        Service::query_config(self)
+    }
+
+    fn change_config(&self, service_info: &ServiceInfo) -> Result<()> {
+        Service::change_config(self, service_info)
+    }
+
+    fn set_dependencies(&self, dependencies: &[ServiceDependency]) -> Result<()> {
+        Service::set_dependencies(self, dependencies)
+    }
+
+    fn get_failure_actions(&self) -> Result<ServiceFailureActions> {
+        Service::get_failure_actions(self)
+    }
+
+    fn update_failure_actions(&self, update: ServiceFailureActions) -> Result<()> {
+        Service::update_failure_actions(self, update)
+    }
+
+    fn delete(&self) -> Result<()> {
+        Service::delete(self)
+    }
+
+    fn dependent_services(&self, service_state: ServiceActiveState) -> Result<Vec<ServiceEntry>> {
+        Service::dependent_services(self, service_state)
+    }
+}
+
+#[cfg(windows)]
+impl StatusBackend for ServiceStatusHandle {
+    fn set_service_status(&self, service_status: ServiceStatus) -> Result<()> {
+        ServiceStatusHandle::set_service_status(self, service_status)
+    }
+}
@@ -0,0 +1,1308 @@
+//! An in-memory service control manager.
+//!
+//! [`FakeScm`] keeps a database of services and emulates the behaviour of the system service
+//! control manager closely enough to test code that installs, configures and controls services:
+//! state transitions, dependencies, handle access rights, deletion semantics and the Win32 error
+//! codes returned by the system. It does not call into the operating system.
+//!
+//! By default control requests complete instantly, i.e. starting a service puts it straight into
+//! the [`ServiceState::Running`] state. Use [`FakeScm::set_instant_transitions`] to leave services
+//! in the pending states instead, and drive them forward with a [`FakeStatusHandle`] the same way
+//! a real service reports its status.
+//!
+//! # Example
+//!
+//! ```rust
+//! use std::ffi::{OsStr, OsString};
+//! use windows_service::backend::fake::FakeScm;
+//! use windows_service::backend::{ScmBackend, ServiceBackend};
+//! use windows_service::service::{
+//!     ServiceAccess, ServiceErrorControl, ServiceInfo, ServiceStartType, ServiceState,
+//!     ServiceType,
+//! };
+//! use windows_service::service_manager::ServiceManagerAccess;
+//!
+//! # fn main() -> windows_service::Result<()> {
+//! let scm = FakeScm::new();
+//! let manager = scm.connect(ServiceManagerAccess::CREATE_SERVICE);
+//!
+//! let service_info = ServiceInfo {
+//!     name: OsString::from("my_service"),
+//!     display_name: OsString::from("My service"),
+//!     service_type: ServiceType::OWN_PROCESS,
+//!     start_type: ServiceStartType::OnDemand,
+//!     error_control: ServiceErrorControl::Normal,
+//!     executable_path: "C:\\my_service.exe".into(),
+//!     launch_arguments: vec![],
+//!     dependencies: vec![],
+//!     account_name: None,
+//!     account_password: None,
+//! };
+//! let service = manager.create_service(
+//!     &service_info,
+//!     ServiceAccess::START | ServiceAccess::QUERY_STATUS,
+//! )?;
+//! service.start(&[OsStr::new("--verbose")])?;
+//! assert_eq!(service.query_status()?.current_state, ServiceState::Running);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::ffi::{OsStr, OsString};
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use super::{ScmBackend, ServiceBackend, StatusBackend};
+use crate::dependency_graph::{DependencyGraph, ServiceDependencies};
+use crate::service::{
+    RawServiceInfo, ServiceAccess, ServiceActionType, ServiceConfig, ServiceControl,
+    ServiceControlAccept, ServiceDependency, ServiceExitCode, ServiceFailureActions,
+    ServiceFailureResetPeriod, ServiceInfo, ServiceStartType, ServiceState, ServiceStatus,
+    ServiceType,
+};
+use crate::service_manager::{
+    ListServiceType, ServiceActiveState, ServiceEntry, ServiceManagerAccess,
+};
+use crate::{lock, Error, Result};
+
+// Win32 error codes returned by the fake, defined here so that they are available on all targets.
+const ERROR_ACCESS_DENIED: i32 = 5;
+const ERROR_INVALID_HANDLE: i32 = 6;
+const ERROR_INVALID_PARAMETER: i32 = 87;
+const ERROR_INVALID_NAME: i32 = 123;
+const ERROR_DEPENDENT_SERVICES_RUNNING: i32 = 1051;
+const ERROR_INVALID_SERVICE_CONTROL: i32 = 1052;
+const ERROR_SERVICE_ALREADY_RUNNING: i32 = 1056;
+const ERROR_SERVICE_DISABLED: i32 = 1058;
+const ERROR_CIRCULAR_DEPENDENCY: i32 = 1059;
+const ERROR_SERVICE_DOES_NOT_EXIST: i32 = 1060;
+const ERROR_SERVICE_CANNOT_ACCEPT_CTRL: i32 = 1061;
+const ERROR_SERVICE_NOT_ACTIVE: i32 = 1062;
+const ERROR_SERVICE_DEPENDENCY_FAIL: i32 = 1068;
+const ERROR_SERVICE_MARKED_FOR_DELETE: i32 = 1072;
+const ERROR_SERVICE_EXISTS: i32 = 1073;
+const ERROR_SERVICE_DEPENDENCY_DELETED: i32 = 1075;
+const ERROR_SERVICE_NEVER_STARTED: u32 = 1077;
+const ERROR_DUPLICATE_SERVICE_NAME: i32 = 1078;
+
+/// The maximum length of a service name accepted by the system.
+const MAX_SERVICE_NAME_LEN: usize = 256;
+
+/// The process id assigned to the first service started by the fake.
+const FIRST_PROCESS_ID: u32 = 1000;
+
+/// An in-memory service control manager.
+///
+/// Cloning a `FakeScm` yields another reference to the same database.
+#[derive(Debug, Clone)]
+pub struct FakeScm {
+    state: Arc<Mutex<ScmState>>,
+}
+
+impl Default for FakeScm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FakeScm {
+    /// Create an empty service control manager database.
+    pub fn new() -> Self {
+        FakeScm {
+            state: Arc::new(Mutex::new(ScmState {
+                services: Vec::new(),
+                next_id: 0,
+                next_process_id: FIRST_PROCESS_ID,
+                instant_transitions: true,
+            })),
+        }
+    }
+
+    /// Connect to the service control manager with the given access rights, the equivalent of
+    /// [`ServiceManager::local_computer`].
+    ///
+    /// [`ServiceManager::local_computer`]: crate::service_manager::ServiceManager::local_computer
+    pub fn connect(&self, request_access: ServiceManagerAccess) -> FakeServiceManager {
+        FakeServiceManager {
+            state: self.state.clone(),
+            access: request_access,
+        }
+    }
+
+    /// Obtain the status handle of a service, the equivalent of what the service itself receives
+    /// from [`service_control_handler::register`].
+    ///
+    /// [`service_control_handler::register`]: crate::service_control_handler::register
+    pub fn status_handle(&self, name: impl AsRef<OsStr>) -> Result<FakeStatusHandle> {
+        let state = lock(&self.state);
+        let index = state
+            .find(name.as_ref())
+            .ok_or_else(|| win32_error(ERROR_SERVICE_DOES_NOT_EXIST))?;
+        Ok(FakeStatusHandle {
+            state: self.state.clone(),
+            id: state.services[index].id,
+            reported: Arc::new(Mutex::new(Vec::new())),
+        })
+    }
+
+    /// Choose whether control requests complete instantly, which is the default.
+    ///
+    /// When disabled, starting, stopping, pausing and continuing a service leaves it in the
+    /// corresponding pending state until a new status is reported through a
+    /// [`FakeStatusHandle`].
+    pub fn set_instant_transitions(&self, instant_transitions: bool) {
+        lock(&self.state).instant_transitions = instant_transitions;
+    }
+
+    /// Set the controls that the service accepts once it completes an instant transition into the
+    /// [`ServiceState::Running`] state. Defaults to [`ServiceControlAccept::STOP`].
+    pub fn set_controls_accepted(
+        &self,
+        name: impl AsRef<OsStr>,
+        controls_accepted: ServiceControlAccept,
+    ) -> Result<()> {
+        self.with_service(name.as_ref(), |record| {
+            record.controls_accepted = controls_accepted
+        })
+    }
+
+    /// Assign the service to a load ordering group, which cannot be done through
+    /// [`ServiceInfo`].
+    pub fn set_load_order_group(
+        &self,
+        name: impl AsRef<OsStr>,
+        load_order_group: Option<OsString>,
+    ) -> Result<()> {
+        self.with_service(name.as_ref(), |record| {
+            record.config.load_order_group = load_order_group
+        })
+    }
+
+    /// Returns `true` if the database contains a service with the given name, including services
+    /// marked for deletion.
+    pub fn contains(&self, name: impl AsRef<OsStr>) -> bool {
+        lock(&self.state).find(name.as_ref()).is_some()
+    }
+
+    /// Returns the arguments the service was last started with.
+    pub fn start_arguments(&self, name: impl AsRef<OsStr>) -> Option<Vec<OsString>> {
+        let state = lock(&self.state);
+        state
+            .find(name.as_ref())
+            .map(|index| state.services[index].start_arguments.clone())
+    }
+
+    fn with_service(&self, name: &OsStr, f: impl FnOnce(&mut ServiceRecord)) -> Result<()> {
+        let mut state = lock(&self.state);
+        let index = state
+            .find(name)
+            .ok_or_else(|| win32_error(ERROR_SERVICE_DOES_NOT_EXIST))?;
+        f(&mut state.services[index]);
+        Ok(())
+    }
+}
+
+/// A connection to a [`FakeScm`], the counterpart of [`ServiceManager`].
+///
+/// [`ServiceManager`]: crate::service_manager::ServiceManager
+#[derive(Debug)]
+pub struct FakeServiceManager {
+    state: Arc<Mutex<ScmState>>,
+    access: ServiceManagerAccess,
+}
+
+impl ScmBackend for FakeServiceManager {
+    type Service = FakeService;
+
+    fn open_service(&self, name: &OsStr, request_access: ServiceAccess) -> Result<FakeService> {
+        let mut state = lock(&self.state);
+        let index = state
+            .find(name)
+            .ok_or_else(|| win32_error(ERROR_SERVICE_DOES_NOT_EXIST))?;
+        Ok(state.open_handle(&self.state, index, request_access))
+    }
+
+    fn create_service(
+        &self,
+        service_info: &ServiceInfo,
+        service_access: ServiceAccess,
+    ) -> Result<FakeService> {
+        if !self.access.contains(ServiceManagerAccess::CREATE_SERVICE) {
+            return Err(win32_error(ERROR_ACCESS_DENIED));
+        }
+        let raw_info = RawServiceInfo::new(service_info)?;
+        validate_service_name(&service_info.name)?;
+
+        let mut state = lock(&self.state);
+        if let Some(index) = state.find(&service_info.name) {
+            return Err(win32_error(if state.services[index].marked_for_delete {
+                ERROR_SERVICE_MARKED_FOR_DELETE
+            } else {
+                ERROR_SERVICE_EXISTS
+            }));
+        }
+        state.check_display_name(None, &service_info.name, &service_info.display_name)?;
+
+        let is_driver = service_info
+            .service_type
+            .intersects(ServiceType::KERNEL_DRIVER | ServiceType::FILE_SYSTEM_DRIVER);
+        let account_name = match service_info.account_name {
+            Some(ref account_name) => Some(account_name.clone()),
+            None if is_driver => None,
+            None => Some(OsString::from("LocalSystem")),
+        };
+
+        let id = state.next_id;
+        state.next_id += 1;
+        state.services.push(ServiceRecord {
+            id,
+            name: service_info.name.clone(),
+            config: ServiceConfig {
+                service_type: service_info.service_type,
+                start_type: service_info.start_type,
+                error_control: service_info.error_control,
+                executable_path: PathBuf::from(raw_info.launch_command.to_os_string()),
+                load_order_group: None,
+                tag_id: 0,
+                dependencies: service_info.dependencies.clone(),
+                account_name,
+                display_name: service_info.display_name.clone(),
+            },
+            failure_actions: ServiceFailureActions {
+                reset_period: ServiceFailureResetPeriod::After(Duration::default()),
+                reboot_msg: None,
+                command: None,
+                actions: None,
+            },
+            status: ServiceStatus {
+                service_type: service_info.service_type,
+                current_state: ServiceState::Stopped,
+                controls_accepted: ServiceControlAccept::empty(),
+                exit_code: ServiceExitCode::Win32(ERROR_SERVICE_NEVER_STARTED),
+                checkpoint: 0,
+                wait_hint: Duration::default(),
+                process_id: None,
+            },
+            controls_accepted: ServiceControlAccept::STOP,
+            start_arguments: Vec::new(),
+            open_handles: 0,
+            marked_for_delete: false,
+        });
+
+        let index = state.services.len() - 1;
+        Ok(state.open_handle(&self.state, index, service_access))
+    }
+
+    fn get_all_services(
+        &self,
+        list_service_type: ListServiceType,
+        service_active_state: ServiceActiveState,
+    ) -> Result<Vec<ServiceEntry>> {
+        if !self
+            .access
+            .contains(ServiceManagerAccess::ENUMERATE_SERVICE)
+        {
+            return Err(win32_error(ERROR_ACCESS_DENIED));
+        }
+        let state = lock(&self.state);
+        let mut services: Vec<ServiceEntry> = state
+            .services
+            .iter()
+            .filter(|record| record.config.service_type.bits() & list_service_type.bits() != 0)
+            .filter(|record| service_active_state.contains(record.active_state()))
+            .map(ServiceRecord::entry)
+            .collect();
+        services.sort_by_key(|entry| entry.name.to_lowercase());
+        Ok(services)
+    }
+}
+
+/// A handle to a service in a [`FakeScm`], the counterpart of [`Service`].
+///
+/// As with the system service control manager, a service marked for deletion is removed from the
+/// database once it has stopped and all handles to it have been dropped.
+///
+/// [`Service`]: crate::service::Service
+#[derive(Debug)]
+pub struct FakeService {
+    state: Arc<Mutex<ScmState>>,
+    id: u64,
+    access: ServiceAccess,
+}
+
+impl FakeService {
+    /// Run `f` on the service record after checking that the handle has the required access.
+    fn access<T>(
+        &self,
+        required_access: ServiceAccess,
+        f: impl FnOnce(&mut ScmState, usize) -> Result<T>,
+    ) -> Result<T> {
+        if !self.access.contains(required_access) {
+            return Err(win32_error(ERROR_ACCESS_DENIED));
+        }
+        let mut state = lock(&self.state);
+        let index = state
+            .index_of(self.id)
+            .expect("service record outlived by its handle");
+        f(&mut state, index)
+    }
+}
+
+impl ServiceBackend for FakeService {
+    fn start(&self, service_arguments: &[&OsStr]) -> Result<()> {
+        self.access(ServiceAccess::START, |state, index| {
+            let arguments = service_arguments.iter().map(|s| s.to_os_string()).collect();
+            state
+                .start(index, arguments, &mut Vec::new())
+                .map_err(Error::Winapi)
+        })
+    }
+
+    fn control(&self, control: ServiceControl) -> Result<ServiceStatus> {
+        let (required_access, required_accept) = match control {
+            ServiceControl::Stop => (ServiceAccess::STOP, ServiceControlAccept::STOP),
+            ServiceControl::Pause | ServiceControl::Continue => (
+                ServiceAccess::PAUSE_CONTINUE,
+                ServiceControlAccept::PAUSE_CONTINUE,
+            ),
+            ServiceControl::ParamChange => (
+                ServiceAccess::PAUSE_CONTINUE,
+                ServiceControlAccept::PARAM_CHANGE,
+            ),
+            ServiceControl::NetBindAdd
+            | ServiceControl::NetBindDisable
+            | ServiceControl::NetBindEnable
+            | ServiceControl::NetBindRemove => (
+                ServiceAccess::PAUSE_CONTINUE,
+                ServiceControlAccept::NETBIND_CHANGE,
+            ),
+            ServiceControl::Interrogate => {
+                (ServiceAccess::INTERROGATE, ServiceControlAccept::empty())
+            }
+            ServiceControl::UserEvent(_) => (
+                ServiceAccess::USER_DEFINED_CONTROL,
+                ServiceControlAccept::empty(),
+            ),
+            // The remaining controls are sent by the system only.
+            _ => return Err(win32_error(ERROR_INVALID_PARAMETER)),
+        };
+
+        self.access(required_access, |state, index| {
+            let record = &state.services[index];
+            match record.status.current_state {
+                ServiceState::Stopped => return Err(win32_error(ERROR_SERVICE_NOT_ACTIVE)),
+                ServiceState::StartPending | ServiceState::StopPending => {
+                    return Err(win32_error(ERROR_SERVICE_CANNOT_ACCEPT_CTRL))
+                }
+                _ => (),
+            }
+            if !record.status.controls_accepted.contains(required_accept) {
+                return Err(win32_error(ERROR_INVALID_SERVICE_CONTROL));
+            }
+            if control == ServiceControl::Stop && state.has_active_dependents(index) {
+                return Err(win32_error(ERROR_DEPENDENT_SERVICES_RUNNING));
+            }
+
+            let next_state = match control {
+                ServiceControl::Stop => Some((ServiceState::StopPending, ServiceState::Stopped)),
+                ServiceControl::Pause => Some((ServiceState::PausePending, ServiceState::Paused)),
+                ServiceControl::Continue => {
+                    Some((ServiceState::ContinuePending, ServiceState::Running))
+                }
+                _ => None,
+            };
+            if let Some((pending_state, final_state)) = next_state {
+                if state.instant_transitions {
+                    state.transition(index, final_state);
+                } else {
+                    state.transition(index, pending_state);
+                }
+            }
+
+            // The status returned by `ControlService` does not carry the process id.
+            let status = state.services[index].status.clone();
+            state.remove_if_deleted(index);
+            Ok(ServiceStatus {
+                process_id: None,
+                ..status
+            })
+        })
+    }
+
+    fn query_status(&self) -> Result<ServiceStatus> {
+        self.access(ServiceAccess::QUERY_STATUS, |state, index| {
+            Ok(state.services[index].status.clone())
+        })
+    }
+
+    fn query_config(&self) -> Result<ServiceConfig> {
+        self.access(ServiceAccess::QUERY_CONFIG, |state, index| {
+            Ok(state.services[index].config.clone())
+        })
+    }
+
+    fn change_config(&self, service_info: &ServiceInfo) -> Result<()> {
+        self.access(ServiceAccess::CHANGE_CONFIG, |state, index| {
+            let raw_info = RawServiceInfo::new(service_info)?;
+            let record = &state.services[index];
+            if record.marked_for_delete {
+                return Err(win32_error(ERROR_SERVICE_MARKED_FOR_DELETE));
+            }
+            state.check_display_name(Some(index), &record.name, &service_info.display_name)?;
+
+            // Mirrors `Service::change_config`, which leaves the account and the dependencies
+            // unchanged when they are not given.
+            let config = &mut state.services[index].config;
+            config.service_type = service_info.service_type;
+            config.start_type = service_info.start_type;
+            config.error_control = service_info.error_control;
+            config.executable_path = PathBuf::from(raw_info.launch_command.to_os_string());
+            config.display_name = service_info.display_name.clone();
+            if !service_info.dependencies.is_empty() {
+                config.dependencies = service_info.dependencies.clone();
+            }
+            if let Some(ref account_name) = service_info.account_name {
+                config.account_name = Some(account_name.clone());
+            }
+            Ok(())
+        })
+    }
+
+    fn set_dependencies(&self, dependencies: &[ServiceDependency]) -> Result<()> {
+        self.access(ServiceAccess::CHANGE_CONFIG, |state, index| {
+            let record = &mut state.services[index];
+            if record.marked_for_delete {
+                return Err(win32_error(ERROR_SERVICE_MARKED_FOR_DELETE));
+            }
+            record.config.dependencies = dependencies.to_vec();
+            Ok(())
+        })
+    }
+
+    fn get_failure_actions(&self) -> Result<ServiceFailureActions> {
+        self.access(ServiceAccess::QUERY_CONFIG, |state, index| {
+            Ok(state.services[index].failure_actions.clone())
+        })
+    }
+
+    fn update_failure_actions(&self, update: ServiceFailureActions) -> Result<()> {
+        // The system requires the start right to configure restart actions.
+        let restarts = update
+            .actions
+            .iter()
+            .flatten()
+            .any(|action| action.action_type == ServiceActionType::Restart);
+        let required_access = if restarts {
+            ServiceAccess::CHANGE_CONFIG | ServiceAccess::START
+        } else {
+            ServiceAccess::CHANGE_CONFIG
+        };
+
+        self.access(required_access, |state, index| {
+            let failure_actions = &mut state.services[index].failure_actions;
+            if update.reboot_msg.is_some() {
+                failure_actions.reboot_msg = update.reboot_msg.filter(|msg| !msg.is_empty());
+            }
+            if update.command.is_some() {
+                failure_actions.command = update.command.filter(|command| !command.is_empty());
+            }
+            if let Some(actions) = update.actions {
+                failure_actions.reset_period = update.reset_period;
+                failure_actions.actions = Some(actions).filter(|actions| !actions.is_empty());
+            }
+            Ok(())
+        })
+    }
+
+    fn delete(&self) -> Result<()> {
+        self.access(ServiceAccess::DELETE, |state, index| {
+            let record = &mut state.services[index];
+            if record.marked_for_delete {
+                return Err(win32_error(ERROR_SERVICE_MARKED_FOR_DELETE));
+            }
+            record.marked_for_delete = true;
+            Ok(())
+        })
+    }
+
+    fn dependent_services(&self, service_state: ServiceActiveState) -> Result<Vec<ServiceEntry>> {
+        self.access(ServiceAccess::ENUMERATE_DEPENDENTS, |state, index| {
+            let graph = DependencyGraph::new(state.services.iter().map(|record| {
+                ServiceDependencies::from_config(record.name.clone(), &record.config)
+            }));
+            let stop_order = graph
+                .stop_order_for(&state.services[index].name)
+                .map_err(|_| win32_error(ERROR_CIRCULAR_DEPENDENCY))?;
+
+            // The service itself comes last in its stop order.
+            Ok(stop_order[..stop_order.len() - 1]
+                .iter()
+                .filter_map(|name| state.find(name))
+                .map(|index| &state.services[index])
+                .filter(|record| service_state.contains(record.active_state()))
+                .map(ServiceRecord::entry)
+                .collect())
+        })
+    }
+}
+
+impl Drop for FakeService {
+    fn drop(&mut self) {
+        let mut state = lock(&self.state);
+        if let Some(index) = state.index_of(self.id) {
+            state.services[index].open_handles -= 1;
+            state.remove_if_deleted(index);
+        }
+    }
+}
+
+/// The status handle of a service in a [`FakeScm`], the counterpart of
+/// [`ServiceStatusHandle`].
+///
+/// Cloned handles share the list of reported statuses.
+///
+/// [`ServiceStatusHandle`]: crate::service_control_handler::ServiceStatusHandle
+#[derive(Debug, Clone)]
+pub struct FakeStatusHandle {
+    state: Arc<Mutex<ScmState>>,
+    id: u64,
+    reported: Arc<Mutex<Vec<ServiceStatus>>>,
+}
+
+impl FakeStatusHandle {
+    /// Returns all statuses reported through this handle, oldest first.
+    pub fn reported_statuses(&self) -> Vec<ServiceStatus> {
+        lock(&self.reported).clone()
+    }
+}
+
+impl StatusBackend for FakeStatusHandle {
+    fn set_service_status(&self, service_status: ServiceStatus) -> Result<()> {
+        let mut state = lock(&self.state);
+        let index = state
+            .index_of(self.id)
+            .ok_or_else(|| win32_error(ERROR_INVALID_HANDLE))?;
+        lock(&self.reported).push(service_status.clone());
+
+        let record = &mut state.services[index];
+        // The process keeps running through the pending states until the service stops.
+        let process_id = match service_status.current_state {
+            ServiceState::Stopped => None,
+            _ => record.status.process_id,
+        };
+        record.status = ServiceStatus {
+            process_id,
+            ..service_status
+        };
+        state.remove_if_deleted(index);
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct ScmState {
+    services: Vec<ServiceRecord>,
+    next_id: u64,
+    next_process_id: u32,
+    instant_transitions: bool,
+}
+
+#[derive(Debug)]
+struct ServiceRecord {
+    id: u64,
+    name: OsString,
+    config: ServiceConfig,
+    failure_actions: ServiceFailureActions,
+    status: ServiceStatus,
+    /// The controls accepted after an instant transition into the running state.
+    controls_accepted: ServiceControlAccept,
+    start_arguments: Vec<OsString>,
+    open_handles: usize,
+    marked_for_delete: bool,
+}
+
+impl ServiceRecord {
+    fn active_state(&self) -> ServiceActiveState {
+        if self.status.current_state == ServiceState::Stopped {
+            ServiceActiveState::INACTIVE
+        } else {
+            ServiceActiveState::ACTIVE
+        }
+    }
+
+    /// The entry returned when enumerating services, which does not carry the process id.
+    fn entry(&self) -> ServiceEntry {
+        ServiceEntry {
+            name: self.name.to_string_lossy().into_owned(),
+            display_name: self.config.display_name.to_string_lossy().into_owned(),
+            status: ServiceStatus {
+                process_id: None,
+                ..self.status.clone()
+            },
+        }
+    }
+}
+
+impl ScmState {
+    fn find(&self, name: &OsStr) -> Option<usize> {
+        self.services
+            .iter()
+            .position(|record| names_equal(&record.name, name))
+    }
+
+    fn index_of(&self, id: u64) -> Option<usize> {
+        self.services.iter().position(|record| record.id == id)
+    }
+
+    fn open_handle(
+        &mut self,
+        state: &Arc<Mutex<ScmState>>,
+        index: usize,
+        access: ServiceAccess,
+    ) -> FakeService {
+        let record = &mut self.services[index];
+        record.open_handles += 1;
+        FakeService {
+            state: state.clone(),
+            id: record.id,
+            access,
+        }
+    }
+
+    /// Display names share a namespace with service names.
+    fn check_display_name(
+        &self,
+        own_index: Option<usize>,
+        name: &OsStr,
+        display_name: &OsStr,
+    ) -> Result<()> {
+        let conflict = self
+            .services
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| Some(*index) != own_index)
+            .any(|(_, record)| {
+                names_equal(&record.name, display_name)
+                    || names_equal(&record.config.display_name, display_name)
+                    || names_equal(&record.config.display_name, name)
+            });
+        if conflict {
+            Err(win32_error(ERROR_DUPLICATE_SERVICE_NAME))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Start the service, starting its dependencies first. `starting` holds the ids of the
+    /// services whose dependencies are being started, to detect cycles.
+    fn start(
+        &mut self,
+        index: usize,
+        arguments: Vec<OsString>,
+        starting: &mut Vec<u64>,
+    ) -> io::Result<()> {
+        let record = &self.services[index];
+        if record.marked_for_delete {
+            return Err(io::Error::from_raw_os_error(
+                ERROR_SERVICE_MARKED_FOR_DELETE,
+            ));
+        }
+        if record.config.start_type == ServiceStartType::Disabled {
+            return Err(io::Error::from_raw_os_error(ERROR_SERVICE_DISABLED));
+        }
+        if record.status.current_state != ServiceState::Stopped {
+            return Err(io::Error::from_raw_os_error(ERROR_SERVICE_ALREADY_RUNNING));
+        }
+        if starting.contains(&record.id) {
+            return Err(io::Error::from_raw_os_error(ERROR_CIRCULAR_DEPENDENCY));
+        }
+
+        starting.push(record.id);
+        for dependency in record.config.dependencies.clone() {
+            self.start_dependency(&dependency, starting)?;
+        }
+        starting.pop();
+
+        let process_id = self.next_process_id;
+        self.next_process_id += 1;
+        let instant_transitions = self.instant_transitions;
+
+        let record = &mut self.services[index];
+        record.start_arguments = arguments;
+        record.status.exit_code = ServiceExitCode::NO_ERROR;
+        record.status.process_id = Some(process_id);
+        if instant_transitions {
+            self.transition(index, ServiceState::Running);
+        } else {
+            self.transition(index, ServiceState::StartPending);
+        }
+        Ok(())
+    }
+
+    fn start_dependency(
+        &mut self,
+        dependency: &ServiceDependency,
+        starting: &mut Vec<u64>,
+    ) -> io::Result<()> {
+        let members: Vec<usize> = match dependency {
+            ServiceDependency::Service(name) => {
+                let index = self
+                    .find(name)
+                    .filter(|&index| !self.services[index].marked_for_delete)
+                    .ok_or_else(|| {
+                        io::Error::from_raw_os_error(ERROR_SERVICE_DEPENDENCY_DELETED)
+                    })?;
+                vec![index]
+            }
+            // At least one member of a group must be running after attempting to start all of
+            // them.
+            ServiceDependency::Group(group) => (0..self.services.len())
+                .filter(|&index| {
+                    self.services[index]
+                        .config
+                        .load_order_group
+                        .as_ref()
+                        .is_some_and(|member_group| names_equal(member_group, group))
+                })
+                .collect(),
+        };
+
+        let mut any_running = false;
+        for index in members {
+            if self.services[index].status.current_state == ServiceState::Stopped {
+                match self.start(index, Vec::new(), starting) {
+                    Err(e) if e.raw_os_error() == Some(ERROR_CIRCULAR_DEPENDENCY) => return Err(e),
+                    _ => (),
+                }
+            }
+            any_running |= self.services[index].status.current_state != ServiceState::Stopped;
+        }
+
+        if any_running {
+            Ok(())
+        } else {
+            Err(io::Error::from_raw_os_error(ERROR_SERVICE_DEPENDENCY_FAIL))
+        }
+    }
+
+    fn transition(&mut self, index: usize, next_state: ServiceState) {
+        let record = &mut self.services[index];
+        let (controls_accepted, process_id) = match next_state {
+            ServiceState::Stopped => (ServiceControlAccept::empty(), None),
+            ServiceState::StartPending | ServiceState::StopPending => {
+                (ServiceControlAccept::empty(), record.status.process_id)
+            }
+            _ => (record.controls_accepted, record.status.process_id),
+        };
+        record.status.current_state = next_state;
+        record.status.controls_accepted = controls_accepted;
+        record.status.process_id = process_id;
+        record.status.checkpoint = 0;
+        record.status.wait_hint = Duration::default();
+    }
+
+    fn has_active_dependents(&self, index: usize) -> bool {
+        let record = &self.services[index];
+        self.services.iter().any(|other| {
+            other.status.current_state != ServiceState::Stopped
+                && other
+                    .config
+                    .dependencies
+                    .iter()
+                    .any(|dependency| match dependency {
+                        ServiceDependency::Service(name) => names_equal(name, &record.name),
+                        ServiceDependency::Group(group) => record
+                            .config
+                            .load_order_group
+                            .as_ref()
+                            .is_some_and(|own_group| names_equal(own_group, group)),
+                    })
+        })
+    }
+
+    fn remove_if_deleted(&mut self, index: usize) {
+        let record = &self.services[index];
+        if record.marked_for_delete
+            && record.open_handles == 0
+            && record.status.current_state == ServiceState::Stopped
+        {
+            self.services.remove(index);
+        }
+    }
+}
+
+/// The config of an own process service started on demand, for tests.
+#[cfg(test)]
+pub(crate) fn service_info(name: &str, dependencies: Vec<ServiceDependency>) -> ServiceInfo {
+    ServiceInfo {
+        name: OsString::from(name),
+        display_name: OsString::from(format!("{} display name", name)),
+        service_type: ServiceType::OWN_PROCESS,
+        start_type: ServiceStartType::OnDemand,
+        error_control: crate::service::ServiceErrorControl::Normal,
+        executable_path: PathBuf::from(format!("C:\\{}.exe", name)),
+        launch_arguments: vec![],
+        dependencies,
+        account_name: None,
+        account_password: None,
+    }
+}
+
+/// The status handle of a service named `svc` in a new database, for tests.
+#[cfg(test)]
+pub(crate) fn status_handle() -> FakeStatusHandle {
+    let scm = FakeScm::new();
+    scm.connect(ServiceManagerAccess::CREATE_SERVICE)
+        .create_service(&service_info("svc", vec![]), ServiceAccess::QUERY_STATUS)
+        .unwrap();
+    scm.status_handle("svc").unwrap()
+}
+
+fn win32_error(code: i32) -> Error {
+    Error::Winapi(io::Error::from_raw_os_error(code))
+}
+
+/// Service names are case insensitive.
+fn names_equal(a: &OsStr, b: &OsStr) -> bool {
+    a.to_string_lossy().to_lowercase() == b.to_string_lossy().to_lowercase()
+}
+
+fn validate_service_name(name: &OsStr) -> Result<()> {
+    let name = name.to_string_lossy();
+    if name.is_empty() || name.chars().count() > MAX_SERVICE_NAME_LEN || name.contains(['/', '\\'])
+    {
+        Err(win32_error(ERROR_INVALID_NAME))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::ServiceAction;
+
+    fn error_code<T: std::fmt::Debug>(result: Result<T>) -> i32 {
+        match result {
+            Err(Error::Winapi(e)) => e.raw_os_error().expect("not an os error"),
+            other => panic!("expected a winapi error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_create_and_query_config() {
+        let scm = FakeScm::new();
+        let manager = scm.connect(ServiceManagerAccess::CREATE_SERVICE);
+        let mut info = service_info("my_service", vec![]);
+        info.launch_arguments = vec![OsString::from("--flag"), OsString::from("a b")];
+        let service = manager
+            .create_service(
+                &info,
+                ServiceAccess::QUERY_CONFIG | ServiceAccess::QUERY_STATUS,
+            )
+            .unwrap();
+
+        let config = service.query_config().unwrap();
+        assert_eq!(
+            config.executable_path,
+            PathBuf::from("C:\\my_service.exe --flag \"a b\"")
+        );
+        assert_eq!(config.account_name, Some(OsString::from("LocalSystem")));
+        assert_eq!(config.display_name, info.display_name);
+
+        let status = service.query_status().unwrap();
+        assert_eq!(status.current_state, ServiceState::Stopped);
+        assert_eq!(
+            status.exit_code,
+            ServiceExitCode::Win32(ERROR_SERVICE_NEVER_STARTED)
+        );
+    }
+
+    #[test]
+    fn test_create_errors() {
+        let scm = FakeScm::new();
+        let manager = scm.connect(ServiceManagerAccess::CREATE_SERVICE);
+        manager
+            .create_service(&service_info("a", vec![]), ServiceAccess::empty())
+            .unwrap();
+
+        assert_eq!(
+            error_code(manager.create_service(&service_info("A", vec![]), ServiceAccess::empty())),
+            ERROR_SERVICE_EXISTS
+        );
+        let mut info = service_info("b", vec![]);
+        info.display_name = OsString::from("a display name");
+        assert_eq!(
+            error_code(manager.create_service(&info, ServiceAccess::empty())),
+            ERROR_DUPLICATE_SERVICE_NAME
+        );
+        assert_eq!(
+            error_code(
+                manager.create_service(&service_info("c/d", vec![]), ServiceAccess::empty())
+            ),
+            ERROR_INVALID_NAME
+        );
+        assert_eq!(
+            error_code(
+                scm.connect(ServiceManagerAccess::CONNECT)
+                    .create_service(&service_info("e", vec![]), ServiceAccess::empty())
+            ),
+            ERROR_ACCESS_DENIED
+        );
+        assert_eq!(
+            error_code(
+                scm.connect(ServiceManagerAccess::CONNECT)
+                    .open_service(OsStr::new("f"), ServiceAccess::empty())
+            ),
+            ERROR_SERVICE_DOES_NOT_EXIST
+        );
+    }
+
+    #[test]
+    fn test_start_stop_transitions() {
+        let scm = FakeScm::new();
+        let manager = scm.connect(ServiceManagerAccess::CREATE_SERVICE);
+        let service = manager
+            .create_service(&service_info("svc", vec![]), ServiceAccess::ALL_ACCESS)
+            .unwrap();
+
+        assert_eq!(
+            error_code(service.control(ServiceControl::Stop)),
+            ERROR_SERVICE_NOT_ACTIVE
+        );
+        service.start(&[OsStr::new("arg")]).unwrap();
+        assert_eq!(
+            scm.start_arguments("svc"),
+            Some(vec![OsString::from("arg")])
+        );
+
+        let status = service.query_status().unwrap();
+        assert_eq!(status.current_state, ServiceState::Running);
+        assert_eq!(status.controls_accepted, ServiceControlAccept::STOP);
+        assert_eq!(status.process_id, Some(FIRST_PROCESS_ID));
+        assert_eq!(
+            error_code(service.start(&[])),
+            ERROR_SERVICE_ALREADY_RUNNING
+        );
+        assert_eq!(
+            error_code(service.control(ServiceControl::Pause)),
+            ERROR_INVALID_SERVICE_CONTROL
+        );
+
+        let status = service.control(ServiceControl::Stop).unwrap();
+        assert_eq!(status.current_state, ServiceState::Stopped);
+        assert_eq!(status.process_id, None);
+    }
+
+    #[test]
+    fn test_pending_transitions_reported_by_status_handle() {
+        let scm = FakeScm::new();
+        scm.set_instant_transitions(false);
+        let manager = scm.connect(ServiceManagerAccess::CREATE_SERVICE);
+        let service = manager
+            .create_service(&service_info("svc", vec![]), ServiceAccess::ALL_ACCESS)
+            .unwrap();
+        let status_handle = scm.status_handle("svc").unwrap();
+
+        service.start(&[]).unwrap();
+        assert_eq!(
+            service.query_status().unwrap().current_state,
+            ServiceState::StartPending
+        );
+        assert_eq!(
+            error_code(service.control(ServiceControl::Stop)),
+            ERROR_SERVICE_CANNOT_ACCEPT_CTRL
+        );
+
+        let start_pending = ServiceStatus {
+            service_type: ServiceType::OWN_PROCESS,
+            current_state: ServiceState::StartPending,
+            controls_accepted: ServiceControlAccept::empty(),
+            exit_code: ServiceExitCode::NO_ERROR,
+            checkpoint: 1,
+            wait_hint: Duration::from_secs(5),
+            process_id: None,
+        };
+        status_handle
+            .set_service_status(start_pending.clone())
+            .unwrap();
+        assert_eq!(
+            service.query_status().unwrap().process_id,
+            Some(FIRST_PROCESS_ID)
+        );
+
+        let running = ServiceStatus {
+            service_type: ServiceType::OWN_PROCESS,
+            current_state: ServiceState::Running,
+            controls_accepted: ServiceControlAccept::STOP | ServiceControlAccept::PAUSE_CONTINUE,
+            exit_code: ServiceExitCode::NO_ERROR,
+            checkpoint: 0,
+            wait_hint: Duration::default(),
+            process_id: None,
+        };
+        status_handle.set_service_status(running.clone()).unwrap();
+        assert_eq!(
+            service.query_status().unwrap().process_id,
+            Some(FIRST_PROCESS_ID)
+        );
+
+        let status = service.control(ServiceControl::Pause).unwrap();
+        assert_eq!(status.current_state, ServiceState::PausePending);
+        assert_eq!(
+            status_handle.reported_statuses(),
+            vec![start_pending, running]
+        );
+    }
+
+    #[test]
+    fn test_start_dependencies() {
+        let scm = FakeScm::new();
+        let manager = scm.connect(ServiceManagerAccess::CREATE_SERVICE);
+        let access = ServiceAccess::START | ServiceAccess::STOP | ServiceAccess::QUERY_STATUS;
+        let base = manager
+            .create_service(&service_info("base", vec![]), access)
+            .unwrap();
+        manager
+            .create_service(&service_info("grouped", vec![]), access)
+            .unwrap();
+        scm.set_load_order_group("grouped", Some(OsString::from("Group")))
+            .unwrap();
+        let top = manager
+            .create_service(
+                &service_info(
+                    "top",
+                    vec![
+                        ServiceDependency::Service(OsString::from("BASE")),
+                        ServiceDependency::Group(OsString::from("group")),
+                    ],
+                ),
+                access,
+            )
+            .unwrap();
+
+        top.start(&[]).unwrap();
+        assert_eq!(
+            base.query_status().unwrap().current_state,
+            ServiceState::Running
+        );
+        assert_eq!(scm.start_arguments("grouped"), Some(vec![]));
+        assert_eq!(
+            error_code(base.control(ServiceControl::Stop)),
+            ERROR_DEPENDENT_SERVICES_RUNNING
+        );
+        top.control(ServiceControl::Stop).unwrap();
+        base.control(ServiceControl::Stop).unwrap();
+    }
+
+    #[test]
+    fn test_dependency_errors() {
+        let scm = FakeScm::new();
+        let manager = scm.connect(ServiceManagerAccess::CREATE_SERVICE);
+        let missing = manager
+            .create_service(
+                &service_info("missing", vec![ServiceDependency::Service("nope".into())]),
+                ServiceAccess::START,
+            )
+            .unwrap();
+        assert_eq!(
+            error_code(missing.start(&[])),
+            ERROR_SERVICE_DEPENDENCY_DELETED
+        );
+
+        let mut disabled = service_info("disabled", vec![]);
+        disabled.start_type = ServiceStartType::Disabled;
+        manager
+            .create_service(&disabled, ServiceAccess::empty())
+            .unwrap();
+        let failing = manager
+            .create_service(
+                &service_info(
+                    "failing",
+                    vec![ServiceDependency::Service("disabled".into())],
+                ),
+                ServiceAccess::START,
+            )
+            .unwrap();
+        assert_eq!(
+            error_code(failing.start(&[])),
+            ERROR_SERVICE_DEPENDENCY_FAIL
+        );
+
+        let a = manager
+            .create_service(
+                &service_info("a", vec![ServiceDependency::Service("b".into())]),
+                ServiceAccess::START,
+            )
+            .unwrap();
+        manager
+            .create_service(
+                &service_info("b", vec![ServiceDependency::Service("a".into())]),
+                ServiceAccess::empty(),
+            )
+            .unwrap();
+        assert_eq!(error_code(a.start(&[])), ERROR_CIRCULAR_DEPENDENCY);
+    }
+
+    #[test]
+    fn test_access_rights() {
+        let scm = FakeScm::new();
+        let manager = scm.connect(ServiceManagerAccess::CREATE_SERVICE);
+        let service = manager
+            .create_service(&service_info("svc", vec![]), ServiceAccess::START)
+            .unwrap();
+        service.start(&[]).unwrap();
+
+        assert_eq!(error_code(service.query_status()), ERROR_ACCESS_DENIED);
+        assert_eq!(
+            error_code(service.control(ServiceControl::Stop)),
+            ERROR_ACCESS_DENIED
+        );
+        assert_eq!(error_code(service.delete()), ERROR_ACCESS_DENIED);
+        assert_eq!(
+            error_code(manager.get_all_services(ListServiceType::WIN32, ServiceActiveState::ALL)),
+            ERROR_ACCESS_DENIED
+        );
+    }
+
+    #[test]
+    fn test_delete_waits_for_handles_and_stop() {
+        let scm = FakeScm::new();
+        let manager = scm.connect(ServiceManagerAccess::CREATE_SERVICE);
+        let service = manager
+            .create_service(&service_info("svc", vec![]), ServiceAccess::ALL_ACCESS)
+            .unwrap();
+        service.start(&[]).unwrap();
+        let other = manager
+            .open_service(OsStr::new("svc"), ServiceAccess::empty())
+            .unwrap();
+
+        service.delete().unwrap();
+        assert_eq!(
+            error_code(service.delete()),
+            ERROR_SERVICE_MARKED_FOR_DELETE
+        );
+        assert_eq!(
+            error_code(
+                manager.create_service(&service_info("svc", vec![]), ServiceAccess::empty())
+            ),
+            ERROR_SERVICE_MARKED_FOR_DELETE
+        );
+
+        service.control(ServiceControl::Stop).unwrap();
+        drop(service);
+        assert!(scm.contains("svc"));
+        drop(other);
+        assert!(!scm.contains("svc"));
+    }
+
+    #[test]
+    fn test_get_all_services() {
+        let scm = FakeScm::new();
+        let manager = scm.connect(ServiceManagerAccess::ALL_ACCESS);
+        manager
+            .create_service(&service_info("b", vec![]), ServiceAccess::START)
+            .unwrap()
+            .start(&[])
+            .unwrap();
+        manager
+            .create_service(&service_info("a", vec![]), ServiceAccess::empty())
+            .unwrap();
+
+        let names = |state| {
+            manager
+                .get_all_services(ListServiceType::WIN32, state)
+                .unwrap()
+                .into_iter()
+                .map(|entry| entry.name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(ServiceActiveState::ALL), vec!["a", "b"]);
+        assert_eq!(names(ServiceActiveState::ACTIVE), vec!["b"]);
+        assert_eq!(names(ServiceActiveState::INACTIVE), vec!["a"]);
+    }
+
+    #[test]
+    fn test_dependent_services() {
+        let scm = FakeScm::new();
+        let manager = scm.connect(ServiceManagerAccess::CREATE_SERVICE);
+        let dependency = |name: &str| ServiceDependency::Service(name.into());
+        let services = [
+            ("base", vec![]),
+            ("middle", vec![dependency("base")]),
+            ("top", vec![dependency("middle")]),
+            ("other", vec![dependency("base")]),
+        ];
+        for (name, dependencies) in services {
+            manager
+                .create_service(&service_info(name, dependencies), ServiceAccess::START)
+                .unwrap();
+        }
+        manager
+            .open_service(OsStr::new("top"), ServiceAccess::START)
+            .unwrap()
+            .start(&[])
+            .unwrap();
+
+        let base = manager
+            .open_service(OsStr::new("base"), ServiceAccess::ENUMERATE_DEPENDENTS)
+            .unwrap();
+        let names = |state| {
+            base.dependent_services(state)
+                .unwrap()
+                .into_iter()
+                .map(|entry| entry.name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names(ServiceActiveState::ALL),
+            vec!["top", "middle", "other"]
+        );
+        assert_eq!(names(ServiceActiveState::ACTIVE), vec!["top", "middle"]);
+        assert_eq!(names(ServiceActiveState::INACTIVE), vec!["other"]);
+
+        let top = manager
+            .open_service(OsStr::new("top"), ServiceAccess::QUERY_STATUS)
+            .unwrap();
+        assert_eq!(
+            error_code(top.dependent_services(ServiceActiveState::ALL)),
+            ERROR_ACCESS_DENIED
+        );
+    }
+
+    #[test]
+    fn test_update_failure_actions() {
+        let scm = FakeScm::new();
+        let manager = scm.connect(ServiceManagerAccess::CREATE_SERVICE);
+        let service = manager
+            .create_service(
+                &service_info("svc", vec![]),
+                ServiceAccess::CHANGE_CONFIG | ServiceAccess::QUERY_CONFIG,
+            )
+            .unwrap();
+        let update = ServiceFailureActions {
+            reset_period: ServiceFailureResetPeriod::Never,
+            reboot_msg: None,
+            command: Some(OsString::from("cleanup.exe")),
+            actions: Some(vec![ServiceAction {
+                action_type: ServiceActionType::Restart,
+                delay: Duration::from_secs(5),
+            }]),
+        };
+        assert_eq!(
+            error_code(service.update_failure_actions(update.clone())),
+            ERROR_ACCESS_DENIED
+        );
+
+        let update = ServiceFailureActions {
+            actions: Some(vec![ServiceAction {
+                action_type: ServiceActionType::RunCommand,
+                delay: Duration::from_secs(5),
+            }]),
+            ..update
+        };
+        service.update_failure_actions(update.clone()).unwrap();
+        assert_eq!(service.get_failure_actions().unwrap(), update);
+    }
+}
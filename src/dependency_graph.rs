@@ -0,0 +1,677 @@
+//! Dependency graph of services.
+//!
+//! [`ServiceConfig::dependencies`] lists what a single service depends on. [`DependencyGraph`]
+//! puts the dependencies of many services together, so that the graph can be walked in both
+//! directions: the order in which services have to be started, the order in which they have to
+//! be stopped, and the dependency cycles that prevent a service from starting.
+//!
+//! A service can depend on another service or on a load ordering group. The system starts every
+//! member of a group before the services that depend on the group, so in the graph a group
+//! depends on all of its members.
+//!
+//! The graph is built from plain [`ServiceDependencies`] records and does not talk to the system
+//! itself. [`DependencyGraph::query`] builds it from the services registered with a service
+//! control manager.
+//!
+//! # Example
+//!
+//! ```rust
+//! use windows_service::dependency_graph::{DependencyGraph, ServiceDependencies};
+//! use windows_service::service::ServiceDependency;
+//!
+//! # fn main() -> windows_service::Result<()> {
+//! let graph = DependencyGraph::new(vec![
+//!     ServiceDependencies {
+//!         name: "web".into(),
+//!         load_order_group: None,
+//!         dependencies: vec![ServiceDependency::Service("database".into())],
+//!     },
+//!     ServiceDependencies {
+//!         name: "database".into(),
+//!         load_order_group: None,
+//!         dependencies: vec![],
+//!     },
+//! ]);
+//! assert_eq!(graph.start_order()?, ["database", "web"]);
+//! assert_eq!(graph.stop_order()?, ["web", "database"]);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+
+use crate::backend::{ScmBackend, ServiceBackend};
+use crate::service::{ServiceAccess, ServiceConfig, ServiceDependency};
+use crate::service_manager::{ListServiceType, ServiceActiveState};
+use crate::{Error, Result};
+
+/// The error returned by the system when a service does not exist.
+const ERROR_SERVICE_DOES_NOT_EXIST: i32 = 1060;
+
+/// The dependencies of a single service.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ServiceDependencies {
+    /// The service name.
+    pub name: OsString,
+
+    /// The load ordering group the service belongs to.
+    pub load_order_group: Option<OsString>,
+
+    /// The services and groups the service depends on.
+    pub dependencies: Vec<ServiceDependency>,
+}
+
+impl ServiceDependencies {
+    /// Take the dependencies of the service `name` from its config.
+    pub fn from_config(name: impl Into<OsString>, config: &ServiceConfig) -> Self {
+        ServiceDependencies {
+            name: name.into(),
+            load_order_group: config.load_order_group.clone(),
+            dependencies: config.dependencies.clone(),
+        }
+    }
+}
+
+/// A set of services that depend on each other in a cycle.
+///
+/// Returned in [`Error::DependencyCycle`] when a start or stop order cannot be produced.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DependencyCycle {
+    path: Vec<ServiceDependency>,
+}
+
+impl DependencyCycle {
+    /// The services and groups on the cycle, each depending on the next one. The last element
+    /// depends on the first one.
+    pub fn path(&self) -> &[ServiceDependency] {
+        &self.path
+    }
+}
+
+impl std::error::Error for DependencyCycle {}
+
+impl fmt::Display for DependencyCycle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for node in &self.path {
+            write_node(f, node)?;
+            write!(f, " -> ")?;
+        }
+        write_node(f, &self.path[0])
+    }
+}
+
+fn write_node(f: &mut fmt::Formatter<'_>, node: &ServiceDependency) -> fmt::Result {
+    match node {
+        ServiceDependency::Service(name) => write!(f, "{}", name.to_string_lossy()),
+        ServiceDependency::Group(name) => write!(f, "group {}", name.to_string_lossy()),
+    }
+}
+
+/// A dependency graph of services and load ordering groups.
+///
+/// Service and group names are compared case insensitively, like the system does.
+#[derive(Debug, Clone)]
+pub struct DependencyGraph {
+    nodes: Vec<Node>,
+    index: HashMap<NodeKey, usize>,
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    id: ServiceDependency,
+    /// Whether the node is a service in the graph or a group with members in the graph, as
+    /// opposed to a dependency that cannot be resolved.
+    exists: bool,
+    /// For a service the nodes it depends on, for a group its members.
+    dependencies: Vec<usize>,
+    /// The nodes that depend on this node.
+    dependents: Vec<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum NodeKey {
+    Service(String),
+    Group(String),
+}
+
+impl NodeKey {
+    fn new(id: &ServiceDependency) -> Self {
+        match id {
+            ServiceDependency::Service(name) => NodeKey::Service(fold_case(name)),
+            ServiceDependency::Group(name) => NodeKey::Group(fold_case(name)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    Dependencies,
+    Dependents,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mark {
+    New,
+    InProgress,
+    Done,
+}
+
+impl DependencyGraph {
+    /// Build the graph from the dependencies of a set of services.
+    ///
+    /// A service listed more than once keeps its last record. Dependencies on services that are
+    /// not listed are kept in the graph, see [`DependencyGraph::missing_dependencies`].
+    pub fn new(services: impl IntoIterator<Item = ServiceDependencies>) -> Self {
+        let mut graph = DependencyGraph {
+            nodes: Vec::new(),
+            index: HashMap::new(),
+        };
+
+        let mut records: Vec<ServiceDependencies> = Vec::new();
+        for service in services {
+            let index = graph.node(ServiceDependency::Service(service.name.clone()));
+            graph.nodes[index].exists = true;
+            match records
+                .iter_mut()
+                .find(|record| names_equal(&record.name, &service.name))
+            {
+                Some(record) => *record = service,
+                None => records.push(service),
+            }
+        }
+
+        for record in &records {
+            let service = graph.index[&NodeKey::Service(fold_case(&record.name))];
+            if let Some(ref group) = record.load_order_group {
+                let group = graph.node(ServiceDependency::Group(group.clone()));
+                graph.nodes[group].exists = true;
+                graph.add_edge(group, service);
+            }
+            for dependency in &record.dependencies {
+                let dependency = graph.node(dependency.clone());
+                graph.add_edge(service, dependency);
+            }
+        }
+        graph
+    }
+
+    /// Build the graph from all services registered with the service control manager.
+    ///
+    /// The manager must have the [`ServiceManagerAccess::ENUMERATE_SERVICE`] access right, and
+    /// the services are opened with [`ServiceAccess::QUERY_CONFIG`].
+    ///
+    /// [`ServiceManagerAccess::ENUMERATE_SERVICE`]: crate::service_manager::ServiceManagerAccess::ENUMERATE_SERVICE
+    pub fn query(manager: &impl ScmBackend) -> Result<Self> {
+        let entries = manager.get_all_services(
+            ListServiceType::WIN32 | ListServiceType::DRIVER,
+            ServiceActiveState::ALL,
+        )?;
+
+        let mut services = Vec::with_capacity(entries.len());
+        for entry in entries {
+            let config = manager
+                .open_service(OsStr::new(&entry.name), ServiceAccess::QUERY_CONFIG)
+                .and_then(|service| service.query_config());
+            match config {
+                Ok(config) => services.push(ServiceDependencies::from_config(entry.name, &config)),
+                // The service was deleted since it was enumerated.
+                Err(Error::Winapi(ref e))
+                    if e.raw_os_error() == Some(ERROR_SERVICE_DOES_NOT_EXIST) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(DependencyGraph::new(services))
+    }
+
+    /// The services in the graph, in the order they were added.
+    pub fn services(&self) -> impl Iterator<Item = &OsStr> {
+        self.nodes
+            .iter()
+            .filter(|node| node.exists)
+            .filter_map(|node| match node.id {
+                ServiceDependency::Service(ref name) => Some(name.as_os_str()),
+                ServiceDependency::Group(_) => None,
+            })
+    }
+
+    /// Returns `true` if the graph contains the service.
+    pub fn contains(&self, name: impl AsRef<OsStr>) -> bool {
+        self.service_index(name.as_ref()).is_some()
+    }
+
+    /// The services that depend on the service directly or through its load ordering group.
+    pub fn dependents(&self, name: impl AsRef<OsStr>) -> Vec<&OsStr> {
+        let Some(index) = self.service_index(name.as_ref()) else {
+            return Vec::new();
+        };
+        let mut dependents = Vec::new();
+        for &dependent in &self.nodes[index].dependents {
+            match self.nodes[dependent].id {
+                ServiceDependency::Service(_) => dependents.push(dependent),
+                ServiceDependency::Group(_) => {
+                    dependents.extend_from_slice(&self.nodes[dependent].dependents)
+                }
+            }
+        }
+        dependents.sort_unstable();
+        dependents.dedup();
+        self.service_names(dependents)
+    }
+
+    /// The dependencies that refer to services that are not in the graph or to groups without
+    /// members, together with the services declaring them.
+    ///
+    /// The system fails to start a service with a missing dependency.
+    pub fn missing_dependencies(&self) -> Vec<(&OsStr, &ServiceDependency)> {
+        let mut missing = Vec::new();
+        for node in self.nodes.iter().filter(|node| !node.exists) {
+            for &dependent in &node.dependents {
+                if let ServiceDependency::Service(ref name) = self.nodes[dependent].id {
+                    missing.push((name.as_os_str(), &node.id));
+                }
+            }
+        }
+        missing
+    }
+
+    /// Find the sets of services and groups that depend on each other in a cycle.
+    ///
+    /// Each set is listed in the order its members were added to the graph. A service that
+    /// depends on itself forms a set of its own.
+    pub fn cycles(&self) -> Vec<Vec<&ServiceDependency>> {
+        let mut components = StronglyConnectedComponents::new(self);
+        for index in 0..self.nodes.len() {
+            components.visit(index);
+        }
+
+        let mut cycles: Vec<Vec<usize>> = components
+            .components
+            .into_iter()
+            .filter(|component| {
+                component.len() > 1
+                    || self.nodes[component[0]]
+                        .dependencies
+                        .contains(&component[0])
+            })
+            .collect();
+        for cycle in &mut cycles {
+            cycle.sort_unstable();
+        }
+        cycles.sort_unstable();
+        cycles
+            .into_iter()
+            .map(|cycle| {
+                cycle
+                    .into_iter()
+                    .map(|index| &self.nodes[index].id)
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// The order in which all services in the graph can be started, each after its dependencies.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::DependencyCycle`] if services depend on each other in a cycle.
+    pub fn start_order(&self) -> Result<Vec<&OsStr>> {
+        self.order(0..self.nodes.len(), Direction::Dependencies)
+    }
+
+    /// The order in which all services in the graph can be stopped, each after its dependents.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::DependencyCycle`] if services depend on each other in a cycle.
+    pub fn stop_order(&self) -> Result<Vec<&OsStr>> {
+        self.order(0..self.nodes.len(), Direction::Dependents)
+    }
+
+    /// The service and everything it depends on, directly or indirectly, in the order they have
+    /// to be started. The service itself comes last. Returns an empty list if the graph does not
+    /// contain the service.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::DependencyCycle`] if the dependencies of the service form a cycle.
+    pub fn start_order_for(&self, name: impl AsRef<OsStr>) -> Result<Vec<&OsStr>> {
+        self.order(self.service_index(name.as_ref()), Direction::Dependencies)
+    }
+
+    /// The service and everything that depends on it, directly or indirectly, in the order they
+    /// have to be stopped. The service itself comes last. Returns an empty list if the graph does
+    /// not contain the service.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::DependencyCycle`] if the dependents of the service form a cycle.
+    pub fn stop_order_for(&self, name: impl AsRef<OsStr>) -> Result<Vec<&OsStr>> {
+        self.order(self.service_index(name.as_ref()), Direction::Dependents)
+    }
+
+    /// Get the node with the given id, adding it if needed.
+    fn node(&mut self, id: ServiceDependency) -> usize {
+        let key = NodeKey::new(&id);
+        if let Some(&index) = self.index.get(&key) {
+            return index;
+        }
+        self.nodes.push(Node {
+            id,
+            exists: false,
+            dependencies: Vec::new(),
+            dependents: Vec::new(),
+        });
+        self.index.insert(key, self.nodes.len() - 1);
+        self.nodes.len() - 1
+    }
+
+    fn add_edge(&mut self, dependent: usize, dependency: usize) {
+        if !self.nodes[dependent].dependencies.contains(&dependency) {
+            self.nodes[dependent].dependencies.push(dependency);
+            self.nodes[dependency].dependents.push(dependent);
+        }
+    }
+
+    fn service_index(&self, name: &OsStr) -> Option<usize> {
+        self.index
+            .get(&NodeKey::Service(fold_case(name)))
+            .copied()
+            .filter(|&index| self.nodes[index].exists)
+    }
+
+    fn service_names(&self, indices: impl IntoIterator<Item = usize>) -> Vec<&OsStr> {
+        indices
+            .into_iter()
+            .filter(|&index| self.nodes[index].exists)
+            .filter_map(|index| match self.nodes[index].id {
+                ServiceDependency::Service(ref name) => Some(name.as_os_str()),
+                ServiceDependency::Group(_) => None,
+            })
+            .collect()
+    }
+
+    fn edges(&self, index: usize, direction: Direction) -> &[usize] {
+        match direction {
+            Direction::Dependencies => &self.nodes[index].dependencies,
+            Direction::Dependents => &self.nodes[index].dependents,
+        }
+    }
+
+    /// Walk the graph from the `roots` in the given direction, and list the services so that
+    /// each comes after the nodes it leads to.
+    fn order(
+        &self,
+        roots: impl IntoIterator<Item = usize>,
+        direction: Direction,
+    ) -> Result<Vec<&OsStr>> {
+        let mut marks = vec![Mark::New; self.nodes.len()];
+        let mut path = Vec::new();
+        let mut order = Vec::new();
+        for root in roots {
+            self.visit(root, direction, &mut marks, &mut path, &mut order)?;
+        }
+        Ok(self.service_names(order))
+    }
+
+    fn visit(
+        &self,
+        index: usize,
+        direction: Direction,
+        marks: &mut [Mark],
+        path: &mut Vec<usize>,
+        order: &mut Vec<usize>,
+    ) -> Result<()> {
+        match marks[index] {
+            Mark::Done => return Ok(()),
+            Mark::InProgress => {
+                let start = path
+                    .iter()
+                    .position(|&node| node == index)
+                    .expect("node in progress is on the path");
+                let mut cycle: Vec<ServiceDependency> = path[start..]
+                    .iter()
+                    .map(|&node| self.nodes[node].id.clone())
+                    .collect();
+                if direction == Direction::Dependents {
+                    cycle.reverse();
+                }
+                return Err(Error::DependencyCycle(DependencyCycle { path: cycle }));
+            }
+            Mark::New => (),
+        }
+
+        marks[index] = Mark::InProgress;
+        path.push(index);
+        for &next in self.edges(index, direction) {
+            self.visit(next, direction, marks, path, order)?;
+        }
+        path.pop();
+        marks[index] = Mark::Done;
+        order.push(index);
+        Ok(())
+    }
+}
+
+/// Tarjan's algorithm for finding strongly connected components.
+struct StronglyConnectedComponents<'a> {
+    graph: &'a DependencyGraph,
+    next_index: usize,
+    indices: Vec<Option<usize>>,
+    low_links: Vec<usize>,
+    stack: Vec<usize>,
+    on_stack: Vec<bool>,
+    components: Vec<Vec<usize>>,
+}
+
+impl<'a> StronglyConnectedComponents<'a> {
+    fn new(graph: &'a DependencyGraph) -> Self {
+        let len = graph.nodes.len();
+        StronglyConnectedComponents {
+            graph,
+            next_index: 0,
+            indices: vec![None; len],
+            low_links: vec![0; len],
+            stack: Vec::new(),
+            on_stack: vec![false; len],
+            components: Vec::new(),
+        }
+    }
+
+    fn visit(&mut self, node: usize) {
+        if self.indices[node].is_some() {
+            return;
+        }
+        self.indices[node] = Some(self.next_index);
+        self.low_links[node] = self.next_index;
+        self.next_index += 1;
+        self.stack.push(node);
+        self.on_stack[node] = true;
+
+        for &next in &self.graph.nodes[node].dependencies {
+            match self.indices[next] {
+                None => {
+                    self.visit(next);
+                    self.low_links[node] = self.low_links[node].min(self.low_links[next]);
+                }
+                Some(next_index) if self.on_stack[next] => {
+                    self.low_links[node] = self.low_links[node].min(next_index);
+                }
+                Some(_) => (),
+            }
+        }
+
+        if Some(self.low_links[node]) == self.indices[node] {
+            let mut component = Vec::new();
+            while let Some(member) = self.stack.pop() {
+                self.on_stack[member] = false;
+                component.push(member);
+                if member == node {
+                    break;
+                }
+            }
+            self.components.push(component);
+        }
+    }
+}
+
+fn fold_case(name: &OsStr) -> String {
+    name.to_string_lossy().to_lowercase()
+}
+
+fn names_equal(a: &OsStr, b: &OsStr) -> bool {
+    fold_case(a) == fold_case(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn service(name: &str, group: Option<&str>, dependencies: &[&str]) -> ServiceDependencies {
+        ServiceDependencies {
+            name: name.into(),
+            load_order_group: group.map(OsString::from),
+            dependencies: dependencies
+                .iter()
+                .map(ServiceDependency::from_system_identifier)
+                .collect(),
+        }
+    }
+
+    fn cycle_path(result: Result<Vec<&OsStr>>) -> Vec<String> {
+        match result {
+            Err(Error::DependencyCycle(cycle)) => cycle
+                .path()
+                .iter()
+                .map(|node| node.to_system_identifier().to_string_lossy().into_owned())
+                .collect(),
+            result => panic!("expected a dependency cycle, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_start_and_stop_orders() {
+        // "app" depends on "db" and on the "net" group, whose members depend on "base".
+        let graph = DependencyGraph::new(vec![
+            service("app", None, &["DB", "+Net"]),
+            service("tcp", Some("net"), &["base"]),
+            service("db", None, &["base"]),
+            service("dns", Some("NET"), &["base"]),
+            service("base", None, &[]),
+            service("idle", None, &[]),
+        ]);
+
+        assert_eq!(
+            graph.start_order().unwrap(),
+            ["base", "db", "tcp", "dns", "app", "idle"]
+        );
+        assert_eq!(
+            graph.stop_order().unwrap(),
+            ["app", "tcp", "db", "dns", "base", "idle"]
+        );
+        assert_eq!(
+            graph.start_order_for("APP").unwrap(),
+            ["base", "db", "tcp", "dns", "app"]
+        );
+        assert_eq!(graph.stop_order_for("dns").unwrap(), ["app", "dns"]);
+        assert_eq!(graph.dependents("base"), ["tcp", "db", "dns"]);
+        assert_eq!(graph.dependents("tcp"), ["app"]);
+        assert!(graph.start_order_for("unknown").unwrap().is_empty());
+        assert!(graph.cycles().is_empty());
+        assert!(graph.missing_dependencies().is_empty());
+    }
+
+    #[test]
+    fn test_cycles() {
+        let graph = DependencyGraph::new(vec![
+            service("a", None, &["b"]),
+            service("b", Some("ring"), &["c"]),
+            service("c", None, &["+ring"]),
+            service("d", None, &["a"]),
+            service("self", None, &["self"]),
+        ]);
+
+        assert_eq!(cycle_path(graph.start_order()), ["b", "c", "+ring"]);
+        // Walking the dependents finds the same cycle from the other side.
+        assert_eq!(cycle_path(graph.stop_order_for("c")), ["+ring", "b", "c"]);
+        assert!(graph.stop_order_for("d").is_ok());
+
+        let cycles = graph.cycles();
+        assert_eq!(
+            cycles,
+            [
+                vec![
+                    &ServiceDependency::Service("b".into()),
+                    &ServiceDependency::Service("c".into()),
+                    &ServiceDependency::Group("ring".into()),
+                ],
+                vec![&ServiceDependency::Service("self".into())],
+            ]
+        );
+
+        match graph.start_order() {
+            Err(Error::DependencyCycle(cycle)) => {
+                assert_eq!(cycle.to_string(), "b -> c -> group ring -> b")
+            }
+            result => panic!("expected a dependency cycle, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_missing_dependencies_and_duplicates() {
+        let graph = DependencyGraph::new(vec![
+            service("a", None, &["gone", "+empty"]),
+            service("b", None, &["a"]),
+            // The last record of a service wins.
+            service("B", None, &[]),
+        ]);
+
+        assert_eq!(
+            graph.missing_dependencies(),
+            [
+                (OsStr::new("a"), &ServiceDependency::Service("gone".into())),
+                (OsStr::new("a"), &ServiceDependency::Group("empty".into())),
+            ]
+        );
+        assert_eq!(graph.services().collect::<Vec<_>>(), ["a", "b"]);
+        assert!(graph.dependents("a").is_empty());
+        assert_eq!(graph.start_order().unwrap(), ["a", "b"]);
+        assert!(!graph.contains("gone"));
+    }
+
+    #[test]
+    fn test_query() {
+        use crate::backend::fake::FakeScm;
+        use crate::service::{ServiceErrorControl, ServiceInfo, ServiceStartType, ServiceType};
+        use crate::service_manager::ServiceManagerAccess;
+
+        let scm = FakeScm::new();
+        let manager = scm.connect(ServiceManagerAccess::ALL_ACCESS);
+        for (name, dependencies) in [("app", vec!["+net"]), ("tcp", vec![])] {
+            let service_info = ServiceInfo {
+                name: name.into(),
+                display_name: name.into(),
+                service_type: ServiceType::OWN_PROCESS,
+                start_type: ServiceStartType::OnDemand,
+                error_control: ServiceErrorControl::Normal,
+                executable_path: format!("C:\\{}.exe", name).into(),
+                launch_arguments: vec![],
+                dependencies: dependencies
+                    .into_iter()
+                    .map(ServiceDependency::from_system_identifier)
+                    .collect(),
+                account_name: None,
+                account_password: None,
+            };
+            manager
+                .create_service(&service_info, ServiceAccess::empty())
+                .unwrap();
+        }
+        scm.set_load_order_group("tcp", Some("Net".into())).unwrap();
+
+        let graph = DependencyGraph::query(&manager).unwrap();
+        assert_eq!(graph.start_order().unwrap(), ["tcp", "app"]);
+        assert_eq!(graph.dependents("tcp"), ["app"]);
+    }
+}
@@ -1,8 +1,14 @@
-use std::ffi::{OsStr, OsString};
-use widestring::{error::ContainsNul, WideCStr, WideCString, WideString};
+use std::ffi::OsStr;
+#[cfg(windows)]
+use std::ffi::OsString;
+
+#[cfg(windows)]
+use widestring::U16CStr;
+use widestring::{error::ContainsNul, U16CString, U16String};
+#[cfg(windows)]
 use windows_sys::core::PWSTR;
 
-/// A helper to join a slice of `OsStr`s into a nul-separated `WideString` ending with two nul
+/// A helper to join a slice of `OsStr`s into a nul-separated `U16String` ending with two nul
 /// wide characters.
 ///
 /// Input:
@@ -12,14 +18,14 @@ use windows_sys::core::PWSTR;
 /// "Hello\0World\0\0"
 ///
 /// Returns None if the source collection is empty.
-pub fn from_slice(source: &[impl AsRef<OsStr>]) -> Result<Option<WideString>, ContainsNul<u16>> {
+pub fn from_slice(source: &[impl AsRef<OsStr>]) -> Result<Option<U16String>, ContainsNul<u16>> {
     if source.is_empty() {
         Ok(None)
     } else {
         let capacity = source.iter().map(|s| s.as_ref().len() + 1).sum::<usize>() + 1;
-        let mut wide = WideString::with_capacity(capacity);
+        let mut wide = U16String::with_capacity(capacity);
         for s in source {
-            let checked_str = WideCString::from_os_str(s)?;
+            let checked_str = U16CString::from_os_str(s)?;
             wide.push_slice(checked_str);
             wide.push_slice([0]);
         }
@@ -36,13 +42,14 @@ pub fn from_slice(source: &[impl AsRef<OsStr>]) -> Result<Option<WideString>, Co
 ///
 /// Output:
 /// ["Hello", "World"]
+#[cfg(windows)]
 pub unsafe fn parse_str_ptr(double_nul_terminated_string: PWSTR) -> Vec<OsString> {
     let mut results: Vec<OsString> = Vec::new();
 
     if !double_nul_terminated_string.is_null() {
         let mut next = double_nul_terminated_string;
         loop {
-            let element = WideCStr::from_ptr_str(next);
+            let element = U16CStr::from_ptr_str(next);
             if element.is_empty() {
                 break;
             } else {
@@ -62,7 +69,7 @@ mod tests {
     #[test]
     fn test_from_slice() {
         assert_eq!(
-            Some(WideString::from_str("Hello\0World\0\0")),
+            Some(U16String::from_str("Hello\0World\0\0")),
             from_slice(&["Hello", "World"]).unwrap(),
         );
     }
@@ -78,19 +85,22 @@ mod tests {
     }
 
     #[test]
+    #[cfg(windows)]
     fn test_nul_byte_string() {
         let mut raw_data: Vec<u16> = vec![0];
         assert!(unsafe { parse_str_ptr(raw_data.as_mut_ptr()) }.is_empty());
     }
 
     #[test]
+    #[cfg(windows)]
     fn test_nul_ptr_string() {
         assert!(unsafe { parse_str_ptr(::std::ptr::null_mut()) }.is_empty());
     }
 
     #[test]
+    #[cfg(windows)]
     fn test_with_values() {
-        let mut raw_data = WideString::from_str("Hello\0World\0\0").into_vec();
+        let mut raw_data = U16String::from_str("Hello\0World\0\0").into_vec();
         assert_eq!(
             unsafe { parse_str_ptr(raw_data.as_mut_ptr()) },
             vec![OsString::from("Hello"), OsString::from("World")]
@@ -25,7 +25,8 @@
 //! This guide references the low level entry function as `ffi_service_main` and higher
 //! level function as `my_service_main` but it's up to developer how to call them.
 //!
-//! ```rust,no_run
+#![cfg_attr(windows, doc = "```rust,no_run")]
+#![cfg_attr(not(windows), doc = "```rust,ignore")]
 //! #[macro_use]
 //! extern crate windows_service;
 //!
@@ -52,7 +53,8 @@
 //! The first thing that a windows service should do early in its lifecycle is to subscribe for
 //! service events such as stop or pause and many other.
 //!
-//! ```rust,no_run
+#![cfg_attr(windows, doc = "```rust,no_run")]
+#![cfg_attr(not(windows), doc = "```rust,ignore")]
 //! extern crate windows_service;
 //!
 //! use std::ffi::OsString;
@@ -109,7 +111,8 @@
 //!
 //! Note that it's safe to clone [`ServiceStatusHandle`] and use it from any thread.
 //!
-//! ```rust,no_run
+#![cfg_attr(windows, doc = "```rust,no_run")]
+#![cfg_attr(not(windows), doc = "```rust,ignore")]
 //! extern crate windows_service;
 //!
 //! use std::ffi::OsString;
@@ -174,10 +177,14 @@
 //! [`StartPending`]: service::ServiceState::StartPending
 //! [`Running`]: service::ServiceState::Running
 
-#![cfg(windows)]
-
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// Lock the mutex, ignoring the poisoning by a thread that panicked while holding the lock. The
+/// data protected by the mutexes of this crate stays consistent across panics.
+pub(crate) fn lock<T>(mutex: &std::sync::Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
 #[derive(Debug)]
 #[non_exhaustive]
 pub enum Error {
@@ -189,8 +196,25 @@ pub enum Error {
     ArgumentHasNulByte(&'static str),
     /// An argument array contains a nul byte in element at the given index
     ArgumentArrayElementHasNulByte(&'static str, usize),
+    /// A service with the same name is already registered
+    DuplicateServiceName(std::ffi::OsString),
     /// IO error in winapi call
     Winapi(std::io::Error),
+    /// The service did not reach the expected state
+    Wait(wait::WaitError),
+    /// Services depend on each other in a cycle
+    DependencyCycle(dependency_graph::DependencyCycle),
+    /// The service status is not valid in the current state
+    Status(status_reporter::StatusError),
+    /// The system rejected the launch protection of the service
+    LaunchProtectedRejected(service::ServiceLaunchProtected, std::io::Error),
+    /// The system rejected the preferred NUMA node of the service, or clearing it when `None`
+    PreferredNodeRejected(Option<u16>, std::io::Error),
+    /// A buffer returned by the system has the given size in bytes, which is too small for the
+    /// data it refers to or does not match the type of its content
+    InvalidBufferSize(&'static str, usize),
+    /// The reason given for stopping a service is not valid, for the given reason
+    InvalidStopReason(&'static str),
 }
 
 impl std::error::Error for Error {
@@ -198,6 +222,11 @@ impl std::error::Error for Error {
         match self {
             Self::ParseValue(_, e) => Some(e),
             Self::Winapi(e) => Some(e),
+            Self::Wait(e) => Some(e),
+            Self::DependencyCycle(e) => Some(e),
+            Self::Status(e) => Some(e),
+            Self::LaunchProtectedRejected(_, e) => Some(e),
+            Self::PreferredNodeRejected(_, e) => Some(e),
             _ => None,
         }
     }
@@ -216,17 +245,57 @@ impl std::fmt::Display for Error {
                 "{} contains a nul byte in element at {} index",
                 name, index
             ),
+            Self::DuplicateServiceName(name) => {
+                write!(f, "duplicate service name: {}", name.to_string_lossy())
+            }
             Self::Winapi(_) => write!(f, "IO error in winapi call"),
+            Self::Wait(_) => write!(f, "service did not reach the expected state"),
+            Self::DependencyCycle(_) => write!(f, "circular service dependency"),
+            Self::Status(_) => write!(f, "invalid service status"),
+            Self::LaunchProtectedRejected(launch_protected, _) => write!(
+                f,
+                "launch protection {:?} rejected by the system",
+                launch_protected
+            ),
+            Self::PreferredNodeRejected(Some(node), _) => {
+                write!(f, "preferred node {} rejected by the system", node)
+            }
+            Self::PreferredNodeRejected(None, _) => {
+                write!(f, "clearing the preferred node rejected by the system")
+            }
+            Self::InvalidBufferSize(name, size) => {
+                write!(f, "invalid {} size: {} bytes", name, size)
+            }
+            Self::InvalidStopReason(reason) => write!(f, "invalid stop reason: {}", reason),
         }
     }
 }
 
+#[cfg(feature = "async")]
+pub mod asynchronous;
+pub mod audit;
+pub mod backend;
+pub mod dependency_graph;
+pub mod manifest;
+pub mod notify;
+#[cfg(windows)]
 mod sc_handle;
 pub mod service;
+#[cfg(windows)]
 pub mod service_control_handler;
+#[macro_use]
+pub mod service_handler;
 pub mod service_manager;
+pub mod service_runtime;
+pub mod service_trigger;
+pub mod status_reporter;
+#[cfg(windows)]
 #[macro_use]
 pub mod service_dispatcher;
+pub mod wait;
 
 mod double_nul_terminated;
+#[cfg(feature = "serde")]
+mod serde_helpers;
 mod shell_escape;
+mod sys;
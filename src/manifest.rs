@@ -0,0 +1,1115 @@
+//! Declarative service installation.
+//!
+//! A [`Manifest`] describes the services that should be installed, how they should be configured
+//! and which services should be removed. [`Manifest::diff`] compares the manifest with the
+//! [`InstalledService`]s and computes a [`Plan`] of steps that bring the system in line with it.
+//! The plan implements [`Display`](fmt::Display), so it can be printed for a dry run before
+//! being applied with [`Plan::apply`].
+//!
+//! The diff is computed from plain data and does not talk to the service control manager, only
+//! [`Manifest::plan`] and [`Plan::apply`] do.
+//!
+//! With the `serde` feature enabled, the manifest can be read from formats like TOML or JSON. The
+//! fields of [`ServiceInfo`] are flattened into the service entries:
+//!
+//! ```toml
+//! absent = ["old_service"]
+//!
+//! [[services]]
+//! name = "my_service"
+//! display_name = "My service"
+//! service_type = ["own_process"]
+//! start_type = "auto_start"
+//! error_control = "normal"
+//! executable_path = 'C:\Program Files\My Service\my_service.exe'
+//! launch_arguments = ["--service"]
+//! dependencies = [{ service = "Tcpip" }]
+//! delayed_auto_start = true
+//! description = "Does things in the background"
+//! preshutdown_timeout = 30000
+//! sid_type = "unrestricted"
+//! access_grants = [{ trustee = "Users", access = ["query_status", "start"] }]
+//!
+//! [services.failure_actions]
+//! reset_period = 86400
+//! actions = [{ action_type = "restart", delay = 5000 }]
+//! ```
+//!
+//! # Example
+//!
+#![cfg_attr(windows, doc = "```rust,no_run")]
+#![cfg_attr(not(windows), doc = "```rust,ignore")]
+//! use std::ffi::OsString;
+//! use windows_service::manifest::{Manifest, ServiceManifest};
+//! use windows_service::service::{
+//!     ServiceErrorControl, ServiceInfo, ServiceStartType, ServiceType,
+//! };
+//! use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+//!
+//! # fn main() -> windows_service::Result<()> {
+//! let manifest = Manifest {
+//!     services: vec![ServiceManifest {
+//!         description: Some(OsString::from("Does things in the background")),
+//!         delayed_auto_start: Some(true),
+//!         ..ServiceManifest::new(ServiceInfo {
+//!             name: OsString::from("my_service"),
+//!             display_name: OsString::from("My service"),
+//!             service_type: ServiceType::OWN_PROCESS,
+//!             start_type: ServiceStartType::AutoStart,
+//!             error_control: ServiceErrorControl::Normal,
+//!             executable_path: "C:\\Program Files\\My Service\\my_service.exe".into(),
+//!             launch_arguments: vec![],
+//!             dependencies: vec![],
+//!             account_name: None,
+//!             account_password: None,
+//!         })
+//!     }],
+//!     absent: vec![OsString::from("old_service")],
+//! };
+//!
+//! let manager = ServiceManager::local_computer(
+//!     None::<&str>,
+//!     ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE,
+//! )?;
+//! let plan = manifest.plan(&manager)?;
+//! println!("{}", plan);
+//! plan.apply(&manager)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::time::Duration;
+
+#[cfg(windows)]
+use windows_sys::Win32::Foundation::ERROR_SERVICE_DOES_NOT_EXIST;
+
+use crate::backend::ServiceBackend;
+use crate::service::{
+    RawServiceInfo, ServiceAccess, ServiceConfig, ServiceDependency, ServiceFailureActions,
+    ServiceInfo, ServiceSidType,
+};
+#[cfg(windows)]
+use crate::service::{Service, Trustee};
+#[cfg(windows)]
+use crate::service_manager::ServiceManager;
+use crate::{Error, Result};
+
+/// The desired state of a set of services.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Manifest {
+    /// Services that should be installed.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub services: Vec<ServiceManifest>,
+
+    /// Names of services that should not be installed, and are deleted if they are.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "crate::serde_helpers::vec_os_string")
+    )]
+    pub absent: Vec<OsString>,
+}
+
+/// The desired configuration of a single service.
+///
+/// The optional settings that are `None` are left as they are.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ServiceManifest {
+    /// The service configuration, as passed to [`ServiceManager::create_service`] and
+    /// [`Service::change_config`].
+    ///
+    /// The account password is only used when the service is created or its config changes, as
+    /// the current password cannot be queried.
+    ///
+    /// [`ServiceManager::create_service`]: crate::service_manager::ServiceManager::create_service
+    /// [`Service::change_config`]: crate::service::Service::change_config
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    pub service_info: ServiceInfo,
+
+    /// Whether the start of an auto-start service is delayed.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub delayed_auto_start: Option<bool>,
+
+    /// The service description.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "crate::serde_helpers::option_os_string")
+    )]
+    pub description: Option<OsString>,
+
+    /// The failure actions. The `None` fields of the failure actions are left as they are, in the
+    /// same way as with [`Service::update_failure_actions`].
+    ///
+    /// [`Service::update_failure_actions`]: crate::service::Service::update_failure_actions
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub failure_actions: Option<ServiceFailureActions>,
+
+    /// Whether the failure actions also run when the service stops with a non-zero exit code.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub failure_actions_on_non_crash_failures: Option<bool>,
+
+    /// The preshutdown timeout.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "crate::serde_helpers::option_duration_ms")
+    )]
+    pub preshutdown_timeout: Option<Duration>,
+
+    /// How the service SID is added to the service process token.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub sid_type: Option<ServiceSidType>,
+
+    /// Access rights granted to users or groups on the service object.
+    ///
+    /// The DACL of the service is not compared with the grants, so they are part of every plan
+    /// that includes the service. Granting access that is already granted has no effect.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub access_grants: Vec<AccessGrant>,
+}
+
+impl ServiceManifest {
+    /// Create a manifest for a service that leaves all the optional settings as they are.
+    pub fn new(service_info: ServiceInfo) -> Self {
+        ServiceManifest {
+            service_info,
+            delayed_auto_start: None,
+            description: None,
+            failure_actions: None,
+            failure_actions_on_non_crash_failures: None,
+            preshutdown_timeout: None,
+            sid_type: None,
+            access_grants: Vec::new(),
+        }
+    }
+
+    fn settings(&self, current: Option<&InstalledService>) -> Vec<ServiceSetting> {
+        let mut settings = Vec::new();
+
+        if let Some(delayed) = self.delayed_auto_start {
+            if current.map_or(true, |current| current.delayed_auto_start != delayed) {
+                settings.push(ServiceSetting::DelayedAutoStart(delayed));
+            }
+        }
+        if let Some(description) = &self.description {
+            if current.map_or(true, |current| {
+                current.description.as_deref().unwrap_or_default() != description
+            }) {
+                settings.push(ServiceSetting::Description(description.clone()));
+            }
+        }
+        if let Some(failure_actions) = &self.failure_actions {
+            if current.map_or(true, |current| {
+                !failure_actions_match(failure_actions, &current.failure_actions)
+            }) {
+                settings.push(ServiceSetting::FailureActions(failure_actions.clone()));
+            }
+        }
+        if let Some(enabled) = self.failure_actions_on_non_crash_failures {
+            if current.map_or(true, |current| {
+                current.failure_actions_on_non_crash_failures != enabled
+            }) {
+                settings.push(ServiceSetting::FailureActionsOnNonCrashFailures(enabled));
+            }
+        }
+        if let Some(timeout) = self.preshutdown_timeout {
+            if current.map_or(true, |current| current.preshutdown_timeout != timeout) {
+                settings.push(ServiceSetting::PreshutdownTimeout(timeout));
+            }
+        }
+        if let Some(sid_type) = self.sid_type {
+            if current.map_or(true, |current| current.sid_type != sid_type) {
+                settings.push(ServiceSetting::SidType(sid_type));
+            }
+        }
+        settings.extend(
+            self.access_grants
+                .iter()
+                .cloned()
+                .map(ServiceSetting::GrantAccess),
+        );
+
+        settings
+    }
+}
+
+/// Access rights granted to a user or group.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AccessGrant {
+    /// The name of the user or group, or `CURRENT_USER`.
+    pub trustee: String,
+
+    /// The granted access rights.
+    pub access: ServiceAccess,
+}
+
+/// The current state of an installed service, which the manifest is compared with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InstalledService {
+    /// Service name
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_helpers::os_string"))]
+    pub name: OsString,
+
+    /// The service config, see [`Service::query_config`].
+    ///
+    /// [`Service::query_config`]: crate::service::Service::query_config
+    pub config: ServiceConfig,
+
+    /// The service description, see [`Service::get_description`].
+    ///
+    /// [`Service::get_description`]: crate::service::Service::get_description
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "crate::serde_helpers::option_os_string")
+    )]
+    pub description: Option<OsString>,
+
+    /// See [`Service::get_delayed_auto_start`].
+    ///
+    /// [`Service::get_delayed_auto_start`]: crate::service::Service::get_delayed_auto_start
+    pub delayed_auto_start: bool,
+
+    /// See [`Service::get_failure_actions`].
+    ///
+    /// [`Service::get_failure_actions`]: crate::service::Service::get_failure_actions
+    pub failure_actions: ServiceFailureActions,
+
+    /// See [`Service::get_failure_actions_on_non_crash_failures`].
+    ///
+    /// [`Service::get_failure_actions_on_non_crash_failures`]:
+    /// crate::service::Service::get_failure_actions_on_non_crash_failures
+    pub failure_actions_on_non_crash_failures: bool,
+
+    /// See [`Service::get_preshutdown_timeout`].
+    ///
+    /// [`Service::get_preshutdown_timeout`]: crate::service::Service::get_preshutdown_timeout
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_helpers::duration_ms"))]
+    pub preshutdown_timeout: Duration,
+
+    /// See [`Service::get_config_service_sid_info`].
+    ///
+    /// [`Service::get_config_service_sid_info`]:
+    /// crate::service::Service::get_config_service_sid_info
+    pub sid_type: ServiceSidType,
+}
+
+#[cfg(windows)]
+impl InstalledService {
+    /// Query the current state of a service.
+    ///
+    /// Returns `None` if the service does not exist.
+    pub fn query(manager: &ServiceManager, name: impl AsRef<OsStr>) -> Result<Option<Self>> {
+        let service = match manager.open_service(name.as_ref(), ServiceAccess::QUERY_CONFIG) {
+            Ok(service) => service,
+            Err(Error::Winapi(e))
+                if e.raw_os_error() == Some(ERROR_SERVICE_DOES_NOT_EXIST as i32) =>
+            {
+                return Ok(None)
+            }
+            Err(e) => return Err(e),
+        };
+
+        Ok(Some(InstalledService {
+            name: name.as_ref().to_os_string(),
+            config: service.query_config()?,
+            description: service.get_description()?,
+            delayed_auto_start: service.get_delayed_auto_start()?,
+            failure_actions: service.get_failure_actions()?,
+            failure_actions_on_non_crash_failures: service
+                .get_failure_actions_on_non_crash_failures()?,
+            preshutdown_timeout: service.get_preshutdown_timeout()?,
+            sid_type: service.get_config_service_sid_info()?,
+        }))
+    }
+}
+
+impl Manifest {
+    /// Compute the steps needed to go from the installed services to the state described by the
+    /// manifest.
+    ///
+    /// `installed` should contain the current state of the services named in the manifest that
+    /// are installed. Other services are ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::DuplicateServiceName`] if a service is listed more than once in the
+    /// manifest, and an error if a [`ServiceInfo`] cannot be converted to its system form.
+    pub fn diff(&self, installed: &[InstalledService]) -> Result<Plan> {
+        self.check_duplicates()?;
+
+        let find = |name: &OsStr| {
+            installed
+                .iter()
+                .find(|service| names_equal(&service.name, name))
+        };
+
+        let mut steps = Vec::new();
+        for desired in &self.services {
+            match find(&desired.service_info.name) {
+                None => steps.push(PlanStep::Create {
+                    service_info: desired.service_info.clone(),
+                    settings: desired.settings(None),
+                }),
+                Some(current) => {
+                    let config = ConfigChange::new(&desired.service_info, &current.config)?;
+                    let settings = desired.settings(Some(current));
+                    if config.is_some() || !settings.is_empty() {
+                        steps.push(PlanStep::Change {
+                            name: current.name.clone(),
+                            config,
+                            settings,
+                        });
+                    }
+                }
+            }
+        }
+        for name in &self.absent {
+            if let Some(current) = find(name) {
+                steps.push(PlanStep::Delete {
+                    name: current.name.clone(),
+                });
+            }
+        }
+
+        Ok(Plan { steps })
+    }
+
+    /// Query the services named in the manifest and compute the plan with [`Manifest::diff`].
+    ///
+    /// The service manager must be open with the [`ServiceManagerAccess::CONNECT`] access right.
+    ///
+    /// [`ServiceManagerAccess::CONNECT`]: crate::service_manager::ServiceManagerAccess::CONNECT
+    #[cfg(windows)]
+    pub fn plan(&self, manager: &ServiceManager) -> Result<Plan> {
+        let names = self
+            .services
+            .iter()
+            .map(|service| service.service_info.name.as_os_str())
+            .chain(self.absent.iter().map(OsString::as_os_str));
+
+        let mut installed = Vec::new();
+        for name in names {
+            installed.extend(InstalledService::query(manager, name)?);
+        }
+        self.diff(&installed)
+    }
+
+    fn check_duplicates(&self) -> Result<()> {
+        let names: Vec<&OsStr> = self
+            .services
+            .iter()
+            .map(|service| service.service_info.name.as_os_str())
+            .chain(self.absent.iter().map(OsString::as_os_str))
+            .collect();
+
+        for (i, name) in names.iter().enumerate() {
+            if names[..i].iter().any(|other| names_equal(name, other)) {
+                return Err(Error::DuplicateServiceName(name.to_os_string()));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The steps needed to reconcile the installed services with a [`Manifest`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Plan {
+    /// The steps, in the order they are applied.
+    pub steps: Vec<PlanStep>,
+}
+
+impl Plan {
+    /// Returns `true` if the installed services already match the manifest.
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// Apply the steps in order, stopping at the first error.
+    ///
+    /// The service manager must be open with the [`ServiceManagerAccess::CREATE_SERVICE`] access
+    /// right if the plan creates services.
+    ///
+    /// [`ServiceManagerAccess::CREATE_SERVICE`]:
+    /// crate::service_manager::ServiceManagerAccess::CREATE_SERVICE
+    #[cfg(windows)]
+    pub fn apply(&self, manager: &ServiceManager) -> Result<()> {
+        self.steps.iter().try_for_each(|step| step.apply(manager))
+    }
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.steps.is_empty() {
+            return writeln!(f, "no changes");
+        }
+        for step in &self.steps {
+            write!(f, "{}", step)?;
+        }
+        Ok(())
+    }
+}
+
+/// A step of a [`Plan`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlanStep {
+    /// Create the service and apply the settings.
+    Create {
+        service_info: ServiceInfo,
+        settings: Vec<ServiceSetting>,
+    },
+
+    /// Change the config of an existing service if needed, and apply the settings.
+    Change {
+        name: OsString,
+        config: Option<ConfigChange>,
+        settings: Vec<ServiceSetting>,
+    },
+
+    /// Delete the service.
+    Delete { name: OsString },
+}
+
+impl PlanStep {
+    /// The name of the service this step applies to.
+    pub fn service_name(&self) -> &OsStr {
+        match self {
+            PlanStep::Create { service_info, .. } => &service_info.name,
+            PlanStep::Change { name, .. } | PlanStep::Delete { name } => name,
+        }
+    }
+
+    #[cfg(windows)]
+    fn apply(&self, manager: &ServiceManager) -> Result<()> {
+        // Restart failure actions require the START access right.
+        let access = ServiceAccess::CHANGE_CONFIG | ServiceAccess::START;
+
+        match self {
+            PlanStep::Create {
+                service_info,
+                settings,
+            } => {
+                let service = manager.create_service(service_info, access)?;
+                settings
+                    .iter()
+                    .try_for_each(|setting| setting.apply(&service))
+            }
+            PlanStep::Change {
+                name,
+                config,
+                settings,
+            } => {
+                let service = manager.open_service(name, access)?;
+                if let Some(config) = config {
+                    config.apply(&service)?;
+                }
+                settings
+                    .iter()
+                    .try_for_each(|setting| setting.apply(&service))
+            }
+            PlanStep::Delete { name } => {
+                manager.open_service(name, ServiceAccess::DELETE)?.delete()
+            }
+        }
+    }
+}
+
+impl fmt::Display for PlanStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = self.service_name();
+        match self {
+            PlanStep::Create { settings, .. } => {
+                writeln!(f, "create service {:?}", name)?;
+                write_settings(f, settings)
+            }
+            PlanStep::Change {
+                config, settings, ..
+            } => {
+                writeln!(f, "change service {:?}", name)?;
+                if let Some(config) = config {
+                    writeln!(f, "  change config: {}", config.changed_fields.join(", "))?;
+                }
+                write_settings(f, settings)
+            }
+            PlanStep::Delete { .. } => writeln!(f, "delete service {:?}", name),
+        }
+    }
+}
+
+fn write_settings(f: &mut fmt::Formatter<'_>, settings: &[ServiceSetting]) -> fmt::Result {
+    settings
+        .iter()
+        .try_for_each(|setting| writeln!(f, "  {}", setting))
+}
+
+/// A change of the config of an existing service.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigChange {
+    /// The new config, passed to [`Service::change_config`].
+    ///
+    /// If the service should run as `LocalSystem` again, the account name is set to
+    /// `LocalSystem`, as `None` would leave the account unchanged. Likewise, empty dependencies
+    /// leave the dependencies unchanged, so they are removed with [`Service::set_dependencies`]
+    /// when [`ConfigChange::changed_fields`] contains `dependencies`.
+    ///
+    /// [`Service::change_config`]: crate::service::Service::change_config
+    /// [`Service::set_dependencies`]: crate::service::Service::set_dependencies
+    pub service_info: ServiceInfo,
+
+    /// The names of the [`ServiceInfo`] fields that differ from the current config.
+    pub changed_fields: Vec<&'static str>,
+}
+
+impl ConfigChange {
+    fn new(desired: &ServiceInfo, current: &ServiceConfig) -> Result<Option<Self>> {
+        // The system stores the escaped executable path and launch arguments as a single command.
+        let launch_command = RawServiceInfo::new(desired)?.launch_command.to_os_string();
+
+        let mut changed_fields = Vec::new();
+        if desired.service_type != current.service_type {
+            changed_fields.push("service_type");
+        }
+        if desired.start_type != current.start_type {
+            changed_fields.push("start_type");
+        }
+        if desired.error_control != current.error_control {
+            changed_fields.push("error_control");
+        }
+        if launch_command != current.executable_path.as_os_str() {
+            changed_fields.push("executable_path");
+        }
+        if !dependencies_match(&desired.dependencies, &current.dependencies) {
+            changed_fields.push("dependencies");
+        }
+        if !account_names_match(
+            desired.account_name.as_deref(),
+            current.account_name.as_deref(),
+        ) {
+            changed_fields.push("account_name");
+        }
+        if desired.display_name != current.display_name {
+            changed_fields.push("display_name");
+        }
+
+        if changed_fields.is_empty() {
+            return Ok(None);
+        }
+
+        let mut service_info = desired.clone();
+        if service_info.account_name.is_none() && changed_fields.contains(&"account_name") {
+            // `Service::change_config` leaves the account unchanged when it is not given.
+            service_info.account_name = Some(OsString::from("LocalSystem"));
+        }
+        Ok(Some(ConfigChange {
+            service_info,
+            changed_fields,
+        }))
+    }
+
+    #[cfg_attr(not(windows), allow(dead_code))]
+    fn apply(&self, service: &impl ServiceBackend) -> Result<()> {
+        service.change_config(&self.service_info)?;
+        if self.service_info.dependencies.is_empty()
+            && self.changed_fields.contains(&"dependencies")
+        {
+            service.set_dependencies(&[])?;
+        }
+        Ok(())
+    }
+}
+
+/// An optional setting applied with one of the `Service::set_*` methods.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ServiceSetting {
+    DelayedAutoStart(bool),
+    Description(OsString),
+    FailureActions(ServiceFailureActions),
+    FailureActionsOnNonCrashFailures(bool),
+    PreshutdownTimeout(Duration),
+    SidType(ServiceSidType),
+    GrantAccess(AccessGrant),
+}
+
+impl ServiceSetting {
+    #[cfg(windows)]
+    fn apply(&self, service: &Service) -> Result<()> {
+        match self {
+            ServiceSetting::DelayedAutoStart(delayed) => service.set_delayed_auto_start(*delayed),
+            ServiceSetting::Description(description) => service.set_description(description),
+            ServiceSetting::FailureActions(failure_actions) => {
+                service.update_failure_actions(failure_actions.clone())
+            }
+            ServiceSetting::FailureActionsOnNonCrashFailures(enabled) => {
+                service.set_failure_actions_on_non_crash_failures(*enabled)
+            }
+            ServiceSetting::PreshutdownTimeout(timeout) => {
+                service.set_preshutdown_timeout(*timeout)
+            }
+            ServiceSetting::SidType(sid_type) => service.set_config_service_sid_info(*sid_type),
+            ServiceSetting::GrantAccess(grant) => {
+                service.grant_user_access(Trustee::Name(grant.trustee.clone()), grant.access)
+            }
+        }
+    }
+}
+
+impl fmt::Display for ServiceSetting {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServiceSetting::DelayedAutoStart(delayed) => {
+                write!(f, "set delayed auto start to {}", delayed)
+            }
+            ServiceSetting::Description(description) => {
+                write!(f, "set description to {:?}", description)
+            }
+            ServiceSetting::FailureActions(failure_actions) => {
+                write!(f, "set failure actions to {:?}", failure_actions)
+            }
+            ServiceSetting::FailureActionsOnNonCrashFailures(enabled) => write!(
+                f,
+                "set failure actions on non-crash failures to {}",
+                enabled
+            ),
+            ServiceSetting::PreshutdownTimeout(timeout) => {
+                write!(f, "set preshutdown timeout to {:?}", timeout)
+            }
+            ServiceSetting::SidType(sid_type) => write!(f, "set SID type to {:?}", sid_type),
+            ServiceSetting::GrantAccess(grant) => {
+                write!(f, "grant {:?} to {:?}", grant.access, grant.trustee)
+            }
+        }
+    }
+}
+
+/// The fields that are `None` in the desired failure actions are left as they are, so they match
+/// anything. The reset period is ignored when there are no actions.
+fn failure_actions_match(desired: &ServiceFailureActions, current: &ServiceFailureActions) -> bool {
+    let text_matches = |desired: &Option<OsString>, current: &Option<OsString>| {
+        desired.as_deref().map_or(true, |desired| {
+            current.as_deref().unwrap_or_default() == desired
+        })
+    };
+    let actions_match = desired.actions.as_deref().map_or(true, |actions| {
+        current.actions.as_deref().unwrap_or_default() == actions
+            && (actions.is_empty() || desired.reset_period == current.reset_period)
+    });
+
+    actions_match
+        && text_matches(&desired.reboot_msg, &current.reboot_msg)
+        && text_matches(&desired.command, &current.command)
+}
+
+/// The order of dependencies does not matter, and names are case insensitive.
+fn dependencies_match(desired: &[ServiceDependency], current: &[ServiceDependency]) -> bool {
+    let contains = |dependencies: &[ServiceDependency], dependency: &ServiceDependency| {
+        dependencies.iter().any(|other| match (dependency, other) {
+            (ServiceDependency::Service(a), ServiceDependency::Service(b))
+            | (ServiceDependency::Group(a), ServiceDependency::Group(b)) => names_equal(a, b),
+            _ => false,
+        })
+    };
+
+    desired.len() == current.len()
+        && desired
+            .iter()
+            .all(|dependency| contains(current, dependency))
+        && current
+            .iter()
+            .all(|dependency| contains(desired, dependency))
+}
+
+/// Services without an account name run as `LocalSystem`.
+fn account_names_match(desired: Option<&OsStr>, current: Option<&OsStr>) -> bool {
+    fn normalize(name: Option<&OsStr>) -> Option<&OsStr> {
+        name.filter(|name| !names_equal(name, "LocalSystem"))
+    }
+
+    match (normalize(desired), normalize(current)) {
+        (Some(desired), Some(current)) => names_equal(desired, current),
+        (desired, current) => desired == current,
+    }
+}
+
+fn names_equal(a: impl AsRef<OsStr>, b: impl AsRef<OsStr>) -> bool {
+    a.as_ref().to_string_lossy().to_lowercase() == b.as_ref().to_string_lossy().to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::service::{
+        ServiceAction, ServiceActionType, ServiceErrorControl, ServiceFailureResetPeriod,
+        ServiceStartType, ServiceType,
+    };
+
+    fn service_info(name: &str) -> ServiceInfo {
+        ServiceInfo {
+            name: OsString::from(name),
+            display_name: OsString::from(format!("{} display name", name)),
+            service_type: ServiceType::OWN_PROCESS,
+            start_type: ServiceStartType::AutoStart,
+            error_control: ServiceErrorControl::Normal,
+            executable_path: PathBuf::from("C:\\Program Files\\app\\service.exe"),
+            launch_arguments: vec![OsString::from("--service")],
+            dependencies: vec![ServiceDependency::Service(OsString::from("Tcpip"))],
+            account_name: None,
+            account_password: None,
+        }
+    }
+
+    fn installed(name: &str) -> InstalledService {
+        InstalledService {
+            name: OsString::from(name),
+            config: ServiceConfig {
+                service_type: ServiceType::OWN_PROCESS,
+                start_type: ServiceStartType::AutoStart,
+                error_control: ServiceErrorControl::Normal,
+                executable_path: PathBuf::from("\"C:\\Program Files\\app\\service.exe\" --service"),
+                load_order_group: None,
+                tag_id: 0,
+                dependencies: vec![ServiceDependency::Service(OsString::from("TCPIP"))],
+                account_name: Some(OsString::from("LocalSystem")),
+                display_name: OsString::from(format!("{} display name", name)),
+            },
+            description: None,
+            delayed_auto_start: false,
+            failure_actions: ServiceFailureActions {
+                reset_period: ServiceFailureResetPeriod::After(Duration::ZERO),
+                reboot_msg: None,
+                command: None,
+                actions: None,
+            },
+            failure_actions_on_non_crash_failures: false,
+            preshutdown_timeout: Duration::from_secs(10),
+            sid_type: ServiceSidType::None,
+        }
+    }
+
+    fn restart_actions() -> ServiceFailureActions {
+        ServiceFailureActions {
+            reset_period: ServiceFailureResetPeriod::After(Duration::from_secs(86400)),
+            reboot_msg: None,
+            command: None,
+            actions: Some(vec![ServiceAction {
+                action_type: ServiceActionType::Restart,
+                delay: Duration::from_secs(5),
+            }]),
+        }
+    }
+
+    #[test]
+    fn test_create_missing_service() {
+        let manifest = Manifest {
+            services: vec![ServiceManifest {
+                description: Some(OsString::from("description")),
+                sid_type: Some(ServiceSidType::Unrestricted),
+                ..ServiceManifest::new(service_info("a"))
+            }],
+            absent: vec![],
+        };
+
+        let plan = manifest.diff(&[installed("b")]).unwrap();
+        assert_eq!(
+            plan.steps,
+            vec![PlanStep::Create {
+                service_info: service_info("a"),
+                settings: vec![
+                    ServiceSetting::Description(OsString::from("description")),
+                    ServiceSetting::SidType(ServiceSidType::Unrestricted),
+                ],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_no_changes_when_in_sync() {
+        let mut current = installed("a");
+        current.name = OsString::from("A");
+        current.description = Some(OsString::from("description"));
+        current.failure_actions = restart_actions();
+        current.preshutdown_timeout = Duration::from_secs(30);
+
+        let manifest = Manifest {
+            services: vec![ServiceManifest {
+                description: Some(OsString::from("description")),
+                failure_actions: Some(restart_actions()),
+                preshutdown_timeout: Some(Duration::from_secs(30)),
+                delayed_auto_start: Some(false),
+                ..ServiceManifest::new(service_info("a"))
+            }],
+            absent: vec![OsString::from("not_installed")],
+        };
+
+        let plan = manifest.diff(&[current]).unwrap();
+        assert!(plan.is_empty(), "{}", plan);
+    }
+
+    #[test]
+    fn test_config_changes() {
+        let mut desired = service_info("a");
+        desired.start_type = ServiceStartType::OnDemand;
+        desired.launch_arguments.push(OsString::from("--verbose"));
+        desired.account_name = Some(OsString::from("NT AUTHORITY\\LocalService"));
+        let manifest = Manifest {
+            services: vec![ServiceManifest::new(desired.clone())],
+            absent: vec![],
+        };
+
+        let plan = manifest.diff(&[installed("a")]).unwrap();
+        assert_eq!(
+            plan.steps,
+            vec![PlanStep::Change {
+                name: OsString::from("a"),
+                config: Some(ConfigChange {
+                    service_info: desired,
+                    changed_fields: vec!["start_type", "executable_path", "account_name"],
+                }),
+                settings: vec![],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_config_change_converges() {
+        use crate::backend::fake::FakeScm;
+        use crate::backend::ScmBackend;
+        use crate::service_manager::ServiceManagerAccess;
+
+        let scm = FakeScm::new();
+        let manager = scm.connect(ServiceManagerAccess::CREATE_SERVICE);
+        let mut current = service_info("a");
+        current.account_name = Some(OsString::from("NT AUTHORITY\\LocalService"));
+        let service = manager
+            .create_service(
+                &current,
+                ServiceAccess::QUERY_CONFIG | ServiceAccess::CHANGE_CONFIG,
+            )
+            .unwrap();
+
+        let mut desired = service_info("a");
+        desired.dependencies.clear();
+        let manifest = Manifest {
+            services: vec![ServiceManifest::new(desired)],
+            absent: vec![],
+        };
+        let plan = || {
+            let installed = InstalledService {
+                config: service.query_config().unwrap(),
+                ..installed("a")
+            };
+            manifest.diff(&[installed]).unwrap()
+        };
+
+        let steps = plan().steps;
+        let config = match &steps[..] {
+            [PlanStep::Change {
+                config: Some(config),
+                ..
+            }] => config,
+            steps => panic!("unexpected steps: {:?}", steps),
+        };
+        assert_eq!(config.changed_fields, vec!["dependencies", "account_name"]);
+        config.apply(&service).unwrap();
+
+        let plan = plan();
+        assert!(plan.is_empty(), "{}", plan);
+    }
+
+    #[test]
+    fn test_dependencies_and_account_comparison() {
+        let services = [
+            ServiceDependency::Service(OsString::from("a")),
+            ServiceDependency::Group(OsString::from("b")),
+        ];
+        assert!(dependencies_match(
+            &services,
+            &[
+                ServiceDependency::Group(OsString::from("B")),
+                ServiceDependency::Service(OsString::from("A")),
+            ]
+        ));
+        assert!(!dependencies_match(
+            &services,
+            &[
+                ServiceDependency::Service(OsString::from("b")),
+                ServiceDependency::Service(OsString::from("a")),
+            ]
+        ));
+        assert!(!dependencies_match(&services, &services[..1]));
+
+        assert!(account_names_match(None, Some(OsStr::new("localsystem"))));
+        assert!(account_names_match(None, None));
+        assert!(account_names_match(
+            Some(OsStr::new("NT AUTHORITY\\LocalService")),
+            Some(OsStr::new("NT Authority\\LocalService"))
+        ));
+        assert!(!account_names_match(
+            None,
+            Some(OsStr::new("NT AUTHORITY\\LocalService"))
+        ));
+    }
+
+    #[test]
+    fn test_failure_actions_comparison() {
+        let current = restart_actions();
+
+        // `None` fields are left as they are.
+        let mut desired = restart_actions();
+        desired.actions = None;
+        desired.reset_period = ServiceFailureResetPeriod::Never;
+        assert!(failure_actions_match(&desired, &current));
+
+        let mut desired = restart_actions();
+        desired.reset_period = ServiceFailureResetPeriod::Never;
+        assert!(!failure_actions_match(&desired, &current));
+
+        let mut desired = restart_actions();
+        desired.command = Some(OsString::new());
+        assert!(failure_actions_match(&desired, &current));
+        desired.command = Some(OsString::from("cleanup.exe"));
+        assert!(!failure_actions_match(&desired, &current));
+
+        let mut desired = restart_actions();
+        desired.actions = Some(vec![]);
+        assert!(!failure_actions_match(&desired, &current));
+        assert!(failure_actions_match(
+            &desired,
+            &installed("a").failure_actions
+        ));
+    }
+
+    #[test]
+    fn test_settings_changes_and_grants() {
+        let grant = AccessGrant {
+            trustee: String::from("Users"),
+            access: ServiceAccess::QUERY_STATUS | ServiceAccess::START,
+        };
+        let manifest = Manifest {
+            services: vec![ServiceManifest {
+                delayed_auto_start: Some(true),
+                failure_actions: Some(restart_actions()),
+                failure_actions_on_non_crash_failures: Some(false),
+                access_grants: vec![grant.clone()],
+                ..ServiceManifest::new(service_info("a"))
+            }],
+            absent: vec![],
+        };
+
+        let plan = manifest.diff(&[installed("a")]).unwrap();
+        assert_eq!(
+            plan.steps,
+            vec![PlanStep::Change {
+                name: OsString::from("a"),
+                config: None,
+                settings: vec![
+                    ServiceSetting::DelayedAutoStart(true),
+                    ServiceSetting::FailureActions(restart_actions()),
+                    ServiceSetting::GrantAccess(grant),
+                ],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_delete_absent_services() {
+        let manifest = Manifest {
+            services: vec![],
+            absent: vec![OsString::from("old"), OsString::from("gone")],
+        };
+
+        let plan = manifest
+            .diff(&[installed("OLD"), installed("other")])
+            .unwrap();
+        assert_eq!(
+            plan.steps,
+            vec![PlanStep::Delete {
+                name: OsString::from("OLD")
+            }]
+        );
+    }
+
+    #[test]
+    fn test_duplicate_service_names() {
+        let manifest = Manifest {
+            services: vec![ServiceManifest::new(service_info("a"))],
+            absent: vec![OsString::from("A")],
+        };
+
+        assert!(matches!(
+            manifest.diff(&[]),
+            Err(Error::DuplicateServiceName(name)) if name == "A"
+        ));
+    }
+
+    #[test]
+    fn test_display_plan() {
+        let mut desired = service_info("b");
+        desired.display_name = OsString::from("B");
+        let manifest = Manifest {
+            services: vec![
+                ServiceManifest {
+                    delayed_auto_start: Some(true),
+                    ..ServiceManifest::new(service_info("a"))
+                },
+                ServiceManifest {
+                    preshutdown_timeout: Some(Duration::from_secs(30)),
+                    ..ServiceManifest::new(desired)
+                },
+            ],
+            absent: vec![OsString::from("c")],
+        };
+
+        let plan = manifest.diff(&[installed("b"), installed("c")]).unwrap();
+        assert_eq!(
+            plan.to_string(),
+            "create service \"a\"\n\
+             \x20 set delayed auto start to true\n\
+             change service \"b\"\n\
+             \x20 change config: display_name\n\
+             \x20 set preshutdown timeout to 30s\n\
+             delete service \"c\"\n"
+        );
+        assert_eq!(Plan::default().to_string(), "no changes\n");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_deserialize_manifest() {
+        let manifest: Manifest = serde_json::from_value(serde_json::json!({
+            "services": [{
+                "name": "a",
+                "display_name": "a display name",
+                "service_type": ["own_process"],
+                "start_type": "auto_start",
+                "error_control": "normal",
+                "executable_path": "C:\\Program Files\\app\\service.exe",
+                "launch_arguments": ["--service"],
+                "dependencies": [{ "service": "Tcpip" }],
+                "preshutdown_timeout": 30000,
+                "access_grants": [{ "trustee": "Users", "access": ["query_status"] }],
+            }],
+            "absent": ["old"],
+        }))
+        .unwrap();
+
+        assert_eq!(
+            manifest,
+            Manifest {
+                services: vec![ServiceManifest {
+                    preshutdown_timeout: Some(Duration::from_secs(30)),
+                    access_grants: vec![AccessGrant {
+                        trustee: String::from("Users"),
+                        access: ServiceAccess::QUERY_STATUS,
+                    }],
+                    ..ServiceManifest::new(service_info("a"))
+                }],
+                absent: vec![OsString::from("old")],
+            }
+        );
+    }
+}
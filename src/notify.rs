@@ -0,0 +1,467 @@
+//! Notifications about service state changes and about services being created or deleted.
+//!
+//! `NotifyServiceStatusChangeW` lets the system tell when a service changes its state, instead of
+//! polling [`Service::query_status`]. Notifications for a [`ServiceManager`] report the services
+//! that are created or deleted.
+//!
+//! The system delivers a notification as an asynchronous procedure call to the thread that asked
+//! for it, once the thread enters an alertable wait. [`ServiceNotifications`] does that wait in
+//! its [`Iterator`] implementation, blocking the current thread until the next notification.
+//! [`NotificationReceiver`] does the same on a background thread and forwards the notifications
+//! over a channel.
+//!
+//! A notification is registered for one event at a time, so a service that changes its state
+//! again before the next registration only reports its latest state.
+//!
+//! # Example
+//!
+#![cfg_attr(windows, doc = "```rust,no_run")]
+#![cfg_attr(not(windows), doc = "```rust,ignore")]
+//! use windows_service::notify::{ServiceNotification, ServiceNotifyMask};
+//! use windows_service::service::ServiceAccess;
+//! use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+//!
+//! # fn main() -> windows_service::Result<()> {
+//! let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)?;
+//! let service = manager.open_service("my_service", ServiceAccess::QUERY_STATUS)?;
+//! for notification in service.into_notifications(ServiceNotifyMask::STOPPED) {
+//!     if let ServiceNotification::StateChanged(status) = notification? {
+//!         println!("my_service stopped with {:?}", status.exit_code);
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`Service::query_status`]: crate::service::Service::query_status
+//! [`ServiceManager`]: crate::service_manager::ServiceManager
+
+use std::ffi::OsString;
+#[cfg(windows)]
+use std::{
+    collections::VecDeque,
+    ffi::c_void,
+    io, mem,
+    os::windows::io::AsRawHandle,
+    ptr,
+    sync::atomic::{AtomicBool, Ordering},
+    sync::{mpsc, Arc},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use widestring::{U16Str, U16String};
+
+#[cfg(windows)]
+use crate::double_nul_terminated;
+#[cfg(windows)]
+use crate::service::Service;
+use crate::service::ServiceStatus;
+#[cfg(windows)]
+use crate::service_manager::ServiceManager;
+use crate::sys::Services;
+#[cfg(windows)]
+use crate::sys::{Foundation, Threading};
+#[cfg(windows)]
+use crate::{Error, Result};
+
+bitflags::bitflags! {
+    /// Flags selecting the events to be notified about.
+    ///
+    /// The state flags apply to services, while [`ServiceNotifyMask::CREATED`] and
+    /// [`ServiceNotifyMask::DELETED`] apply to the service control manager.
+    #[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Copy)]
+    pub struct ServiceNotifyMask: u32 {
+        /// The service entered the stopped state.
+        const STOPPED = Services::SERVICE_NOTIFY_STOPPED;
+
+        /// The service entered the start pending state.
+        const START_PENDING = Services::SERVICE_NOTIFY_START_PENDING;
+
+        /// The service entered the stop pending state.
+        const STOP_PENDING = Services::SERVICE_NOTIFY_STOP_PENDING;
+
+        /// The service entered the running state.
+        const RUNNING = Services::SERVICE_NOTIFY_RUNNING;
+
+        /// The service entered the continue pending state.
+        const CONTINUE_PENDING = Services::SERVICE_NOTIFY_CONTINUE_PENDING;
+
+        /// The service entered the pause pending state.
+        const PAUSE_PENDING = Services::SERVICE_NOTIFY_PAUSE_PENDING;
+
+        /// The service entered the paused state.
+        const PAUSED = Services::SERVICE_NOTIFY_PAUSED;
+
+        /// A service was created.
+        const CREATED = Services::SERVICE_NOTIFY_CREATED;
+
+        /// A service was deleted.
+        const DELETED = Services::SERVICE_NOTIFY_DELETED;
+
+        /// The service was marked for deletion.
+        const DELETE_PENDING = Services::SERVICE_NOTIFY_DELETE_PENDING;
+
+        /// All the state flags.
+        const ALL_STATES = Self::STOPPED.bits()
+            | Self::START_PENDING.bits()
+            | Self::STOP_PENDING.bits()
+            | Self::RUNNING.bits()
+            | Self::CONTINUE_PENDING.bits()
+            | Self::PAUSE_PENDING.bits()
+            | Self::PAUSED.bits();
+    }
+}
+
+/// An event reported by the system.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum ServiceNotification {
+    /// A service with the given name was created.
+    Created(
+        #[cfg_attr(feature = "serde", serde(with = "crate::serde_helpers::os_string"))] OsString,
+    ),
+
+    /// A service with the given name was deleted.
+    Deleted(
+        #[cfg_attr(feature = "serde", serde(with = "crate::serde_helpers::os_string"))] OsString,
+    ),
+
+    /// The service was marked for deletion. It is deleted once it stops and all handles to it
+    /// are closed.
+    DeletePending,
+
+    /// The service entered a new state.
+    StateChanged(ServiceStatus),
+}
+
+impl ServiceNotification {
+    /// Convert the service names reported to the service control manager into notifications.
+    /// The names of created services are prefixed with a slash.
+    #[cfg_attr(not(windows), allow(dead_code))]
+    fn from_service_names(names: Vec<OsString>) -> impl Iterator<Item = ServiceNotification> {
+        names.into_iter().map(|name| {
+            let wide = U16String::from_os_str(&name);
+            match wide.as_slice().split_first() {
+                Some((&first, created)) if first == u16::from(b'/') => {
+                    ServiceNotification::Created(U16Str::from_slice(created).to_os_string())
+                }
+                _ => ServiceNotification::Deleted(name),
+            }
+        })
+    }
+}
+
+/// The handle notifications are requested for.
+#[cfg(windows)]
+pub(crate) enum NotifyHandle {
+    Service(Service),
+    Manager(ServiceManager),
+}
+
+#[cfg(windows)]
+impl NotifyHandle {
+    fn raw_handle(&self) -> Services::SC_HANDLE {
+        match self {
+            NotifyHandle::Service(service) => service.raw_handle(),
+            NotifyHandle::Manager(manager) => manager.raw_handle(),
+        }
+    }
+}
+
+/// A blocking iterator over the notifications for a service or the service control manager.
+///
+/// Created with [`Service::into_notifications`] or [`ServiceManager::into_notifications`]. Each
+/// call to [`Iterator::next`] blocks the current thread in an alertable wait until the next
+/// notification arrives. The iterator ends after yielding an error from registering for
+/// notifications, for example when the service is marked for deletion.
+///
+/// The iterator cannot be sent to another thread, because the system delivers the notifications
+/// to the thread that registered for them. Use [`NotificationReceiver`] to receive the
+/// notifications on another thread.
+#[cfg(windows)]
+pub struct ServiceNotifications {
+    handle: Option<NotifyHandle>,
+    mask: ServiceNotifyMask,
+    context: Box<NotifyContext>,
+    pending: VecDeque<ServiceNotification>,
+    registered: bool,
+    done: bool,
+    stop: Option<Arc<AtomicBool>>,
+}
+
+/// The buffer shared with the system while a notification is registered.
+#[cfg(windows)]
+struct NotifyContext {
+    buffer: Services::SERVICE_NOTIFY_2W,
+    triggered: bool,
+}
+
+#[cfg(windows)]
+impl ServiceNotifications {
+    pub(crate) fn new(handle: NotifyHandle, mask: ServiceNotifyMask) -> Self {
+        ServiceNotifications {
+            handle: Some(handle),
+            mask,
+            context: Box::new(NotifyContext {
+                buffer: unsafe { mem::zeroed() },
+                triggered: false,
+            }),
+            pending: VecDeque::new(),
+            registered: false,
+            done: false,
+            stop: None,
+        }
+    }
+
+    fn register(&mut self) -> Result<()> {
+        let handle = self
+            .handle
+            .as_ref()
+            .expect("handle is closed only when dropping")
+            .raw_handle();
+        let context: *mut NotifyContext = &mut *self.context;
+        let result = unsafe {
+            (*context).buffer = Services::SERVICE_NOTIFY_2W {
+                dwVersion: Services::SERVICE_NOTIFY_STATUS_CHANGE,
+                pfnNotifyCallback: Some(notify_callback),
+                pContext: context as *mut c_void,
+                ..mem::zeroed()
+            };
+            Services::NotifyServiceStatusChangeW(handle, self.mask.bits(), &(*context).buffer)
+        };
+        if result == Foundation::NO_ERROR {
+            self.registered = true;
+            Ok(())
+        } else {
+            Err(Error::Winapi(io::Error::from_raw_os_error(result as i32)))
+        }
+    }
+
+    /// Turn the triggered notification into events.
+    fn take_notification(&mut self) -> Result<()> {
+        self.context.triggered = false;
+        self.registered = false;
+        let buffer = &mut self.context.buffer;
+
+        let service_names = if buffer.pszServiceNames.is_null() {
+            Vec::new()
+        } else {
+            let names = unsafe { double_nul_terminated::parse_str_ptr(buffer.pszServiceNames) };
+            unsafe { Foundation::LocalFree(buffer.pszServiceNames as _) };
+            buffer.pszServiceNames = ptr::null_mut();
+            names
+        };
+
+        if buffer.dwNotificationStatus != Foundation::NO_ERROR {
+            return Err(Error::Winapi(io::Error::from_raw_os_error(
+                buffer.dwNotificationStatus as i32,
+            )));
+        }
+
+        let triggered = ServiceNotifyMask::from_bits_truncate(buffer.dwNotificationTriggered);
+        if triggered.intersects(ServiceNotifyMask::CREATED | ServiceNotifyMask::DELETED) {
+            self.pending
+                .extend(ServiceNotification::from_service_names(service_names));
+        } else if triggered.contains(ServiceNotifyMask::DELETE_PENDING) {
+            self.pending.push_back(ServiceNotification::DeletePending);
+        } else {
+            let status = ServiceStatus::from_raw_ex(buffer.ServiceStatus)
+                .map_err(|e| Error::ParseValue("service status", e))?;
+            self.pending
+                .push_back(ServiceNotification::StateChanged(status));
+        }
+        Ok(())
+    }
+
+    fn is_stopped(&self) -> bool {
+        self.stop
+            .as_ref()
+            .is_some_and(|stop| stop.load(Ordering::SeqCst))
+    }
+}
+
+#[cfg(windows)]
+impl Iterator for ServiceNotifications {
+    type Item = Result<ServiceNotification>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(notification) = self.pending.pop_front() {
+                return Some(Ok(notification));
+            }
+            if self.done {
+                return None;
+            }
+            if !self.registered {
+                if let Err(e) = self.register() {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+
+            // The callback runs on this thread during an alertable wait, which also returns
+            // early for any other asynchronous procedure call queued to the thread.
+            while !self.context.triggered {
+                unsafe { Threading::SleepEx(Threading::INFINITE, 1) };
+                if self.is_stopped() {
+                    self.done = true;
+                    return None;
+                }
+            }
+            if let Err(e) = self.take_notification() {
+                return Some(Err(e));
+            }
+        }
+    }
+}
+
+#[cfg(windows)]
+impl Drop for ServiceNotifications {
+    fn drop(&mut self) {
+        // Closing the handle cancels the registered notification, but its callback may be queued
+        // already. Run it before the buffer is freed.
+        drop(self.handle.take());
+        if self.registered {
+            unsafe { Threading::SleepEx(0, 1) };
+        }
+        if !self.context.buffer.pszServiceNames.is_null() {
+            unsafe { Foundation::LocalFree(self.context.buffer.pszServiceNames as _) };
+        }
+    }
+}
+
+#[cfg(windows)]
+unsafe extern "system" fn notify_callback(parameter: *const c_void) {
+    let buffer = parameter as *const Services::SERVICE_NOTIFY_2W;
+    let context = (*buffer).pContext as *mut NotifyContext;
+    (*context).triggered = true;
+}
+
+/// Receives the notifications for a service or the service control manager from a background
+/// thread.
+///
+/// Created with [`Service::into_notification_channel`] or
+/// [`ServiceManager::into_notification_channel`]. The background thread waits for notifications
+/// like [`ServiceNotifications`] does, and is stopped when the receiver is dropped. The channel
+/// is disconnected once the thread ends after an error.
+#[cfg(windows)]
+pub struct NotificationReceiver {
+    receiver: mpsc::Receiver<Result<ServiceNotification>>,
+    _worker: NotificationWorker,
+}
+
+#[cfg(windows)]
+impl NotificationReceiver {
+    pub(crate) fn spawn(handle: NotifyHandle, mask: ServiceNotifyMask) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let worker = NotificationWorker::spawn(handle, mask, move |notification| {
+            sender.send(notification).is_ok()
+        });
+        NotificationReceiver {
+            receiver,
+            _worker: worker,
+        }
+    }
+
+    /// Wait for the next notification. Fails once the background thread has ended.
+    pub fn recv(&self) -> std::result::Result<Result<ServiceNotification>, mpsc::RecvError> {
+        self.receiver.recv()
+    }
+
+    /// Wait for the next notification for at most `timeout`.
+    pub fn recv_timeout(
+        &self,
+        timeout: Duration,
+    ) -> std::result::Result<Result<ServiceNotification>, mpsc::RecvTimeoutError> {
+        self.receiver.recv_timeout(timeout)
+    }
+
+    /// Return the next notification if there is one, without blocking.
+    pub fn try_recv(&self) -> std::result::Result<Result<ServiceNotification>, mpsc::TryRecvError> {
+        self.receiver.try_recv()
+    }
+
+    /// An iterator that blocks waiting for notifications, and ends once the background thread
+    /// has ended.
+    pub fn iter(&self) -> mpsc::Iter<'_, Result<ServiceNotification>> {
+        self.receiver.iter()
+    }
+}
+
+/// A background thread waiting for notifications, which is stopped when dropped.
+#[cfg(windows)]
+pub(crate) struct NotificationWorker {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+#[cfg(windows)]
+impl NotificationWorker {
+    /// Spawn a thread passing the notifications to `on_notification` until it returns `false`.
+    pub(crate) fn spawn(
+        handle: NotifyHandle,
+        mask: ServiceNotifyMask,
+        mut on_notification: impl FnMut(Result<ServiceNotification>) -> bool + Send + 'static,
+    ) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        let thread = thread::spawn(move || {
+            let mut notifications = ServiceNotifications::new(handle, mask);
+            notifications.stop = Some(thread_stop);
+            for notification in notifications {
+                if !on_notification(notification) {
+                    break;
+                }
+            }
+        });
+        NotificationWorker {
+            stop,
+            thread: Some(thread),
+        }
+    }
+}
+
+#[cfg(windows)]
+impl Drop for NotificationWorker {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            // Interrupt the alertable wait of the background thread. Queuing fails only if the
+            // thread has ended already.
+            unsafe {
+                Threading::QueueUserAPC(Some(wake_up), thread.as_raw_handle() as _, 0);
+            }
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(windows)]
+unsafe extern "system" fn wake_up(_parameter: usize) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_service_names() {
+        let names = vec![
+            OsString::from("/created"),
+            OsString::from("deleted"),
+            OsString::from("/"),
+        ];
+        assert_eq!(
+            ServiceNotification::from_service_names(names).collect::<Vec<_>>(),
+            vec![
+                ServiceNotification::Created("created".into()),
+                ServiceNotification::Deleted("deleted".into()),
+                ServiceNotification::Created("".into()),
+            ]
+        );
+    }
+}
@@ -14,6 +14,11 @@ impl ScHandle {
     }
 }
 
+// SAFETY: The service control manager functions can be called with the same handle from any
+// thread.
+unsafe impl Send for ScHandle {}
+unsafe impl Sync for ScHandle {}
+
 impl Drop for ScHandle {
     fn drop(&mut self) {
         unsafe { Services::CloseServiceHandle(self.0) };
@@ -0,0 +1,466 @@
+//! Serde support, enabled with the `serde` cargo feature.
+//!
+//! The representations are meant to be stable and readable in formats like JSON or TOML:
+//!
+//! * Enums are written as snake case strings, e.g. [`ServiceStartType::AutoStart`] as
+//!   `"auto_start"`, and enums carrying data as single key maps, e.g.
+//!   `{ "service_specific": 3 }`.
+//! * Bitflags are written as lists of snake case flag names, e.g. `["own_process"]`.
+//! * [`ServiceFailureResetPeriod`] is written as a number of seconds or `"never"`.
+//! * Other durations are written as a number of milliseconds.
+//! * `OsString`s and paths are written as strings. The ones that are not valid Unicode are
+//!   written losslessly in their platform encoding instead, as `{ "wide": [...] }` with the UTF-16
+//!   code units on Windows and as `{ "bytes": [...] }` elsewhere.
+//!
+//! [`ServiceStartType::AutoStart`]: crate::service::ServiceStartType::AutoStart
+
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::time::Duration;
+
+use serde::de::{self, Deserializer, Visitor};
+use serde::ser::{self, Serializer};
+use serde::{Deserialize, Serialize};
+
+use crate::service::{ServiceAccess, ServiceControlAccept, ServiceFailureResetPeriod, ServiceType};
+use crate::service_manager::ServiceManagerAccess;
+
+/// The lossless representation of an `OsStr` that is not valid Unicode.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum EncodedOsString {
+    Wide(Vec<u16>),
+    Bytes(Vec<u8>),
+}
+
+impl EncodedOsString {
+    #[cfg(windows)]
+    fn encode(value: &OsStr) -> Option<Self> {
+        use std::os::windows::ffi::OsStrExt;
+        Some(EncodedOsString::Wide(value.encode_wide().collect()))
+    }
+
+    #[cfg(unix)]
+    fn encode(value: &OsStr) -> Option<Self> {
+        use std::os::unix::ffi::OsStrExt;
+        Some(EncodedOsString::Bytes(value.as_bytes().to_vec()))
+    }
+
+    #[cfg(not(any(windows, unix)))]
+    fn encode(_value: &OsStr) -> Option<Self> {
+        None
+    }
+
+    /// Decode the value, which only fails for the encoding of another platform that is not
+    /// valid Unicode.
+    fn decode<E: de::Error>(self) -> Result<OsString, E> {
+        match self {
+            #[cfg(windows)]
+            EncodedOsString::Wide(wide) => {
+                use std::os::windows::ffi::OsStringExt;
+                Ok(OsString::from_wide(&wide))
+            }
+            #[cfg(not(windows))]
+            EncodedOsString::Wide(wide) => String::from_utf16(&wide)
+                .map(OsString::from)
+                .map_err(E::custom),
+            #[cfg(unix)]
+            EncodedOsString::Bytes(bytes) => {
+                use std::os::unix::ffi::OsStringExt;
+                Ok(OsString::from_vec(bytes))
+            }
+            #[cfg(not(unix))]
+            EncodedOsString::Bytes(bytes) => String::from_utf8(bytes)
+                .map(OsString::from)
+                .map_err(E::custom),
+        }
+    }
+}
+
+/// Serializes an `OsStr` as a string, or as [`EncodedOsString`] if it is not valid Unicode.
+struct OsStrRef<'a>(&'a OsStr);
+
+impl Serialize for OsStrRef<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match (self.0.to_str(), EncodedOsString::encode(self.0)) {
+            (Some(s), _) => serializer.serialize_str(s),
+            (None, Some(encoded)) => encoded.serialize(serializer),
+            (None, None) => Err(ser::Error::custom(format_args!(
+                "{:?} is not valid unicode",
+                self.0
+            ))),
+        }
+    }
+}
+
+/// Deserializes an `OsString` written by [`OsStrRef`].
+struct OsStringValue(OsString);
+
+impl<'de> Deserialize<'de> for OsStringValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            String(String),
+            Encoded(EncodedOsString),
+        }
+
+        match Repr::deserialize(deserializer)? {
+            Repr::String(s) => Ok(OsStringValue(OsString::from(s))),
+            Repr::Encoded(encoded) => encoded.decode().map(OsStringValue),
+        }
+    }
+}
+
+pub(crate) mod os_string {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(value: &OsStr, serializer: S) -> Result<S::Ok, S::Error> {
+        OsStrRef(value).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<OsString, D::Error> {
+        OsStringValue::deserialize(deserializer).map(|value| value.0)
+    }
+}
+
+pub(crate) mod option_os_string {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        value: &Option<OsString>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        value.as_deref().map(OsStrRef).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<OsString>, D::Error> {
+        Option::<OsStringValue>::deserialize(deserializer).map(|value| value.map(|value| value.0))
+    }
+}
+
+pub(crate) mod vec_os_string {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(value: &[OsString], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(value.iter().map(|s| OsStrRef(s)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<OsString>, D::Error> {
+        Vec::<OsStringValue>::deserialize(deserializer)
+            .map(|values| values.into_iter().map(|value| value.0).collect())
+    }
+}
+
+pub(crate) mod path_buf {
+    use std::path::{Path, PathBuf};
+
+    use super::*;
+
+    pub fn serialize<S: Serializer>(value: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+        OsStrRef(value.as_os_str()).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<PathBuf, D::Error> {
+        OsStringValue::deserialize(deserializer).map(|value| PathBuf::from(value.0))
+    }
+}
+
+pub(crate) mod duration_ms {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(value: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        let millis = u64::try_from(value.as_millis())
+            .map_err(|_| ser::Error::custom("duration is too long"))?;
+        serializer.serialize_u64(millis)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        u64::deserialize(deserializer).map(Duration::from_millis)
+    }
+}
+
+pub(crate) mod option_duration_ms {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        value: &Option<Duration>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct DurationMs(#[serde(with = "duration_ms")] Duration);
+
+        value.map(DurationMs).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Duration>, D::Error> {
+        Option::<u64>::deserialize(deserializer).map(|value| value.map(Duration::from_millis))
+    }
+}
+
+impl Serialize for ServiceFailureResetPeriod {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            ServiceFailureResetPeriod::Never => serializer.serialize_str("never"),
+            ServiceFailureResetPeriod::After(duration) => {
+                serializer.serialize_u64(duration.as_secs())
+            }
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ServiceFailureResetPeriod {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ResetPeriodVisitor;
+
+        impl Visitor<'_> for ResetPeriodVisitor {
+            type Value = ServiceFailureResetPeriod;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+                formatter.write_str("a number of seconds or \"never\"")
+            }
+
+            fn visit_u64<E: de::Error>(self, seconds: u64) -> Result<Self::Value, E> {
+                Ok(ServiceFailureResetPeriod::After(Duration::from_secs(
+                    seconds,
+                )))
+            }
+
+            fn visit_i64<E: de::Error>(self, seconds: i64) -> Result<Self::Value, E> {
+                u64::try_from(seconds)
+                    .map_err(|_| E::invalid_value(de::Unexpected::Signed(seconds), &self))
+                    .and_then(|seconds| self.visit_u64(seconds))
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+                match value {
+                    "never" => Ok(ServiceFailureResetPeriod::Never),
+                    _ => Err(E::invalid_value(de::Unexpected::Str(value), &self)),
+                }
+            }
+        }
+
+        deserializer.deserialize_any(ResetPeriodVisitor)
+    }
+}
+
+/// Implements serde traits for bitflags, represented as lists of snake case flag names.
+macro_rules! impl_serde_for_bitflags {
+    ($($flags:ty),* $(,)?) => {
+        $(
+            impl Serialize for $flags {
+                fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    serializer.collect_seq(self.iter_names().map(|(name, _)| name.to_lowercase()))
+                }
+            }
+
+            impl<'de> Deserialize<'de> for $flags {
+                fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    Vec::<String>::deserialize(deserializer)?
+                        .iter()
+                        .try_fold(Self::empty(), |flags, name| {
+                            Self::from_name(&name.to_uppercase())
+                                .map(|flag| flags | flag)
+                                .ok_or_else(|| {
+                                    de::Error::custom(format_args!("unknown flag {:?}", name))
+                                })
+                        })
+                }
+            }
+        )*
+    };
+}
+
+impl_serde_for_bitflags!(
+    ServiceType,
+    ServiceAccess,
+    ServiceControlAccept,
+    ServiceManagerAccess,
+);
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use serde_json::json;
+
+    use crate::service::{
+        ServiceAction, ServiceActionType, ServiceConfig, ServiceDependency, ServiceErrorControl,
+        ServiceExitCode, ServiceFailureActions, ServiceInfo, ServiceStartType, ServiceState,
+        ServiceStatus,
+    };
+
+    use super::*;
+
+    fn round_trip<T>(value: &T) -> serde_json::Value
+    where
+        T: Serialize + for<'de> Deserialize<'de> + PartialEq + fmt::Debug,
+    {
+        let json = serde_json::to_value(value).unwrap();
+        let deserialized: T = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(&deserialized, value);
+        json
+    }
+
+    #[test]
+    fn test_service_info_round_trip() {
+        let service_info = ServiceInfo {
+            name: OsString::from("my_service"),
+            display_name: OsString::from("My service"),
+            service_type: ServiceType::OWN_PROCESS | ServiceType::INTERACTIVE_PROCESS,
+            start_type: ServiceStartType::AutoStart,
+            error_control: ServiceErrorControl::Normal,
+            executable_path: PathBuf::from("C:\\my service.exe"),
+            launch_arguments: vec![OsString::from("--verbose")],
+            dependencies: vec![
+                ServiceDependency::Service(OsString::from("netlogon")),
+                ServiceDependency::Group(OsString::from("network")),
+            ],
+            account_name: None,
+            account_password: None,
+        };
+
+        assert_eq!(
+            round_trip(&service_info),
+            json!({
+                "name": "my_service",
+                "display_name": "My service",
+                "service_type": ["own_process", "interactive_process"],
+                "start_type": "auto_start",
+                "error_control": "normal",
+                "executable_path": "C:\\my service.exe",
+                "launch_arguments": ["--verbose"],
+                "dependencies": [{ "service": "netlogon" }, { "group": "network" }],
+                "account_name": null,
+                "account_password": null,
+            })
+        );
+    }
+
+    #[test]
+    fn test_service_config_round_trip() {
+        round_trip(&ServiceConfig {
+            service_type: ServiceType::KERNEL_DRIVER,
+            start_type: ServiceStartType::BootStart,
+            error_control: ServiceErrorControl::Critical,
+            executable_path: PathBuf::from("\\SystemRoot\\System32\\drivers\\my.sys"),
+            load_order_group: Some(OsString::from("Boot Bus Extender")),
+            tag_id: 4,
+            dependencies: vec![],
+            account_name: None,
+            display_name: OsString::from("My driver"),
+        });
+    }
+
+    #[test]
+    fn test_service_status_round_trip() {
+        let status = ServiceStatus {
+            service_type: ServiceType::OWN_PROCESS,
+            current_state: ServiceState::StartPending,
+            controls_accepted: ServiceControlAccept::STOP | ServiceControlAccept::PAUSE_CONTINUE,
+            exit_code: ServiceExitCode::ServiceSpecific(3),
+            checkpoint: 2,
+            wait_hint: Duration::from_secs(5),
+            process_id: Some(1234),
+        };
+
+        assert_eq!(
+            round_trip(&status),
+            json!({
+                "service_type": ["own_process"],
+                "current_state": "start_pending",
+                "controls_accepted": ["pause_continue", "stop"],
+                "exit_code": { "service_specific": 3 },
+                "checkpoint": 2,
+                "wait_hint": 5000,
+                "process_id": 1234,
+            })
+        );
+    }
+
+    #[test]
+    fn test_failure_actions_round_trip() {
+        let failure_actions = ServiceFailureActions {
+            reset_period: ServiceFailureResetPeriod::After(Duration::from_secs(86400)),
+            reboot_msg: None,
+            command: Some(OsString::from("ping 127.0.0.1")),
+            actions: Some(vec![ServiceAction {
+                action_type: ServiceActionType::RunCommand,
+                delay: Duration::from_millis(1500),
+            }]),
+        };
+
+        assert_eq!(
+            round_trip(&failure_actions),
+            json!({
+                "reset_period": 86400,
+                "reboot_msg": null,
+                "command": "ping 127.0.0.1",
+                "actions": [{ "action_type": "run_command", "delay": 1500 }],
+            })
+        );
+        assert_eq!(
+            round_trip(&ServiceFailureResetPeriod::Never),
+            json!("never")
+        );
+    }
+
+    #[test]
+    fn test_invalid_values() {
+        assert!(serde_json::from_value::<ServiceFailureResetPeriod>(json!("always")).is_err());
+        assert!(serde_json::from_value::<ServiceFailureResetPeriod>(json!(-1)).is_err());
+        assert!(serde_json::from_value::<ServiceType>(json!(["own_process", "nope"])).is_err());
+        assert!(serde_json::from_value::<ServiceStartType>(json!("AutoStart")).is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_invalid_unicode_round_trip() {
+        use std::os::unix::ffi::OsStringExt;
+
+        let dependency = ServiceDependency::Service(OsString::from_vec(vec![0x66, 0xff]));
+        assert_eq!(
+            round_trip(&dependency),
+            json!({ "service": { "bytes": [0x66, 0xff] } })
+        );
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn test_invalid_unicode_round_trip() {
+        use std::os::windows::ffi::OsStringExt;
+
+        // Unpaired surrogate
+        let dependency = ServiceDependency::Service(OsString::from_wide(&[0x66, 0xd800]));
+        assert_eq!(
+            round_trip(&dependency),
+            json!({ "service": { "wide": [0x66, 0xd800] } })
+        );
+    }
+
+    #[test]
+    fn test_unpaired_surrogate_round_trip() {
+        // The Windows encoding of a name with an unpaired surrogate, as written on Windows.
+        let json = json!({ "wide": [0x66, 0xd800] });
+        let decoded = serde_json::from_value::<OsStringValue>(json.clone());
+        if cfg!(windows) {
+            let value = decoded.unwrap().0;
+            assert_eq!(serde_json::to_value(OsStrRef(&value)).unwrap(), json);
+        } else {
+            // Not representable outside of Windows.
+            assert!(decoded.is_err());
+        }
+        assert_eq!(
+            serde_json::from_value::<OsStringValue>(json!({ "wide": [0x66, 0x6f] }))
+                .unwrap()
+                .0,
+            "fo"
+        );
+    }
+}
@@ -1,28 +1,50 @@
 use std::borrow::Cow;
 use std::ffi::{OsStr, OsString};
+use std::io;
+#[cfg(windows)]
+use std::mem;
+#[cfg(windows)]
 use std::os::raw::c_void;
-use std::os::windows::ffi::{OsStrExt, OsStringExt};
 use std::path::PathBuf;
+#[cfg(windows)]
 use std::ptr;
 use std::time::Duration;
-use std::{io, mem};
 
-use widestring::{error::ContainsNul, WideCStr, WideCString, WideString};
-use windows_sys::Win32::Foundation::ERROR_SUCCESS;
-use windows_sys::Win32::Security;
-use windows_sys::Win32::Security::Authorization;
+#[cfg(windows)]
+use widestring::U16CStr;
+use widestring::{error::ContainsNul, U16CString, U16String};
+#[cfg(windows)]
 use windows_sys::{
     core::GUID,
     Win32::{
-        Foundation::{ERROR_SERVICE_SPECIFIC_ERROR, NO_ERROR},
-        Storage::FileSystem,
-        System::{Power, RemoteDesktop, Services, SystemServices, Threading::INFINITE},
-        UI::WindowsAndMessaging,
+        Foundation::ERROR_SUCCESS,
+        Security::{self, Authorization},
+        System::RemoteDesktop,
     },
 };
 
+#[cfg(windows)]
+use crate::notify::{NotificationReceiver, NotifyHandle, ServiceNotifications, ServiceNotifyMask};
+#[cfg(windows)]
 use crate::sc_handle::ScHandle;
+#[cfg(windows)]
+use crate::service_manager::{ServiceActiveState, ServiceEntry, ServiceManager};
+#[cfg(windows)]
+use crate::service_trigger;
+use crate::service_trigger::ServiceTrigger;
 use crate::shell_escape;
+#[cfg(windows)]
+use crate::sys::Foundation::{
+    ERROR_INSUFFICIENT_BUFFER, ERROR_INVALID_LEVEL, ERROR_MORE_DATA, ERROR_SERVICE_SPECIFIC_ERROR,
+};
+use crate::sys::Foundation::{
+    ERROR_INVALID_IMAGE_HASH, ERROR_INVALID_PARAMETER, ERROR_NOT_SUPPORTED, NO_ERROR,
+};
+use crate::sys::{
+    FileSystem, Power, Services, SystemServices, Threading::INFINITE, WindowsAndMessaging,
+};
+#[cfg(windows)]
+use crate::wait;
 use crate::{double_nul_terminated, Error};
 
 bitflags::bitflags! {
@@ -71,6 +93,9 @@ bitflags::bitflags! {
         /// Can pause or continue the service execution
         const PAUSE_CONTINUE = Services::SERVICE_PAUSE_CONTINUE;
 
+        /// Can enumerate the services that depend on the service
+        const ENUMERATE_DEPENDENTS = Services::SERVICE_ENUMERATE_DEPENDENTS;
+
         /// Can ask the service to report its status
         const INTERROGATE = Services::SERVICE_INTERROGATE;
 
@@ -99,6 +124,11 @@ bitflags::bitflags! {
 
 /// Enum describing the start options for windows services.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
 #[repr(u32)]
 pub enum ServiceStartType {
     /// Autostart on system startup
@@ -136,6 +166,11 @@ impl ServiceStartType {
 ///
 /// See <https://msdn.microsoft.com/en-us/library/windows/desktop/ms682450(v=vs.85).aspx>
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
 #[repr(u32)]
 pub enum ServiceErrorControl {
     Critical = Services::SERVICE_ERROR_CRITICAL,
@@ -162,9 +197,16 @@ impl ServiceErrorControl {
 
 /// Service dependency descriptor
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
 pub enum ServiceDependency {
-    Service(OsString),
-    Group(OsString),
+    Service(
+        #[cfg_attr(feature = "serde", serde(with = "crate::serde_helpers::os_string"))] OsString,
+    ),
+    Group(#[cfg_attr(feature = "serde", serde(with = "crate::serde_helpers::os_string"))] OsString),
 }
 
 impl ServiceDependency {
@@ -184,22 +226,24 @@ impl ServiceDependency {
 
     pub fn from_system_identifier(identifier: impl AsRef<OsStr>) -> Self {
         let group_prefix: u16 = '+' as u16;
-        let mut iter = identifier.as_ref().encode_wide().peekable();
+        let wide = U16String::from_os_str(identifier.as_ref());
 
-        if iter.peek() == Some(&group_prefix) {
-            let chars: Vec<u16> = iter.skip(1).collect();
-            let group_name = OsString::from_wide(&chars);
-            ServiceDependency::Group(group_name)
-        } else {
-            let chars: Vec<u16> = iter.collect();
-            let service_name = OsString::from_wide(&chars);
-            ServiceDependency::Service(service_name)
+        match wide.as_slice().split_first() {
+            Some((&first, group_name)) if first == group_prefix => {
+                ServiceDependency::Group(U16String::from_vec(group_name).to_os_string())
+            }
+            _ => ServiceDependency::Service(wide.to_os_string()),
         }
     }
 }
 
 /// Enum describing the types of actions that the service control manager can perform.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
 #[repr(i32)]
 pub enum ServiceActionType {
     None = Services::SC_ACTION_NONE,
@@ -228,6 +272,7 @@ impl ServiceActionType {
 ///
 /// See <https://docs.microsoft.com/en-us/windows/win32/api/winsvc/ns-winsvc-sc_action>
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ServiceAction {
     /// The action to be performed.
     pub action_type: ServiceActionType,
@@ -238,9 +283,11 @@ pub struct ServiceAction {
     ///
     /// Converting this to the FFI form will panic if the delay is too large to fit as milliseconds
     /// in a `u32`.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_helpers::duration_ms"))]
     pub delay: Duration,
 }
 
+#[cfg(windows)]
 impl ServiceAction {
     pub fn from_raw(raw: Services::SC_ACTION) -> crate::Result<ServiceAction> {
         Ok(ServiceAction {
@@ -293,6 +340,7 @@ impl ServiceFailureResetPeriod {
 /// Please refer to MSDN for more info:\
 /// <https://docs.microsoft.com/en-us/windows/win32/api/winsvc/ns-winsvc-_service_failure_actionsw>
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ServiceFailureActions {
     /// The time after which to reset the failure count to zero if there are no failures, in
     /// seconds.
@@ -303,6 +351,10 @@ pub struct ServiceFailureActions {
     ///
     /// If this value is `None`, the reboot message is unchanged.
     /// If the value is an empty string, the reboot message is deleted and no message is broadcast.
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::serde_helpers::option_os_string")
+    )]
     pub reboot_msg: Option<OsString>,
 
     /// The command line to execute in response to the `SC_ACTION_RUN_COMMAND` service controller
@@ -310,6 +362,10 @@ pub struct ServiceFailureActions {
     ///
     /// If this value is `None`, the command is unchanged. If the value is an empty string, the
     /// command is deleted and no program is run when the service fails.
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::serde_helpers::option_os_string")
+    )]
     pub command: Option<OsString>,
 
     /// The array of actions to perform.
@@ -317,6 +373,21 @@ pub struct ServiceFailureActions {
     pub actions: Option<Vec<ServiceAction>>,
 }
 
+impl ServiceFailureActions {
+    /// Replace the `None` fields, which leave the current values unchanged when applied, with
+    /// empty values that clear them, so that applying the failure actions replaces them entirely.
+    #[cfg_attr(not(windows), allow(dead_code))]
+    pub(crate) fn into_replacement(self) -> Self {
+        ServiceFailureActions {
+            reset_period: self.reset_period,
+            reboot_msg: Some(self.reboot_msg.unwrap_or_default()),
+            command: Some(self.command.unwrap_or_default()),
+            actions: Some(self.actions.unwrap_or_default()),
+        }
+    }
+}
+
+#[cfg(windows)]
 impl ServiceFailureActions {
     /// Tries to parse a `SERVICE_FAILURE_ACTIONSW` into Rust [`ServiceFailureActions`].
     ///
@@ -334,9 +405,9 @@ impl ServiceFailureActions {
         raw: Services::SERVICE_FAILURE_ACTIONSW,
     ) -> crate::Result<ServiceFailureActions> {
         let reboot_msg = ptr::NonNull::new(raw.lpRebootMsg)
-            .map(|wrapped_ptr| WideCStr::from_ptr_str(wrapped_ptr.as_ptr()).to_os_string());
+            .map(|wrapped_ptr| U16CStr::from_ptr_str(wrapped_ptr.as_ptr()).to_os_string());
         let command = ptr::NonNull::new(raw.lpCommand)
-            .map(|wrapped_ptr| WideCStr::from_ptr_str(wrapped_ptr.as_ptr()).to_os_string());
+            .map(|wrapped_ptr| U16CStr::from_ptr_str(wrapped_ptr.as_ptr()).to_os_string());
         let reset_period = ServiceFailureResetPeriod::from_raw(raw.dwResetPeriod);
 
         let actions: Option<Vec<ServiceAction>> = if raw.lpsaActions.is_null() {
@@ -364,11 +435,14 @@ impl ServiceFailureActions {
 
 /// A struct that describes the service.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ServiceInfo {
     /// Service name
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_helpers::os_string"))]
     pub name: OsString,
 
     /// User-friendly service name
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_helpers::os_string"))]
     pub display_name: OsString,
 
     /// The service type
@@ -381,32 +455,47 @@ pub struct ServiceInfo {
     pub error_control: ServiceErrorControl,
 
     /// Path to the service binary
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_helpers::path_buf"))]
     pub executable_path: PathBuf,
 
     /// Launch arguments passed to `main` when system starts the service.
     /// This is not the same as arguments passed to `service_main`.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "crate::serde_helpers::vec_os_string")
+    )]
     pub launch_arguments: Vec<OsString>,
 
     /// Service dependencies
+    #[cfg_attr(feature = "serde", serde(default))]
     pub dependencies: Vec<ServiceDependency>,
 
     /// Account to use for running the service.
     /// for example: NT Authority\System.
     /// use `None` to run as LocalSystem.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "crate::serde_helpers::option_os_string")
+    )]
     pub account_name: Option<OsString>,
 
     /// Account password.
     /// For system accounts this should normally be `None`.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "crate::serde_helpers::option_os_string")
+    )]
     pub account_password: Option<OsString>,
 }
 
 /// Same as `ServiceInfo` but with fields that are compatible with the Windows API.
+#[cfg_attr(not(windows), allow(dead_code))]
 pub(crate) struct RawServiceInfo {
     /// Service name
-    pub name: WideCString,
+    pub name: U16CString,
 
     /// User-friendly service name
-    pub display_name: WideCString,
+    pub display_name: U16CString,
 
     /// The service type
     pub service_type: u32,
@@ -418,26 +507,26 @@ pub(crate) struct RawServiceInfo {
     pub error_control: u32,
 
     /// Path to the service binary with arguments appended
-    pub launch_command: WideCString,
+    pub launch_command: U16CString,
 
     /// Service dependencies
-    pub dependencies: Option<WideString>,
+    pub dependencies: Option<U16String>,
 
     /// Account to use for running the service.
     /// for example: NT Authority\System.
     /// use `None` to run as LocalSystem.
-    pub account_name: Option<WideCString>,
+    pub account_name: Option<U16CString>,
 
     /// Account password.
     /// For system accounts this should normally be `None`.
-    pub account_password: Option<WideCString>,
+    pub account_password: Option<U16CString>,
 }
 
 impl RawServiceInfo {
     pub fn new(service_info: &ServiceInfo) -> crate::Result<Self> {
-        let service_name = WideCString::from_os_str(&service_info.name)
+        let service_name = U16CString::from_os_str(&service_info.name)
             .map_err(|_| Error::ArgumentHasNulByte("service name"))?;
-        let display_name = WideCString::from_os_str(&service_info.display_name)
+        let display_name = U16CString::from_os_str(&service_info.display_name)
             .map_err(|_| Error::ArgumentHasNulByte("display name"))?;
         let account_name = to_wide(service_info.account_name.as_ref())
             .map_err(|_| Error::ArgumentHasNulByte("account name"))?;
@@ -445,7 +534,7 @@ impl RawServiceInfo {
             .map_err(|_| Error::ArgumentHasNulByte("account password"))?;
 
         // escape executable path and arguments and combine them into a single command
-        let mut launch_command_buffer = WideString::new();
+        let mut launch_command_buffer = U16String::new();
         if service_info
             .service_type
             .intersects(ServiceType::KERNEL_DRIVER | ServiceType::FILE_SYSTEM_DRIVER)
@@ -456,7 +545,7 @@ impl RawServiceInfo {
             }
 
             // also the path must not be quoted even if it contains spaces
-            let executable_path = WideCString::from_os_str(&service_info.executable_path)
+            let executable_path = U16CString::from_os_str(&service_info.executable_path)
                 .map_err(|_| Error::ArgumentHasNulByte("executable path"))?;
             launch_command_buffer.push(executable_path.to_ustring());
         } else {
@@ -474,7 +563,7 @@ impl RawServiceInfo {
         }
 
         // Safety: We are sure launch_command_buffer does not contain nulls
-        let launch_command = unsafe { WideCString::from_ustr_unchecked(launch_command_buffer) };
+        let launch_command = unsafe { U16CString::from_ustr_unchecked(launch_command_buffer) };
 
         let dependency_identifiers: Vec<OsString> = service_info
             .dependencies
@@ -500,6 +589,7 @@ impl RawServiceInfo {
 
 /// A struct that describes the service.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ServiceConfig {
     /// The service type
     pub service_type: ServiceType,
@@ -511,9 +601,14 @@ pub struct ServiceConfig {
     pub error_control: ServiceErrorControl,
 
     /// Path to the service binary
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_helpers::path_buf"))]
     pub executable_path: PathBuf,
 
     /// Path to the service binary
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::serde_helpers::option_os_string")
+    )]
     pub load_order_group: Option<OsString>,
 
     /// A unique tag value for this service in the group specified by the load_order_group
@@ -528,12 +623,112 @@ pub struct ServiceConfig {
     ///
     /// This value can be `None` in certain cases, please refer to MSDN for more info:\
     /// <https://docs.microsoft.com/en-us/windows/desktop/api/winsvc/ns-winsvc-_query_service_configw>
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::serde_helpers::option_os_string")
+    )]
     pub account_name: Option<OsString>,
 
     /// User-friendly service name
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_helpers::os_string"))]
     pub display_name: OsString,
 }
 
+impl ServiceConfig {
+    /// Split [`ServiceConfig::executable_path`], which holds the whole command line of the
+    /// service, into the path to the service binary and the launch arguments. This is the inverse
+    /// of how [`ServiceInfo::executable_path`] and [`ServiceInfo::launch_arguments`] are combined
+    /// when creating the service.
+    ///
+    /// The arguments are parsed the same way as `CommandLineToArgvW` does. An unquoted path that
+    /// contains spaces is ambiguous, the system tries each prefix ending at a space in turn. In
+    /// that case the path is taken to end at the first `.exe` followed by a space, or by the end
+    /// of the command line. The search stops at the first `"` and at the first argument that
+    /// starts with `-` or `/`, and without a `.exe` the path ends at the first space. The command
+    /// line of drivers is a path without arguments.
+    pub fn split_executable_path(&self) -> (PathBuf, Vec<OsString>) {
+        let command_line = self.executable_path.as_os_str();
+
+        if self
+            .service_type
+            .intersects(ServiceType::KERNEL_DRIVER | ServiceType::FILE_SYSTEM_DRIVER)
+        {
+            return (self.executable_path.clone(), Vec::new());
+        }
+
+        if let Some((executable_path, arguments)) = split_unquoted_executable(command_line) {
+            return (
+                PathBuf::from(executable_path),
+                shell_escape::split_arguments(&arguments),
+            );
+        }
+
+        let mut args = shell_escape::split(command_line).into_iter();
+        let executable_path = args.next().map(PathBuf::from).unwrap_or_default();
+        (executable_path, args.collect())
+    }
+}
+
+/// Split an unquoted command line after the first `.exe` that ends the command line or is
+/// followed by a space or tab.
+///
+/// Only the part of the command line given by [`unquoted_executable_len`] is searched, so a
+/// `.exe` in a quoted or option argument does not end the path.
+pub(crate) fn split_unquoted_executable(command_line: &OsStr) -> Option<(OsString, OsString)> {
+    const EXE_SUFFIX: [u16; 4] = ['.' as u16, 'e' as u16, 'x' as u16, 'e' as u16];
+    let to_ascii_lowercase = |c: u16| match u8::try_from(c) {
+        Ok(c) => u16::from(c.to_ascii_lowercase()),
+        Err(_) => c,
+    };
+
+    let wide = U16String::from_os_str(command_line);
+    let wide = wide.as_slice();
+    let len = unquoted_executable_len(wide)?;
+
+    let end = (EXE_SUFFIX.len()..=len).find(|&end| {
+        wide[end - EXE_SUFFIX.len()..end]
+            .iter()
+            .zip(EXE_SUFFIX)
+            .all(|(&c, suffix)| to_ascii_lowercase(c) == suffix)
+            && wide.get(end).map_or(true, is_separator)
+    })?;
+
+    Some((
+        U16String::from_vec(&wide[..end]).to_os_string(),
+        U16String::from_vec(&wide[end..]).to_os_string(),
+    ))
+}
+
+/// Returns the length of the part of an unquoted command line that the executable path can span,
+/// or `None` if the command line is quoted.
+///
+/// The path ends before the first `"` and before the first argument that starts with `-` or `/`,
+/// and the spaces and tabs before them are not part of it.
+pub(crate) fn unquoted_executable_len(wide: &[u16]) -> Option<usize> {
+    const QUOTE: u16 = '"' as u16;
+    let is_option_start = |c: u16| c == '-' as u16 || c == '/' as u16;
+
+    if wide.first() == Some(&QUOTE) {
+        return None;
+    }
+
+    let end = (0..wide.len())
+        .find(|&i| {
+            wide[i] == QUOTE || (i > 0 && is_separator(&wide[i - 1]) && is_option_start(wide[i]))
+        })
+        .unwrap_or(wide.len());
+    let len = wide[..end]
+        .iter()
+        .rposition(|c| !is_separator(c))
+        .map_or(0, |i| i + 1);
+    Some(len)
+}
+
+fn is_separator(c: &u16) -> bool {
+    *c == ' ' as u16 || *c == '\t' as u16
+}
+
+#[cfg(windows)]
 impl ServiceConfig {
     /// Tries to parse a `QUERY_SERVICE_CONFIGW` into Rust [`ServiceConfig`].
     ///
@@ -557,7 +752,7 @@ impl ServiceConfig {
             .collect();
 
         let load_order_group = ptr::NonNull::new(raw.lpLoadOrderGroup).and_then(|wrapped_ptr| {
-            let group = WideCStr::from_ptr_str(wrapped_ptr.as_ptr()).to_os_string();
+            let group = U16CStr::from_ptr_str(wrapped_ptr.as_ptr()).to_os_string();
             // Return None for consistency, because lpLoadOrderGroup can be either nul or empty
             // string, which has the same meaning.
             if group.is_empty() {
@@ -568,7 +763,7 @@ impl ServiceConfig {
         });
 
         let account_name = ptr::NonNull::new(raw.lpServiceStartName)
-            .map(|wrapped_ptr| WideCStr::from_ptr_str(wrapped_ptr.as_ptr()).to_os_string());
+            .map(|wrapped_ptr| U16CStr::from_ptr_str(wrapped_ptr.as_ptr()).to_os_string());
 
         Ok(ServiceConfig {
             service_type: ServiceType::from_bits_truncate(raw.dwServiceType),
@@ -577,13 +772,13 @@ impl ServiceConfig {
             error_control: ServiceErrorControl::from_raw(raw.dwErrorControl)
                 .map_err(|e| Error::ParseValue("service error control", e))?,
             executable_path: PathBuf::from(
-                WideCStr::from_ptr_str(raw.lpBinaryPathName).to_os_string(),
+                U16CStr::from_ptr_str(raw.lpBinaryPathName).to_os_string(),
             ),
             load_order_group,
             tag_id: raw.dwTagId,
             dependencies,
             account_name,
-            display_name: WideCStr::from_ptr_str(raw.lpDisplayName).to_os_string(),
+            display_name: U16CStr::from_ptr_str(raw.lpDisplayName).to_os_string(),
         })
     }
 }
@@ -739,6 +934,7 @@ impl BatterySaverState {
 }
 
 // FIXME: Remove this function if microsoft/windows-rs#1798 gets merged and published.
+#[cfg(windows)]
 fn is_equal_guid(a: &GUID, b: &GUID) -> bool {
     a.data1 == b.data1 && a.data2 == b.data2 && a.data3 == b.data3 && a.data4 == b.data4
 }
@@ -752,6 +948,7 @@ pub enum PowerSchemePersonality {
     Automatic,
 }
 
+#[cfg(windows)]
 impl PowerSchemePersonality {
     pub fn from_guid(guid: &GUID) -> Result<PowerSchemePersonality, ParseRawError> {
         match guid {
@@ -834,6 +1031,7 @@ pub enum PowerBroadcastSetting {
     LidSwitchStateChange(LidSwitchStateChange),
 }
 
+#[cfg(windows)]
 impl PowerBroadcastSetting {
     /// Extract PowerBroadcastSetting from `raw`
     ///
@@ -924,6 +1122,7 @@ pub enum PowerEventParam {
     ResumeCritical,
 }
 
+#[cfg(windows)]
 impl PowerEventParam {
     /// Extract PowerEventParam from `event_type` and `event_data`
     ///
@@ -1025,6 +1224,7 @@ pub struct SessionNotification {
     pub session_id: u32,
 }
 
+#[cfg(windows)]
 impl SessionNotification {
     pub fn from_raw(raw: RemoteDesktop::WTSSESSION_NOTIFICATION) -> Self {
         SessionNotification {
@@ -1041,6 +1241,7 @@ pub struct SessionChangeParam {
     pub notification: SessionNotification,
 }
 
+#[cfg(windows)]
 impl SessionChangeParam {
     /// Extract SessionChangeParam from `event_data`
     ///
@@ -1123,6 +1324,7 @@ impl ServiceControl {
     /// Invalid `event_data` pointer may cause undefined behavior in some circumstances.
     /// Please refer to MSDN for more info about the requirements:
     /// <https://docs.microsoft.com/en-us/windows/win32/api/winsvc/nc-winsvc-lphandler_function_ex>
+    #[cfg(windows)]
     pub unsafe fn from_raw(
         raw: u32,
         event_type: u32,
@@ -1180,10 +1382,43 @@ impl ServiceControl {
             ServiceControl::UserEvent(event) => event.to_raw(),
         }
     }
+
+    /// The flag the service has to accept to receive this control.
+    ///
+    /// Returns `None` for [`ServiceControl::Interrogate`] and user defined controls, which are
+    /// always delivered.
+    pub fn accept_flag(&self) -> Option<ServiceControlAccept> {
+        match self {
+            ServiceControl::Continue | ServiceControl::Pause => {
+                Some(ServiceControlAccept::PAUSE_CONTINUE)
+            }
+            ServiceControl::NetBindAdd
+            | ServiceControl::NetBindDisable
+            | ServiceControl::NetBindEnable
+            | ServiceControl::NetBindRemove => Some(ServiceControlAccept::NETBIND_CHANGE),
+            ServiceControl::ParamChange => Some(ServiceControlAccept::PARAM_CHANGE),
+            ServiceControl::Preshutdown => Some(ServiceControlAccept::PRESHUTDOWN),
+            ServiceControl::Shutdown => Some(ServiceControlAccept::SHUTDOWN),
+            ServiceControl::Stop => Some(ServiceControlAccept::STOP),
+            ServiceControl::HardwareProfileChange(_) => {
+                Some(ServiceControlAccept::HARDWARE_PROFILE_CHANGE)
+            }
+            ServiceControl::PowerEvent(_) => Some(ServiceControlAccept::POWER_EVENT),
+            ServiceControl::SessionChange(_) => Some(ServiceControlAccept::SESSION_CHANGE),
+            ServiceControl::TimeChange => Some(ServiceControlAccept::TIME_CHANGE),
+            ServiceControl::TriggerEvent => Some(ServiceControlAccept::TRIGGER_EVENT),
+            ServiceControl::Interrogate | ServiceControl::UserEvent(_) => None,
+        }
+    }
 }
 
 /// Service state returned as a part of [`ServiceStatus`].
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
 #[repr(u32)]
 pub enum ServiceState {
     Stopped = Services::SERVICE_STOPPED,
@@ -1195,6 +1430,7 @@ pub enum ServiceState {
     Paused = Services::SERVICE_PAUSED,
 }
 
+#[cfg(windows)]
 impl ServiceState {
     fn from_raw(raw: u32) -> Result<Self, ParseRawError> {
         match raw {
@@ -1229,6 +1465,11 @@ impl ServiceState {
 /// [`dwWin32ExitCode`]: Services::SERVICE_STATUS::dwWin32ExitCode
 /// [`dwServiceSpecificExitCode`]: Services::SERVICE_STATUS::dwServiceSpecificExitCode
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
 pub enum ServiceExitCode {
     Win32(u32),
     ServiceSpecific(u32),
@@ -1238,6 +1479,7 @@ impl ServiceExitCode {
     /// A `ServiceExitCode` indicating success, no errors.
     pub const NO_ERROR: Self = ServiceExitCode::Win32(NO_ERROR);
 
+    #[cfg(windows)]
     fn copy_to(&self, raw_service_status: &mut Services::SERVICE_STATUS) {
         match *self {
             ServiceExitCode::Win32(win32_error_code) => {
@@ -1258,6 +1500,7 @@ impl Default for ServiceExitCode {
     }
 }
 
+#[cfg(windows)]
 impl<'a> From<&'a Services::SERVICE_STATUS> for ServiceExitCode {
     fn from(service_status: &'a Services::SERVICE_STATUS) -> Self {
         if service_status.dwWin32ExitCode == ERROR_SERVICE_SPECIFIC_ERROR {
@@ -1268,6 +1511,7 @@ impl<'a> From<&'a Services::SERVICE_STATUS> for ServiceExitCode {
     }
 }
 
+#[cfg(windows)]
 impl<'a> From<&'a Services::SERVICE_STATUS_PROCESS> for ServiceExitCode {
     fn from(service_status: &'a Services::SERVICE_STATUS_PROCESS) -> Self {
         if service_status.dwWin32ExitCode == ERROR_SERVICE_SPECIFIC_ERROR {
@@ -1327,6 +1571,223 @@ bitflags::bitflags! {
     }
 }
 
+/// Policy deciding which control events are delivered to a service registered with
+/// [`service_control_handler::register_channel`].
+///
+/// Delivered controls are acknowledged to the system right away, all other controls are reported
+/// as not implemented. [`ServiceControl::Interrogate`] is always acknowledged without being
+/// delivered.
+///
+/// [`service_control_handler::register_channel`]: crate::service_control_handler::register_channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ControlPolicy {
+    accepted: ServiceControlAccept,
+    user_events: bool,
+}
+
+impl ControlPolicy {
+    /// Deliver the controls enabled by the `accepted` flags.
+    pub fn new(accepted: ServiceControlAccept) -> Self {
+        ControlPolicy {
+            accepted,
+            user_events: false,
+        }
+    }
+
+    /// Whether to deliver user defined controls as well.
+    pub fn user_events(mut self, user_events: bool) -> Self {
+        self.user_events = user_events;
+        self
+    }
+
+    /// The flags to report in [`ServiceStatus::controls_accepted`].
+    pub fn accepted(&self) -> ServiceControlAccept {
+        self.accepted
+    }
+
+    /// Whether the control is delivered to the service.
+    pub fn delivers(&self, control: &ServiceControl) -> bool {
+        match control.accept_flag() {
+            Some(flag) => self.accepted.contains(flag),
+            None => matches!(control, ServiceControl::UserEvent(_)) && self.user_events,
+        }
+    }
+}
+
+impl Default for ControlPolicy {
+    /// Deliver [`ServiceControl::Stop`] only.
+    fn default() -> Self {
+        ControlPolicy::new(ServiceControlAccept::STOP)
+    }
+}
+
+bitflags::bitflags! {
+    /// Flags of the reason given for stopping a service with [`Service::stop_with_reason`].
+    #[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Copy)]
+    pub struct ServiceStopReasonFlags: u32 {
+        /// The service is stopped as planned.
+        const PLANNED = Services::SERVICE_STOP_REASON_FLAG_PLANNED;
+
+        /// The service is stopped unexpectedly.
+        const UNPLANNED = Services::SERVICE_STOP_REASON_FLAG_UNPLANNED;
+
+        /// The reason uses a custom major or minor reason code.
+        const CUSTOM = Services::SERVICE_STOP_REASON_FLAG_CUSTOM;
+    }
+}
+
+/// The major reason for stopping a service.
+/// <https://learn.microsoft.com/en-us/windows/win32/api/winsvc/ns-winsvc-service_control_status_reason_paramsw>
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ServiceStopReasonMajor {
+    Other,
+    Hardware,
+    OperatingSystem,
+    Software,
+    Application,
+    None,
+    /// A custom major reason code, from `0x40` to `0xff`. Requires
+    /// [`ServiceStopReasonFlags::CUSTOM`].
+    Custom(u8),
+}
+
+impl ServiceStopReasonMajor {
+    pub fn to_raw(&self) -> u32 {
+        match *self {
+            ServiceStopReasonMajor::Other => Services::SERVICE_STOP_REASON_MAJOR_OTHER,
+            ServiceStopReasonMajor::Hardware => Services::SERVICE_STOP_REASON_MAJOR_HARDWARE,
+            ServiceStopReasonMajor::OperatingSystem => {
+                Services::SERVICE_STOP_REASON_MAJOR_OPERATINGSYSTEM
+            }
+            ServiceStopReasonMajor::Software => Services::SERVICE_STOP_REASON_MAJOR_SOFTWARE,
+            ServiceStopReasonMajor::Application => Services::SERVICE_STOP_REASON_MAJOR_APPLICATION,
+            ServiceStopReasonMajor::None => Services::SERVICE_STOP_REASON_MAJOR_NONE,
+            ServiceStopReasonMajor::Custom(code) => u32::from(code) << 16,
+        }
+    }
+}
+
+/// The minor reason for stopping a service.
+/// <https://learn.microsoft.com/en-us/windows/win32/api/winsvc/ns-winsvc-service_control_status_reason_paramsw>
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ServiceStopReasonMinor {
+    Other,
+    Maintenance,
+    Installation,
+    Upgrade,
+    Reconfig,
+    Hung,
+    Unstable,
+    Disk,
+    NetworkCard,
+    Environment,
+    HardwareDriver,
+    OtherDriver,
+    ServicePack,
+    SoftwareUpdate,
+    SecurityFix,
+    Security,
+    NetworkConnectivity,
+    Wmi,
+    ServicePackUninstall,
+    SoftwareUpdateUninstall,
+    SecurityFixUninstall,
+    Mmc,
+    None,
+    MemoryLimit,
+    /// A custom minor reason code, from `0x100` to `0xffff`. Requires
+    /// [`ServiceStopReasonFlags::CUSTOM`].
+    Custom(u16),
+}
+
+impl ServiceStopReasonMinor {
+    pub fn to_raw(&self) -> u32 {
+        match *self {
+            ServiceStopReasonMinor::Other => Services::SERVICE_STOP_REASON_MINOR_OTHER,
+            ServiceStopReasonMinor::Maintenance => Services::SERVICE_STOP_REASON_MINOR_MAINTENANCE,
+            ServiceStopReasonMinor::Installation => {
+                Services::SERVICE_STOP_REASON_MINOR_INSTALLATION
+            }
+            ServiceStopReasonMinor::Upgrade => Services::SERVICE_STOP_REASON_MINOR_UPGRADE,
+            ServiceStopReasonMinor::Reconfig => Services::SERVICE_STOP_REASON_MINOR_RECONFIG,
+            ServiceStopReasonMinor::Hung => Services::SERVICE_STOP_REASON_MINOR_HUNG,
+            ServiceStopReasonMinor::Unstable => Services::SERVICE_STOP_REASON_MINOR_UNSTABLE,
+            ServiceStopReasonMinor::Disk => Services::SERVICE_STOP_REASON_MINOR_DISK,
+            ServiceStopReasonMinor::NetworkCard => Services::SERVICE_STOP_REASON_MINOR_NETWORKCARD,
+            ServiceStopReasonMinor::Environment => Services::SERVICE_STOP_REASON_MINOR_ENVIRONMENT,
+            ServiceStopReasonMinor::HardwareDriver => {
+                Services::SERVICE_STOP_REASON_MINOR_HARDWARE_DRIVER
+            }
+            ServiceStopReasonMinor::OtherDriver => Services::SERVICE_STOP_REASON_MINOR_OTHERDRIVER,
+            ServiceStopReasonMinor::ServicePack => Services::SERVICE_STOP_REASON_MINOR_SERVICEPACK,
+            ServiceStopReasonMinor::SoftwareUpdate => {
+                Services::SERVICE_STOP_REASON_MINOR_SOFTWARE_UPDATE
+            }
+            ServiceStopReasonMinor::SecurityFix => Services::SERVICE_STOP_REASON_MINOR_SECURITYFIX,
+            ServiceStopReasonMinor::Security => Services::SERVICE_STOP_REASON_MINOR_SECURITY,
+            ServiceStopReasonMinor::NetworkConnectivity => {
+                Services::SERVICE_STOP_REASON_MINOR_NETWORK_CONNECTIVITY
+            }
+            ServiceStopReasonMinor::Wmi => Services::SERVICE_STOP_REASON_MINOR_WMI,
+            ServiceStopReasonMinor::ServicePackUninstall => {
+                Services::SERVICE_STOP_REASON_MINOR_SERVICEPACK_UNINSTALL
+            }
+            ServiceStopReasonMinor::SoftwareUpdateUninstall => {
+                Services::SERVICE_STOP_REASON_MINOR_SOFTWARE_UPDATE_UNINSTALL
+            }
+            ServiceStopReasonMinor::SecurityFixUninstall => {
+                Services::SERVICE_STOP_REASON_MINOR_SECURITYFIX_UNINSTALL
+            }
+            ServiceStopReasonMinor::Mmc => Services::SERVICE_STOP_REASON_MINOR_MMC,
+            ServiceStopReasonMinor::None => Services::SERVICE_STOP_REASON_MINOR_NONE,
+            ServiceStopReasonMinor::MemoryLimit => Services::SERVICE_STOP_REASON_MINOR_MEMOTYLIMIT,
+            ServiceStopReasonMinor::Custom(code) => u32::from(code),
+        }
+    }
+}
+
+/// The `dwReason` code combining the flags with the major and minor reasons. Custom reasons
+/// outside of the ranges reserved for them are rejected.
+#[cfg_attr(not(windows), allow(dead_code))]
+fn stop_reason_code(
+    major: ServiceStopReasonMajor,
+    minor: ServiceStopReasonMinor,
+    flags: ServiceStopReasonFlags,
+) -> crate::Result<u32> {
+    let planned = ServiceStopReasonFlags::PLANNED | ServiceStopReasonFlags::UNPLANNED;
+    if (flags & planned).bits().count_ones() != 1 {
+        return Err(Error::InvalidStopReason(
+            "exactly one of PLANNED or UNPLANNED must be set",
+        ));
+    }
+    let major_custom = matches!(major, ServiceStopReasonMajor::Custom(_));
+    let minor_custom = matches!(minor, ServiceStopReasonMinor::Custom(_));
+    if (major_custom || minor_custom) && !flags.contains(ServiceStopReasonFlags::CUSTOM) {
+        return Err(Error::InvalidStopReason(
+            "custom reasons require the CUSTOM flag",
+        ));
+    }
+    if major_custom
+        && !(Services::SERVICE_STOP_REASON_MAJOR_MIN_CUSTOM
+            ..=Services::SERVICE_STOP_REASON_MAJOR_MAX_CUSTOM)
+            .contains(&major.to_raw())
+    {
+        return Err(Error::InvalidStopReason(
+            "custom major reason outside the custom range",
+        ));
+    }
+    if minor_custom
+        && !(Services::SERVICE_STOP_REASON_MINOR_MIN_CUSTOM
+            ..=Services::SERVICE_STOP_REASON_MINOR_MAX_CUSTOM)
+            .contains(&minor.to_raw())
+    {
+        return Err(Error::InvalidStopReason(
+            "custom minor reason outside the custom range",
+        ));
+    }
+    Ok(flags.bits() | major.to_raw() | minor.to_raw())
+}
+
 /// Service status.
 ///
 /// This struct wraps the lower level [`SERVICE_STATUS`] providing a few convenience types to fill
@@ -1337,6 +1798,7 @@ bitflags::bitflags! {
 ///
 /// [`SERVICE_STATUS`]: Services::SERVICE_STATUS
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ServiceStatus {
     /// Type of service.
     pub service_type: ServiceType,
@@ -1367,6 +1829,7 @@ pub struct ServiceStatus {
     ///
     /// Converting this to the FFI form will panic if the duration is too large to fit as
     /// milliseconds in a `u32`.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_helpers::duration_ms"))]
     pub wait_hint: Duration,
 
     /// Process ID of the service
@@ -1374,6 +1837,7 @@ pub struct ServiceStatus {
     pub process_id: Option<u32>,
 }
 
+#[cfg(windows)]
 impl ServiceStatus {
     pub(crate) fn to_raw(&self) -> Services::SERVICE_STATUS {
         let mut raw_status = unsafe { mem::zeroed::<Services::SERVICE_STATUS>() };
@@ -1413,7 +1877,9 @@ impl ServiceStatus {
     /// # Errors
     ///
     /// Returns an error if the `dwCurrentState` field does not represent a valid [`ServiceState`].
-    fn from_raw_ex(raw: Services::SERVICE_STATUS_PROCESS) -> Result<Self, ParseRawError> {
+    pub(crate) fn from_raw_ex(
+        raw: Services::SERVICE_STATUS_PROCESS,
+    ) -> Result<Self, ParseRawError> {
         let current_state = ServiceState::from_raw(raw.dwCurrentState)?;
         let process_id = match current_state {
             ServiceState::Running => Some(raw.dwProcessId),
@@ -1434,6 +1900,11 @@ impl ServiceStatus {
 /// This controls how the service SID is added to the service process token.
 /// <https://docs.microsoft.com/en-us/windows/win32/api/winsvc/ns-winsvc-service_sid_info>
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
 #[repr(u32)]
 pub enum ServiceSidType {
     None = 0,
@@ -1441,6 +1912,170 @@ pub enum ServiceSidType {
     Unrestricted = 1,
 }
 
+/// The protection level the service process is launched with.
+/// <https://learn.microsoft.com/en-us/windows/win32/api/winsvc/ns-winsvc-service_launch_protected_info>
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+#[repr(u32)]
+pub enum ServiceLaunchProtected {
+    None = Services::SERVICE_LAUNCH_PROTECTED_NONE,
+    Windows = Services::SERVICE_LAUNCH_PROTECTED_WINDOWS,
+    WindowsLight = Services::SERVICE_LAUNCH_PROTECTED_WINDOWS_LIGHT,
+    /// Requires the service binary to be signed with the certificate registered by an early
+    /// launch antimalware driver.
+    AntimalwareLight = Services::SERVICE_LAUNCH_PROTECTED_ANTIMALWARE_LIGHT,
+}
+
+impl ServiceLaunchProtected {
+    pub fn to_raw(&self) -> u32 {
+        *self as u32
+    }
+
+    pub fn from_raw(raw: u32) -> Result<Self, ParseRawError> {
+        match raw {
+            x if x == ServiceLaunchProtected::None.to_raw() => Ok(ServiceLaunchProtected::None),
+            x if x == ServiceLaunchProtected::Windows.to_raw() => {
+                Ok(ServiceLaunchProtected::Windows)
+            }
+            x if x == ServiceLaunchProtected::WindowsLight.to_raw() => {
+                Ok(ServiceLaunchProtected::WindowsLight)
+            }
+            x if x == ServiceLaunchProtected::AntimalwareLight.to_raw() => {
+                Ok(ServiceLaunchProtected::AntimalwareLight)
+            }
+            _ => Err(ParseRawError::InvalidInteger(raw)),
+        }
+    }
+}
+
+macro_rules! privileges {
+    ($($variant:ident => $name:literal,)*) => {
+        /// A privilege required by a service. The privileges not required by the service are
+        /// removed from its process token by the system.
+        /// <https://learn.microsoft.com/en-us/windows/win32/secauthz/privilege-constants>
+        #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+        pub enum Privilege {
+            $(
+                #[doc = concat!("`", $name, "`")]
+                $variant,
+            )*
+            /// A privilege not listed above, by name.
+            Other(OsString),
+        }
+
+        impl Privilege {
+            /// The name of the privilege, such as `SeChangeNotifyPrivilege`.
+            pub fn name(&self) -> &OsStr {
+                match self {
+                    $(Privilege::$variant => OsStr::new($name),)*
+                    Privilege::Other(name) => name,
+                }
+            }
+
+            /// The privilege with the given name. Privilege names are compared ignoring ASCII
+            /// case, as the system does.
+            pub fn from_name(name: impl AsRef<OsStr>) -> Self {
+                let name = name.as_ref();
+                $(
+                    if name.eq_ignore_ascii_case($name) {
+                        return Privilege::$variant;
+                    }
+                )*
+                Privilege::Other(name.to_owned())
+            }
+        }
+    };
+}
+
+privileges! {
+    AssignPrimaryToken => "SeAssignPrimaryTokenPrivilege",
+    Audit => "SeAuditPrivilege",
+    Backup => "SeBackupPrivilege",
+    ChangeNotify => "SeChangeNotifyPrivilege",
+    CreateGlobal => "SeCreateGlobalPrivilege",
+    CreatePagefile => "SeCreatePagefilePrivilege",
+    CreatePermanent => "SeCreatePermanentPrivilege",
+    CreateSymbolicLink => "SeCreateSymbolicLinkPrivilege",
+    CreateToken => "SeCreateTokenPrivilege",
+    Debug => "SeDebugPrivilege",
+    DelegateSessionUserImpersonate => "SeDelegateSessionUserImpersonatePrivilege",
+    EnableDelegation => "SeEnableDelegationPrivilege",
+    Impersonate => "SeImpersonatePrivilege",
+    IncreaseBasePriority => "SeIncreaseBasePriorityPrivilege",
+    IncreaseQuota => "SeIncreaseQuotaPrivilege",
+    IncreaseWorkingSet => "SeIncreaseWorkingSetPrivilege",
+    LoadDriver => "SeLoadDriverPrivilege",
+    LockMemory => "SeLockMemoryPrivilege",
+    MachineAccount => "SeMachineAccountPrivilege",
+    ManageVolume => "SeManageVolumePrivilege",
+    ProfileSingleProcess => "SeProfileSingleProcessPrivilege",
+    Relabel => "SeRelabelPrivilege",
+    RemoteShutdown => "SeRemoteShutdownPrivilege",
+    Restore => "SeRestorePrivilege",
+    Security => "SeSecurityPrivilege",
+    Shutdown => "SeShutdownPrivilege",
+    SyncAgent => "SeSyncAgentPrivilege",
+    SystemEnvironment => "SeSystemEnvironmentPrivilege",
+    SystemProfile => "SeSystemProfilePrivilege",
+    Systemtime => "SeSystemtimePrivilege",
+    TakeOwnership => "SeTakeOwnershipPrivilege",
+    Tcb => "SeTcbPrivilege",
+    TimeZone => "SeTimeZonePrivilege",
+    TrustedCredManAccess => "SeTrustedCredManAccessPrivilege",
+    Undock => "SeUndockPrivilege",
+}
+
+/// The service SID type and the privileges a service runs with, applied together by
+/// [`Service::set_least_privilege_profile`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LeastPrivilegeProfile {
+    pub sid_type: ServiceSidType,
+    /// The privileges kept in the process token. An empty list keeps all the privileges of the
+    /// service account.
+    pub required_privileges: Vec<Privilege>,
+}
+
+impl LeastPrivilegeProfile {
+    /// A profile with an unrestricted service SID, which lets the service be granted access to
+    /// resources by its own SID, and the given privileges.
+    pub fn new(required_privileges: impl IntoIterator<Item = Privilege>) -> Self {
+        LeastPrivilegeProfile {
+            sid_type: ServiceSidType::Unrestricted,
+            required_privileges: required_privileges.into_iter().collect(),
+        }
+    }
+
+    pub fn sid_type(mut self, sid_type: ServiceSidType) -> Self {
+        self.sid_type = sid_type;
+        self
+    }
+}
+
+/// The optional configuration parameters of a service, as returned by
+/// [`Service::query_extended_config`] and applied by [`Service::apply_extended_config`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceExtendedConfig {
+    /// The service description, `None` if the service has no description.
+    pub description: Option<OsString>,
+    pub delayed_auto_start: bool,
+    pub preshutdown_timeout: Duration,
+    pub failure_actions: ServiceFailureActions,
+    pub failure_actions_on_non_crash_failures: bool,
+    pub sid_type: ServiceSidType,
+    pub required_privileges: Vec<Privilege>,
+    pub triggers: Vec<ServiceTrigger>,
+    /// The protection level the service process is launched with, `None` if the system does not
+    /// support launch protection.
+    pub launch_protected: Option<ServiceLaunchProtected>,
+    /// The NUMA node the service process should run on, `None` if no node is preferred or the
+    /// system does not support preferred nodes.
+    pub preferred_node: Option<u16>,
+}
+
 pub enum Trustee {
     CurrentUser,
     Name(String),
@@ -1451,13 +2086,15 @@ pub enum Trustee {
 /// The instances of the [`Service`] can be obtained via [`ServiceManager`].
 ///
 /// [`ServiceManager`]: super::service_manager::ServiceManager
+#[cfg(windows)]
 pub struct Service {
     service_handle: ScHandle,
-    name: WideCString,
+    name: U16CString,
 }
 
+#[cfg(windows)]
 impl Service {
-    pub(crate) fn new(service_handle: ScHandle, name: WideCString) -> Self {
+    pub(crate) fn new(service_handle: ScHandle, name: U16CString) -> Self {
         Service {
             service_handle,
             name,
@@ -1489,9 +2126,9 @@ impl Service {
         let wide_service_arguments = service_arguments
             .iter()
             .map(|s| {
-                WideCString::from_os_str(s).map_err(|_| Error::ArgumentHasNulByte("start argument"))
+                U16CString::from_os_str(s).map_err(|_| Error::ArgumentHasNulByte("start argument"))
             })
-            .collect::<crate::Result<Vec<WideCString>>>()?;
+            .collect::<crate::Result<Vec<U16CString>>>()?;
 
         let raw_service_arguments: Vec<*const u16> = wide_service_arguments
             .iter()
@@ -1518,6 +2155,75 @@ impl Service {
         self.send_control_command(ServiceControl::Stop)
     }
 
+    /// Stop the service, giving the reason recorded by the system in the event log.
+    ///
+    /// Exactly one of [`ServiceStopReasonFlags::PLANNED`] or [`ServiceStopReasonFlags::UNPLANNED`]
+    /// must be set, along with [`ServiceStopReasonFlags::CUSTOM`] when a custom major or minor
+    /// reason is given. The returned status includes the process id of the service while it is
+    /// stopping.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidStopReason`] if the flags do not match the above, or if a custom
+    /// major or minor reason is outside the range reserved for custom reasons.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use windows_service::service::{
+    ///     ServiceAccess, ServiceStopReasonFlags, ServiceStopReasonMajor, ServiceStopReasonMinor,
+    /// };
+    /// use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+    ///
+    /// # fn main() -> windows_service::Result<()> {
+    /// let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)?;
+    /// let my_service = manager.open_service("my_service", ServiceAccess::STOP)?;
+    /// my_service.stop_with_reason(
+    ///     ServiceStopReasonMajor::Application,
+    ///     ServiceStopReasonMinor::Upgrade,
+    ///     ServiceStopReasonFlags::PLANNED,
+    ///     Some("Deploying version 2.1"),
+    /// )?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn stop_with_reason(
+        &self,
+        major: ServiceStopReasonMajor,
+        minor: ServiceStopReasonMinor,
+        flags: ServiceStopReasonFlags,
+        comment: Option<impl AsRef<OsStr>>,
+    ) -> crate::Result<ServiceStatus> {
+        let mut comment =
+            to_wide_slice(comment).map_err(|_| Error::ArgumentHasNulByte("stop reason comment"))?;
+        let mut params =
+            unsafe { mem::zeroed::<Services::SERVICE_CONTROL_STATUS_REASON_PARAMSW>() };
+        params.dwReason = stop_reason_code(major, minor, flags)?;
+        params.pszComment = comment
+            .as_mut()
+            .map_or(ptr::null_mut(), |comment| comment.as_mut_ptr());
+
+        let success = unsafe {
+            Services::ControlServiceExW(
+                self.service_handle.raw_handle(),
+                Services::SERVICE_CONTROL_STOP,
+                Services::SERVICE_CONTROL_STATUS_REASON_INFO,
+                &mut params as *mut _ as *mut c_void,
+            )
+        };
+
+        if success == 0 {
+            return Err(Error::Winapi(io::Error::last_os_error()));
+        }
+        let mut status = ServiceStatus::from_raw_ex(params.ServiceStatus)
+            .map_err(|e| Error::ParseValue("service status", e))?;
+        // The process keeps running until the service reports that it stopped.
+        if params.ServiceStatus.dwProcessId != 0 {
+            status.process_id = Some(params.ServiceStatus.dwProcessId);
+        }
+        Ok(status)
+    }
+
     /// Pause the service.
     ///
     /// # Example
@@ -1608,6 +2314,84 @@ impl Service {
         }
     }
 
+    /// Get the services that depend on this service, directly or indirectly, and are in the
+    /// given state.
+    ///
+    /// The services are listed in the reverse order of their start order, so they can be stopped
+    /// in the order returned.
+    ///
+    /// Required permission: [`ServiceAccess::ENUMERATE_DEPENDENTS`].
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use windows_service::service::ServiceAccess;
+    /// use windows_service::service_manager::{
+    ///     ServiceActiveState, ServiceManager, ServiceManagerAccess,
+    /// };
+    ///
+    /// # fn main() -> windows_service::Result<()> {
+    /// let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)?;
+    /// let my_service = manager.open_service("my_service", ServiceAccess::ENUMERATE_DEPENDENTS)?;
+    /// for dependent in my_service.dependent_services(ServiceActiveState::ACTIVE)? {
+    ///     println!("{} depends on my_service", dependent.name);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn dependent_services(
+        &self,
+        service_state: ServiceActiveState,
+    ) -> crate::Result<Vec<ServiceEntry>> {
+        let mut bytes_needed: u32 = 0;
+        let mut num_services: u32 = 0;
+
+        // Without dependents the call succeeds with an empty buffer, otherwise it fails with
+        // ERROR_MORE_DATA and tells the size of the buffer needed for the entries and the strings
+        // they point to.
+        let success = unsafe {
+            Services::EnumDependentServicesW(
+                self.service_handle.raw_handle(),
+                service_state.bits(),
+                ptr::null_mut(),
+                0,
+                &mut bytes_needed,
+                &mut num_services,
+            )
+        };
+        if success != 0 {
+            return Ok(Vec::new());
+        }
+        let error = io::Error::last_os_error();
+        if error.raw_os_error() != Some(ERROR_MORE_DATA as i32) {
+            return Err(Error::Winapi(error));
+        }
+
+        let entry_size = mem::size_of::<Services::ENUM_SERVICE_STATUSW>();
+        let mut buffer = vec![
+            unsafe { mem::zeroed::<Services::ENUM_SERVICE_STATUSW>() };
+            (bytes_needed as usize + entry_size - 1) / entry_size
+        ];
+        let success = unsafe {
+            Services::EnumDependentServicesW(
+                self.service_handle.raw_handle(),
+                service_state.bits(),
+                buffer.as_mut_ptr(),
+                (buffer.len() * entry_size) as u32,
+                &mut bytes_needed,
+                &mut num_services,
+            )
+        };
+        if success == 0 {
+            return Err(Error::Winapi(io::Error::last_os_error()));
+        }
+
+        buffer[..num_services as usize]
+            .iter()
+            .map(|raw| ServiceEntry::from_raw(*raw))
+            .collect()
+    }
+
     pub fn grant_user_access(
         &self,
         trustee: Trustee,
@@ -1641,7 +2425,7 @@ impl Service {
                     ptstrName: ptr::null_mut(),
                 },
             };
-            let user = WideCString::from_os_str(match trustee {
+            let user = U16CString::from_os_str(match trustee {
                 Trustee::CurrentUser => "CURRENT_USER".to_owned(),
                 Trustee::Name(name) => name,
             })
@@ -1679,7 +2463,11 @@ impl Service {
     }
 
     /// Update the service config.
-    /// Caveat: You cannot reset the account name/password by passing NULL.
+    /// Caveat: You cannot reset the account name/password by passing NULL. Pass `LocalSystem` as
+    /// the account name to run the service as `LocalSystem` again.
+    ///
+    /// Empty dependencies are passed as NULL, leaving the dependencies unchanged. Use
+    /// [`Service::set_dependencies`] to remove all dependencies.
     ///
     /// This implementation does not currently expose the full flexibility of the
     /// `ChangeServiceConfigW` API. When calling the API it's possible to pass NULL in place of
@@ -1720,6 +2508,45 @@ impl Service {
         }
     }
 
+    /// Replace the dependencies of the service, leaving the rest of the config unchanged. Pass an
+    /// empty slice to remove all dependencies.
+    ///
+    /// Required permission: [`ServiceAccess::CHANGE_CONFIG`].
+    pub fn set_dependencies(&self, dependencies: &[ServiceDependency]) -> crate::Result<()> {
+        let dependency_identifiers: Vec<OsString> = dependencies
+            .iter()
+            .map(|dependency| dependency.to_system_identifier())
+            .collect();
+        let joined_dependencies = double_nul_terminated::from_slice(&dependency_identifiers)
+            .map_err(|_| Error::ArgumentHasNulByte("dependency"))?;
+        // NULL would leave the dependencies unchanged, so pass an empty list instead.
+        let no_dependencies = [0u16; 2];
+
+        let success = unsafe {
+            Services::ChangeServiceConfigW(
+                self.service_handle.raw_handle(),
+                Services::SERVICE_NO_CHANGE,
+                Services::SERVICE_NO_CHANGE,
+                Services::SERVICE_NO_CHANGE,
+                ptr::null(),     // executable path
+                ptr::null(),     // load ordering group
+                ptr::null_mut(), // tag id within the load ordering group
+                joined_dependencies
+                    .as_ref()
+                    .map_or(no_dependencies.as_ptr(), |s| s.as_ptr()),
+                ptr::null(), // account name
+                ptr::null(), // account password
+                ptr::null(), // display name
+            )
+        };
+
+        if success == 0 {
+            Err(Error::Winapi(io::Error::last_os_error()))
+        } else {
+            Ok(())
+        }
+    }
+
     /// Configure failure actions to run when the service terminates before reporting the
     /// [`ServiceState::Stopped`] back to the system or if it exits with non-zero
     /// [`ServiceExitCode`].
@@ -1789,6 +2616,70 @@ impl Service {
         }
     }
 
+    /// Set the privileges kept in the process token of the service, removing all the others when
+    /// the service starts. Pass an empty slice to keep all the privileges of the service account.
+    ///
+    /// Required permission: [`ServiceAccess::CHANGE_CONFIG`].
+    pub fn set_required_privileges(&self, privileges: &[Privilege]) -> crate::Result<()> {
+        let names: Vec<&OsStr> = privileges.iter().map(Privilege::name).collect();
+        let mut joined_names = double_nul_terminated::from_slice(&names)
+            .map_err(|_| Error::ArgumentHasNulByte("privilege"))?
+            .unwrap_or_else(|| U16String::from_vec([0, 0]));
+        let mut required_privileges = Services::SERVICE_REQUIRED_PRIVILEGES_INFOW {
+            pmszRequiredPrivileges: joined_names.as_mut_ptr(),
+        };
+
+        unsafe {
+            self.change_config2(
+                Services::SERVICE_CONFIG_REQUIRED_PRIVILEGES_INFO,
+                &mut required_privileges,
+            )
+            .map_err(Error::Winapi)
+        }
+    }
+
+    /// Query the privileges kept in the process token of the service. See
+    /// [`Service::set_required_privileges`].
+    ///
+    /// Required permission: [`ServiceAccess::QUERY_CONFIG`].
+    pub fn get_required_privileges(&self) -> crate::Result<Vec<Privilege>> {
+        unsafe {
+            let data = self
+                .query_config2_buffer(Services::SERVICE_CONFIG_REQUIRED_PRIVILEGES_INFO)
+                .map_err(Error::Winapi)?;
+            let required_privileges =
+                *(data.as_ptr() as *const Services::SERVICE_REQUIRED_PRIVILEGES_INFOW);
+
+            Ok(
+                double_nul_terminated::parse_str_ptr(required_privileges.pmszRequiredPrivileges)
+                    .iter()
+                    .map(Privilege::from_name)
+                    .collect(),
+            )
+        }
+    }
+
+    /// Set the service SID type and the required privileges of the service.
+    ///
+    /// Required permission: [`ServiceAccess::CHANGE_CONFIG`].
+    pub fn set_least_privilege_profile(
+        &self,
+        profile: &LeastPrivilegeProfile,
+    ) -> crate::Result<()> {
+        self.set_config_service_sid_info(profile.sid_type)?;
+        self.set_required_privileges(&profile.required_privileges)
+    }
+
+    /// Query the service SID type and the required privileges of the service.
+    ///
+    /// Required permission: [`ServiceAccess::QUERY_CONFIG`].
+    pub fn get_least_privilege_profile(&self) -> crate::Result<LeastPrivilegeProfile> {
+        Ok(LeastPrivilegeProfile {
+            sid_type: self.get_config_service_sid_info()?,
+            required_privileges: self.get_required_privileges()?,
+        })
+    }
+
     /// Query the configured failure actions for the service.
     pub fn get_failure_actions(&self) -> crate::Result<ServiceFailureActions> {
         unsafe {
@@ -1888,7 +2779,7 @@ impl Service {
     ///
     /// Required permission: [`ServiceAccess::CHANGE_CONFIG`].
     pub fn set_description(&self, description: impl AsRef<OsStr>) -> crate::Result<()> {
-        let wide_str = WideCString::from_os_str(description)
+        let wide_str = U16CString::from_os_str(description)
             .map_err(|_| Error::ArgumentHasNulByte("service description"))?;
         let mut service_description = Services::SERVICE_DESCRIPTIONW {
             lpDescription: wide_str.as_ptr() as *mut _,
@@ -1903,11 +2794,42 @@ impl Service {
         }
     }
 
-    /// Set if an auto-start service should be delayed.
+    /// Query the service description.
     ///
-    /// If true, the service is started after other auto-start services are started plus a short delay.
-    /// Otherwise, the service is started during system boot. The default is false. This setting is
-    /// ignored unless the service is an auto-start service.
+    /// Returns `None` if the service has no description.
+    ///
+    /// Required permission: [`ServiceAccess::QUERY_CONFIG`].
+    pub fn get_description(&self) -> crate::Result<Option<OsString>> {
+        let mut data = vec![0u8; MAX_QUERY_BUFFER_SIZE];
+
+        unsafe {
+            let service_description: Services::SERVICE_DESCRIPTIONW = self
+                .query_config2(Services::SERVICE_CONFIG_DESCRIPTION, &mut data)
+                .map_err(Error::Winapi)?;
+
+            Ok(ptr::NonNull::new(service_description.lpDescription)
+                .map(|wrapped_ptr| U16CStr::from_ptr_str(wrapped_ptr.as_ptr()).to_os_string()))
+        }
+    }
+
+    /// Query if an auto-start service is delayed. See [`Service::set_delayed_auto_start`].
+    ///
+    /// Required permission: [`ServiceAccess::QUERY_CONFIG`].
+    pub fn get_delayed_auto_start(&self) -> crate::Result<bool> {
+        let mut data = vec![0u8; mem::size_of::<Services::SERVICE_DELAYED_AUTO_START_INFO>()];
+
+        let delayed: Services::SERVICE_DELAYED_AUTO_START_INFO = unsafe {
+            self.query_config2(Services::SERVICE_CONFIG_DELAYED_AUTO_START_INFO, &mut data)
+                .map_err(Error::Winapi)?
+        };
+        Ok(delayed.fDelayedAutostart != 0)
+    }
+
+    /// Set if an auto-start service should be delayed.
+    ///
+    /// If true, the service is started after other auto-start services are started plus a short delay.
+    /// Otherwise, the service is started during system boot. The default is false. This setting is
+    /// ignored unless the service is an auto-start service.
     ///
     /// Required permission: [`ServiceAccess::CHANGE_CONFIG`].
     pub fn set_delayed_auto_start(&self, delayed: bool) -> crate::Result<()> {
@@ -1944,8 +2866,400 @@ impl Service {
         }
     }
 
-    /// Private helper to send the control commands to the system.
-    fn send_control_command(&self, command: ServiceControl) -> crate::Result<ServiceStatus> {
+    /// Query the preshutdown timeout value of the service. See
+    /// [`Service::set_preshutdown_timeout`].
+    ///
+    /// Required permission: [`ServiceAccess::QUERY_CONFIG`].
+    pub fn get_preshutdown_timeout(&self) -> crate::Result<Duration> {
+        let mut data = vec![0u8; mem::size_of::<Services::SERVICE_PRESHUTDOWN_INFO>()];
+
+        let timeout: Services::SERVICE_PRESHUTDOWN_INFO = unsafe {
+            self.query_config2(Services::SERVICE_CONFIG_PRESHUTDOWN_INFO, &mut data)
+                .map_err(Error::Winapi)?
+        };
+        Ok(Duration::from_millis(u64::from(
+            timeout.dwPreshutdownTimeout,
+        )))
+    }
+
+    /// Set the protection level the service process is launched with.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::LaunchProtectedRejected`] if the system does not allow the protection
+    /// level for the service, for instance when the service binary is not signed accordingly.
+    ///
+    /// Required permission: [`ServiceAccess::CHANGE_CONFIG`].
+    pub fn set_launch_protected(
+        &self,
+        launch_protected: ServiceLaunchProtected,
+    ) -> crate::Result<()> {
+        let mut info = Services::SERVICE_LAUNCH_PROTECTED_INFO {
+            dwLaunchProtected: launch_protected.to_raw(),
+        };
+        unsafe {
+            self.change_config2(Services::SERVICE_CONFIG_LAUNCH_PROTECTED, &mut info)
+                .map_err(|e| {
+                    rejected_config_error(e, |e| {
+                        Error::LaunchProtectedRejected(launch_protected, e)
+                    })
+                })
+        }
+    }
+
+    /// Query the protection level the service process is launched with. See
+    /// [`Service::set_launch_protected`].
+    ///
+    /// Required permission: [`ServiceAccess::QUERY_CONFIG`].
+    pub fn get_launch_protected(&self) -> crate::Result<ServiceLaunchProtected> {
+        let mut data = vec![0u8; mem::size_of::<Services::SERVICE_LAUNCH_PROTECTED_INFO>()];
+
+        let info: Services::SERVICE_LAUNCH_PROTECTED_INFO = unsafe {
+            self.query_config2(Services::SERVICE_CONFIG_LAUNCH_PROTECTED, &mut data)
+                .map_err(Error::Winapi)?
+        };
+        ServiceLaunchProtected::from_raw(info.dwLaunchProtected)
+            .map_err(|e| Error::ParseValue("launch protected", e))
+    }
+
+    /// Set the NUMA node the service process should run on, or remove the setting with `None`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::PreferredNodeRejected`] if the node does not exist or the system does not
+    /// support preferred nodes.
+    ///
+    /// Required permission: [`ServiceAccess::CHANGE_CONFIG`].
+    pub fn set_preferred_node(&self, node: Option<u16>) -> crate::Result<()> {
+        let mut info = Services::SERVICE_PREFERRED_NODE_INFO {
+            usPreferredNode: node.unwrap_or(0),
+            fDelete: node.is_none(),
+        };
+        unsafe {
+            self.change_config2(Services::SERVICE_CONFIG_PREFERRED_NODE, &mut info)
+                .map_err(|e| rejected_config_error(e, |e| Error::PreferredNodeRejected(node, e)))
+        }
+    }
+
+    /// Query the NUMA node the service process should run on. See
+    /// [`Service::set_preferred_node`].
+    ///
+    /// Required permission: [`ServiceAccess::QUERY_CONFIG`].
+    pub fn get_preferred_node(&self) -> crate::Result<Option<u16>> {
+        let mut data = vec![0u8; mem::size_of::<Services::SERVICE_PREFERRED_NODE_INFO>()];
+
+        let info: Services::SERVICE_PREFERRED_NODE_INFO = unsafe {
+            self.query_config2(Services::SERVICE_CONFIG_PREFERRED_NODE, &mut data)
+                .map_err(Error::Winapi)?
+        };
+        Ok(Some(info.usPreferredNode).filter(|_| !info.fDelete))
+    }
+
+    /// Query all the optional configuration parameters of the service at once.
+    ///
+    /// Required permission: [`ServiceAccess::QUERY_CONFIG`].
+    pub fn query_extended_config(&self) -> crate::Result<ServiceExtendedConfig> {
+        Ok(ServiceExtendedConfig {
+            description: self.get_description()?,
+            delayed_auto_start: self.get_delayed_auto_start()?,
+            preshutdown_timeout: self.get_preshutdown_timeout()?,
+            failure_actions: self.get_failure_actions()?,
+            failure_actions_on_non_crash_failures: self
+                .get_failure_actions_on_non_crash_failures()?,
+            sid_type: self.get_config_service_sid_info()?,
+            required_privileges: self.get_required_privileges()?,
+            triggers: self.get_triggers()?,
+            launch_protected: unsupported_as_none(self.get_launch_protected())?,
+            preferred_node: unsupported_as_none(self.get_preferred_node())?.flatten(),
+        })
+    }
+
+    /// Apply all the optional configuration parameters of the service, for instance the ones
+    /// queried from another service with [`Service::query_extended_config`].
+    ///
+    /// The failure actions are replaced entirely: the `None` fields of
+    /// [`ServiceExtendedConfig::failure_actions`] clear the current values instead of leaving them
+    /// unchanged.
+    ///
+    /// The launch protection is left unchanged when [`ServiceExtendedConfig::launch_protected`] is
+    /// `None`, and a `None` preferred node is ignored on systems that do not support preferred
+    /// nodes, so a configuration queried on such a system can still be applied.
+    ///
+    /// The parameters are applied one at a time, so the service is left partially configured if
+    /// one of them fails.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::LaunchProtectedRejected`] or [`Error::PreferredNodeRejected`] if the
+    /// system refuses the launch protection or the preferred node of the configuration.
+    ///
+    /// Required permission: [`ServiceAccess::CHANGE_CONFIG`].
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use windows_service::service::ServiceAccess;
+    /// use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+    ///
+    /// # fn main() -> windows_service::Result<()> {
+    /// let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)?;
+    /// let source = manager.open_service("my_service", ServiceAccess::QUERY_CONFIG)?;
+    /// let target = manager.open_service("my_other_service", ServiceAccess::CHANGE_CONFIG)?;
+    ///
+    /// target.apply_extended_config(&source.query_extended_config()?)?;
+    /// #    Ok(())
+    /// # }
+    /// ```
+    pub fn apply_extended_config(&self, config: &ServiceExtendedConfig) -> crate::Result<()> {
+        self.set_description(config.description.as_deref().unwrap_or_default())?;
+        self.set_delayed_auto_start(config.delayed_auto_start)?;
+        self.set_preshutdown_timeout(config.preshutdown_timeout)?;
+        self.update_failure_actions(config.failure_actions.clone().into_replacement())?;
+        self.set_failure_actions_on_non_crash_failures(
+            config.failure_actions_on_non_crash_failures,
+        )?;
+        self.set_config_service_sid_info(config.sid_type)?;
+        self.set_required_privileges(&config.required_privileges)?;
+        self.set_triggers(&config.triggers)?;
+        if let Some(launch_protected) = config.launch_protected {
+            self.set_launch_protected(launch_protected)?;
+        }
+        match self.set_preferred_node(config.preferred_node) {
+            Err(Error::PreferredNodeRejected(None, _)) => Ok(()),
+            result => result,
+        }
+    }
+
+    /// Set the trigger events that start or stop the service, replacing the existing triggers.
+    /// Pass an empty slice to remove all triggers.
+    ///
+    /// Required permission: [`ServiceAccess::CHANGE_CONFIG`].
+    pub fn set_triggers(&self, triggers: &[ServiceTrigger]) -> crate::Result<()> {
+        // The buffer holds pointers to itself, so it is encoded for the address it is stored at.
+        let len = service_trigger::encode_trigger_info(triggers, 0)?.len();
+        let mut buffer = vec![0u64; (len + 7) / 8];
+        let encoded = service_trigger::encode_trigger_info(triggers, buffer.as_ptr() as usize)?;
+
+        unsafe {
+            ptr::copy_nonoverlapping(encoded.as_ptr(), buffer.as_mut_ptr() as *mut u8, len);
+            self.change_config2(Services::SERVICE_CONFIG_TRIGGER_INFO, &mut buffer[0])
+                .map_err(Error::Winapi)
+        }
+    }
+
+    /// Query the trigger events that start or stop the service. See [`Service::set_triggers`].
+    ///
+    /// Required permission: [`ServiceAccess::QUERY_CONFIG`].
+    pub fn get_triggers(&self) -> crate::Result<Vec<ServiceTrigger>> {
+        let data = unsafe {
+            self.query_config2_buffer(Services::SERVICE_CONFIG_TRIGGER_INFO)
+                .map_err(Error::Winapi)?
+        };
+        service_trigger::decode_trigger_info(&data, data.as_ptr() as usize)
+    }
+
+    /// Wait for the service to reach the `target` state, polling its status as recommended by
+    /// the system documentation. Returns the final status.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Wait`] if the service enters another state that is not pending, stops
+    /// advancing its checkpoint within its wait hint, or does not reach the target within
+    /// `timeout`. See [`wait::StateWaiter`] for the details.
+    ///
+    /// Required permission: [`ServiceAccess::QUERY_STATUS`].
+    pub fn wait_for_state(
+        &self,
+        target: ServiceState,
+        timeout: Duration,
+    ) -> crate::Result<ServiceStatus> {
+        wait::wait_for_state(self, target, timeout, &wait::SystemClock)
+    }
+
+    /// Start the service if needed and wait until it is running. A paused service is resumed
+    /// instead. Returns the final status.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Wait`] if the service does not reach the running state within `timeout`.
+    /// When the service failed to start, [`wait::WaitError::exit_code`] returns its exit code.
+    ///
+    /// Required permissions: [`ServiceAccess::START`], [`ServiceAccess::QUERY_STATUS`] and, to
+    /// resume a paused service, [`ServiceAccess::PAUSE_CONTINUE`].
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use std::ffi::OsStr;
+    /// use std::time::Duration;
+    /// use windows_service::service::ServiceAccess;
+    /// use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+    /// use windows_service::Error;
+    ///
+    /// # fn main() -> windows_service::Result<()> {
+    /// let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)?;
+    /// let my_service = manager.open_service(
+    ///     "my_service",
+    ///     ServiceAccess::START | ServiceAccess::QUERY_STATUS,
+    /// )?;
+    /// match my_service.start_and_wait(&[OsStr::new("--verbose")], Duration::from_secs(30)) {
+    ///     Err(Error::Wait(e)) if e.exit_code().is_some() => {
+    ///         println!("my_service failed to start: {:?}", e.exit_code())
+    ///     }
+    ///     result => {
+    ///         result?;
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn start_and_wait<S: AsRef<OsStr>>(
+        &self,
+        service_arguments: &[S],
+        timeout: Duration,
+    ) -> crate::Result<ServiceStatus> {
+        let service_arguments: Vec<&OsStr> = service_arguments.iter().map(|s| s.as_ref()).collect();
+        wait::start_and_wait(self, &service_arguments, timeout, &wait::SystemClock)
+    }
+
+    /// Stop the service if needed and wait until it is stopped. Returns the final status.
+    ///
+    /// Fails if other running services depend on this service, use
+    /// [`Service::stop_dependents`] to stop them first.
+    ///
+    /// Required permissions: [`ServiceAccess::STOP`] and [`ServiceAccess::QUERY_STATUS`].
+    pub fn stop_and_wait(&self, timeout: Duration) -> crate::Result<ServiceStatus> {
+        wait::stop_and_wait(self, timeout, &wait::SystemClock)
+    }
+
+    /// Stop the service if it is running and start it again. Returns the final status.
+    ///
+    /// `timeout` applies to stopping and starting separately. Use
+    /// [`Service::restart_with_dependents`] if other services depend on this service.
+    ///
+    /// Required permissions: [`ServiceAccess::START`], [`ServiceAccess::STOP`] and
+    /// [`ServiceAccess::QUERY_STATUS`].
+    pub fn restart<S: AsRef<OsStr>>(
+        &self,
+        service_arguments: &[S],
+        timeout: Duration,
+    ) -> crate::Result<ServiceStatus> {
+        let service_arguments: Vec<&OsStr> = service_arguments.iter().map(|s| s.as_ref()).collect();
+        wait::restart(self, &service_arguments, timeout, &wait::SystemClock)
+    }
+
+    /// Stop the active services that depend on this service, directly or indirectly, and wait
+    /// until they are stopped. Returns the names of the stopped services in the order they were
+    /// stopped.
+    ///
+    /// The dependents are found and opened through the `manager`. See [`wait::stop_dependents`] for
+    /// the required access rights.
+    pub fn stop_dependents(
+        &self,
+        manager: &ServiceManager,
+        timeout: Duration,
+    ) -> crate::Result<Vec<OsString>> {
+        wait::stop_dependents(
+            manager,
+            &self.name.to_os_string(),
+            timeout,
+            &wait::SystemClock,
+        )
+    }
+
+    /// Restart the service along with the active services that depend on it. The dependents are
+    /// stopped first and started again once this service is running. Returns the final status of
+    /// this service.
+    ///
+    /// The dependents are found and opened through the `manager`. See [`wait::restart_with_dependents`] for
+    /// the required access rights.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use std::time::Duration;
+    /// use windows_service::service::ServiceAccess;
+    /// use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+    ///
+    /// # fn main() -> windows_service::Result<()> {
+    /// let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)?;
+    /// let my_service = manager.open_service(
+    ///     "my_service",
+    ///     ServiceAccess::START | ServiceAccess::STOP | ServiceAccess::QUERY_STATUS,
+    /// )?;
+    /// my_service.restart_with_dependents(&manager, &[] as &[&str], Duration::from_secs(30))?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn restart_with_dependents<S: AsRef<OsStr>>(
+        &self,
+        manager: &ServiceManager,
+        service_arguments: &[S],
+        timeout: Duration,
+    ) -> crate::Result<ServiceStatus> {
+        let service_arguments: Vec<&OsStr> = service_arguments.iter().map(|s| s.as_ref()).collect();
+        wait::restart_with_dependents(
+            manager,
+            &self.name.to_os_string(),
+            self,
+            &service_arguments,
+            timeout,
+            &wait::SystemClock,
+        )
+    }
+
+    /// Turn the service handle into a blocking iterator over notifications about the state
+    /// changes selected by `mask`. See the [`notify`] module for how notifications are delivered.
+    ///
+    /// Required permission: [`ServiceAccess::QUERY_STATUS`].
+    ///
+    /// [`notify`]: crate::notify
+    pub fn into_notifications(self, mask: ServiceNotifyMask) -> ServiceNotifications {
+        ServiceNotifications::new(NotifyHandle::Service(self), mask)
+    }
+
+    /// Turn the service handle into a channel receiving notifications about the state changes
+    /// selected by `mask` from a background thread.
+    ///
+    /// Required permission: [`ServiceAccess::QUERY_STATUS`].
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use std::time::Duration;
+    /// use windows_service::notify::{ServiceNotification, ServiceNotifyMask};
+    /// use windows_service::service::ServiceAccess;
+    /// use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+    ///
+    /// # fn main() -> windows_service::Result<()> {
+    /// let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)?;
+    /// let my_service = manager.open_service("my_service", ServiceAccess::QUERY_STATUS)?;
+    /// let receiver = my_service.into_notification_channel(ServiceNotifyMask::ALL_STATES);
+    /// while let Ok(notification) = receiver.recv_timeout(Duration::from_secs(60)) {
+    ///     if let ServiceNotification::StateChanged(status) = notification? {
+    ///         println!("my_service is {:?}", status.current_state);
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn into_notification_channel(self, mask: ServiceNotifyMask) -> NotificationReceiver {
+        NotificationReceiver::spawn(NotifyHandle::Service(self), mask)
+    }
+
+    /// Turn the service into a stream of the states it enters, starting with the current one.
+    ///
+    /// The service must be opened with [`ServiceAccess::QUERY_STATUS`].
+    #[cfg(feature = "async")]
+    pub fn into_status_stream(self) -> crate::asynchronous::StatusStream {
+        crate::asynchronous::StatusStream::for_service(self)
+    }
+
+    /// Helper to send the control commands to the system.
+    pub(crate) fn send_control_command(
+        &self,
+        command: ServiceControl,
+    ) -> crate::Result<ServiceStatus> {
         let mut raw_status = unsafe { mem::zeroed::<Services::SERVICE_STATUS>() };
         let success = unsafe {
             Services::ControlService(
@@ -1981,6 +3295,36 @@ impl Service {
         }
     }
 
+    /// Private helper to query the optional configuration parameters of windows services that
+    /// may not fit in [`MAX_QUERY_BUFFER_SIZE`], growing the buffer to the size the system asks
+    /// for.
+    unsafe fn query_config2_buffer(&self, kind: u32) -> io::Result<Vec<u8>> {
+        let mut data = vec![0u8; MAX_QUERY_BUFFER_SIZE];
+        loop {
+            let mut bytes_needed: u32 = 0;
+            let success = Services::QueryServiceConfig2W(
+                self.service_handle.raw_handle(),
+                kind,
+                data.as_mut_ptr() as _,
+                data.len() as u32,
+                &mut bytes_needed,
+            );
+            if success != 0 {
+                return Ok(data);
+            }
+
+            let error = io::Error::last_os_error();
+            // The config may grow between the calls, so retry until it fits.
+            if error.raw_os_error() == Some(ERROR_INSUFFICIENT_BUFFER as i32)
+                && bytes_needed as usize > data.len()
+            {
+                data.resize(bytes_needed as usize, 0);
+            } else {
+                return Err(error);
+            }
+        }
+    }
+
     /// Private helper to update the optional configuration parameters of windows services.
     unsafe fn change_config2<T>(&self, kind: u32, data: &mut T) -> io::Result<()> {
         let success = Services::ChangeServiceConfig2W(
@@ -1998,20 +3342,52 @@ impl Service {
 }
 
 /// The maximum size of data buffer used by QueryServiceConfigW and QueryServiceConfig2W is 8K
+#[cfg(windows)]
 const MAX_QUERY_BUFFER_SIZE: usize = 8 * 1024;
 
+#[cfg(windows)]
 fn to_wide_slice(
     s: Option<impl AsRef<OsStr>>,
 ) -> ::std::result::Result<Option<Vec<u16>>, ContainsNul<u16>> {
     if let Some(s) = s {
         Ok(Some(
-            WideCString::from_os_str(s).map(|s| s.into_vec_with_nul())?,
+            U16CString::from_os_str(s).map(|s| s.into_vec_with_nul())?,
         ))
     } else {
         Ok(None)
     }
 }
 
+/// Map the errors returned when the system refuses a configuration value with `rejected`, and
+/// the others to [`Error::Winapi`].
+#[cfg_attr(not(windows), allow(dead_code))]
+fn rejected_config_error(error: io::Error, rejected: impl FnOnce(io::Error) -> Error) -> Error {
+    match error.raw_os_error().map(|code| code as u32) {
+        Some(ERROR_INVALID_PARAMETER | ERROR_NOT_SUPPORTED | ERROR_INVALID_IMAGE_HASH) => {
+            rejected(error)
+        }
+        _ => Error::Winapi(error),
+    }
+}
+
+/// Map the errors returned when the system does not support querying a configuration value to
+/// `None`.
+#[cfg(windows)]
+fn unsupported_as_none<T>(result: crate::Result<T>) -> crate::Result<Option<T>> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(Error::Winapi(e))
+            if matches!(
+                e.raw_os_error().map(|code| code as u32),
+                Some(ERROR_INVALID_LEVEL | ERROR_INVALID_PARAMETER | ERROR_NOT_SUPPORTED)
+            ) =>
+        {
+            Ok(None)
+        }
+        Err(e) => Err(e),
+    }
+}
+
 #[derive(Debug)]
 pub enum ParseRawError {
     InvalidInteger(u32),
@@ -2037,6 +3413,7 @@ impl std::fmt::Display for ParseRawError {
     }
 }
 
+#[cfg(windows)]
 fn string_from_guid(guid: &GUID) -> String {
     format!(
         "{:8X}-{:4X}-{:4X}-{:2X}{:2X}-{:2X}{:2X}{:2X}{:2X}{:2X}{:2X}",
@@ -2056,25 +3433,162 @@ fn string_from_guid(guid: &GUID) -> String {
 
 pub(crate) fn to_wide(
     s: Option<impl AsRef<OsStr>>,
-) -> ::std::result::Result<Option<WideCString>, ContainsNul<u16>> {
+) -> ::std::result::Result<Option<U16CString>, ContainsNul<u16>> {
     if let Some(s) = s {
-        Ok(Some(WideCString::from_os_str(s)?))
+        Ok(Some(U16CString::from_os_str(s)?))
     } else {
         Ok(None)
     }
 }
 
 /// Escapes a given string, but also checks it does not contain any null bytes
-fn escape_wide(s: impl AsRef<OsStr>) -> ::std::result::Result<WideString, ContainsNul<u16>> {
+fn escape_wide(s: impl AsRef<OsStr>) -> ::std::result::Result<U16String, ContainsNul<u16>> {
     let escaped = shell_escape::escape(Cow::Borrowed(s.as_ref()));
-    let wide = WideCString::from_os_str(escaped)?;
+    let wide = U16CString::from_os_str(escaped)?;
     Ok(wide.to_ustring())
 }
 
 #[cfg(test)]
 mod tests {
+    use proptest::prelude::*;
+
     use super::*;
 
+    fn service_config(service_type: ServiceType, executable_path: &str) -> ServiceConfig {
+        ServiceConfig {
+            service_type,
+            start_type: ServiceStartType::OnDemand,
+            error_control: ServiceErrorControl::Normal,
+            executable_path: PathBuf::from(executable_path),
+            load_order_group: None,
+            tag_id: 0,
+            dependencies: vec![],
+            account_name: None,
+            display_name: OsString::from("display name"),
+        }
+    }
+
+    #[test]
+    fn test_split_executable_path() {
+        let split = |executable_path| {
+            service_config(ServiceType::OWN_PROCESS, executable_path).split_executable_path()
+        };
+
+        assert_eq!(
+            split(r#""C:\Program Files\app\service.exe" --name "a b""#),
+            (
+                PathBuf::from("C:\\Program Files\\app\\service.exe"),
+                vec![OsString::from("--name"), OsString::from("a b")]
+            )
+        );
+        assert_eq!(
+            split(r"C:\Windows\system32\svchost.exe -k netsvcs -p"),
+            (
+                PathBuf::from("C:\\Windows\\system32\\svchost.exe"),
+                vec![
+                    OsString::from("-k"),
+                    OsString::from("netsvcs"),
+                    OsString::from("-p")
+                ]
+            )
+        );
+        assert_eq!(
+            split(r"C:\Program Files\My App.EXE\service.exe"),
+            (
+                PathBuf::from("C:\\Program Files\\My App.EXE\\service.exe"),
+                vec![]
+            )
+        );
+        assert_eq!(
+            split(r"C:\Program Files\app\service.exe --arg"),
+            (
+                PathBuf::from("C:\\Program Files\\app\\service.exe"),
+                vec![OsString::from("--arg")]
+            )
+        );
+        assert_eq!(
+            split(r"C:\Program Files\app\service --arg"),
+            (
+                PathBuf::from("C:\\Program"),
+                vec![
+                    OsString::from("Files\\app\\service"),
+                    OsString::from("--arg")
+                ]
+            )
+        );
+        assert_eq!(
+            split(r"C:\tools\svc.bin --run other.exe"),
+            (
+                PathBuf::from("C:\\tools\\svc.bin"),
+                vec![OsString::from("--run"), OsString::from("other.exe")]
+            )
+        );
+        assert_eq!(
+            split(r#"C:\a "x.exe y""#),
+            (PathBuf::from("C:\\a"), vec![OsString::from("x.exe y")])
+        );
+        assert_eq!(split(""), (PathBuf::new(), vec![]));
+
+        assert_eq!(
+            service_config(
+                ServiceType::KERNEL_DRIVER,
+                r"\SystemRoot\System32\drivers\my driver.sys"
+            )
+            .split_executable_path(),
+            (
+                PathBuf::from("\\SystemRoot\\System32\\drivers\\my driver.sys"),
+                vec![]
+            )
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn test_split_executable_path_inverts_service_info(
+            executable_path in r"[a-zA-Z]:(\\[a-zA-Z0-9 ._-]*[a-zA-Z0-9._-])+",
+            launch_arguments in prop::collection::vec("[^\0]*", 0..8),
+        ) {
+            let service_info = ServiceInfo {
+                name: OsString::from("name"),
+                display_name: OsString::from("display name"),
+                service_type: ServiceType::OWN_PROCESS,
+                start_type: ServiceStartType::OnDemand,
+                error_control: ServiceErrorControl::Normal,
+                executable_path: PathBuf::from(executable_path),
+                launch_arguments: launch_arguments.into_iter().map(OsString::from).collect(),
+                dependencies: vec![],
+                account_name: None,
+                account_password: None,
+            };
+            let launch_command = RawServiceInfo::new(&service_info).unwrap().launch_command;
+            let mut config = service_config(ServiceType::OWN_PROCESS, "");
+            config.executable_path = PathBuf::from(launch_command.to_os_string());
+
+            prop_assert_eq!(
+                config.split_executable_path(),
+                (service_info.executable_path, service_info.launch_arguments)
+            );
+        }
+    }
+
+    #[test]
+    fn test_control_policy() {
+        let policy =
+            ControlPolicy::new(ServiceControlAccept::STOP | ServiceControlAccept::PAUSE_CONTINUE);
+        assert!(policy.delivers(&ServiceControl::Stop));
+        assert!(policy.delivers(&ServiceControl::Pause));
+        assert!(policy.delivers(&ServiceControl::Continue));
+        assert!(!policy.delivers(&ServiceControl::Shutdown));
+        assert!(!policy.delivers(&ServiceControl::Interrogate));
+
+        let user_event = ServiceControl::UserEvent(UserEventCode::from_raw(130).unwrap());
+        assert!(!policy.delivers(&user_event));
+        assert!(policy.user_events(true).delivers(&user_event));
+        assert!(!ControlPolicy::default()
+            .user_events(true)
+            .delivers(&ServiceControl::Interrogate));
+    }
+
     #[test]
     fn test_service_group_identifier() {
         let dependency = ServiceDependency::from_system_identifier("+network");
@@ -2092,4 +3606,143 @@ mod tests {
             ServiceDependency::Service(OsString::from("netlogon"))
         );
     }
+
+    #[test]
+    fn test_failure_actions_into_replacement() {
+        let unchanged = ServiceFailureActions {
+            reset_period: ServiceFailureResetPeriod::After(Duration::from_secs(60)),
+            reboot_msg: None,
+            command: None,
+            actions: None,
+        };
+        assert_eq!(
+            unchanged.into_replacement(),
+            ServiceFailureActions {
+                reset_period: ServiceFailureResetPeriod::After(Duration::from_secs(60)),
+                reboot_msg: Some(OsString::new()),
+                command: Some(OsString::new()),
+                actions: Some(vec![]),
+            }
+        );
+
+        let set = ServiceFailureActions {
+            reset_period: ServiceFailureResetPeriod::Never,
+            reboot_msg: Some(OsString::from("rebooting")),
+            command: Some(OsString::from("recover.exe")),
+            actions: Some(vec![ServiceAction {
+                action_type: ServiceActionType::Restart,
+                delay: Duration::from_secs(5),
+            }]),
+        };
+        assert_eq!(set.clone().into_replacement(), set);
+    }
+
+    #[test]
+    fn test_rejected_config_error() {
+        let rejected = |e| Error::PreferredNodeRejected(Some(7), e);
+        assert!(matches!(
+            rejected_config_error(io::Error::from_raw_os_error(87), rejected),
+            Error::PreferredNodeRejected(Some(7), _)
+        ));
+        assert!(matches!(
+            rejected_config_error(io::Error::from_raw_os_error(5), rejected),
+            Error::Winapi(_)
+        ));
+        assert_eq!(
+            ServiceLaunchProtected::from_raw(3).unwrap(),
+            ServiceLaunchProtected::AntimalwareLight
+        );
+        assert!(ServiceLaunchProtected::from_raw(4).is_err());
+    }
+
+    #[test]
+    fn test_stop_reason_code() {
+        assert_eq!(
+            stop_reason_code(
+                ServiceStopReasonMajor::Application,
+                ServiceStopReasonMinor::Upgrade,
+                ServiceStopReasonFlags::PLANNED
+            )
+            .unwrap(),
+            0x4005_0004
+        );
+        assert_eq!(
+            stop_reason_code(
+                ServiceStopReasonMajor::Custom(0x40),
+                ServiceStopReasonMinor::Custom(0x1234),
+                ServiceStopReasonFlags::UNPLANNED | ServiceStopReasonFlags::CUSTOM
+            )
+            .unwrap(),
+            0x3040_1234
+        );
+        assert!(matches!(
+            stop_reason_code(
+                ServiceStopReasonMajor::Custom(0x3f),
+                ServiceStopReasonMinor::Custom(0x100),
+                ServiceStopReasonFlags::PLANNED | ServiceStopReasonFlags::CUSTOM
+            ),
+            Err(Error::InvalidStopReason(
+                "custom major reason outside the custom range"
+            ))
+        ));
+        assert!(matches!(
+            stop_reason_code(
+                ServiceStopReasonMajor::Custom(0xff),
+                ServiceStopReasonMinor::Custom(0xff),
+                ServiceStopReasonFlags::PLANNED | ServiceStopReasonFlags::CUSTOM
+            ),
+            Err(Error::InvalidStopReason(
+                "custom minor reason outside the custom range"
+            ))
+        ));
+        for flags in [
+            ServiceStopReasonFlags::empty(),
+            ServiceStopReasonFlags::PLANNED | ServiceStopReasonFlags::UNPLANNED,
+        ] {
+            assert!(matches!(
+                stop_reason_code(
+                    ServiceStopReasonMajor::Application,
+                    ServiceStopReasonMinor::Upgrade,
+                    flags
+                ),
+                Err(Error::InvalidStopReason(
+                    "exactly one of PLANNED or UNPLANNED must be set"
+                ))
+            ));
+        }
+        assert!(matches!(
+            stop_reason_code(
+                ServiceStopReasonMajor::Application,
+                ServiceStopReasonMinor::Custom(0x100),
+                ServiceStopReasonFlags::PLANNED
+            ),
+            Err(Error::InvalidStopReason(
+                "custom reasons require the CUSTOM flag"
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_privilege_name() {
+        assert_eq!(Privilege::ChangeNotify.name(), "SeChangeNotifyPrivilege");
+        assert_eq!(
+            Privilege::from_name("SeImpersonatePrivilege"),
+            Privilege::Impersonate
+        );
+        assert_eq!(
+            Privilege::from_name("sechangenotifyprivilege"),
+            Privilege::ChangeNotify
+        );
+        assert_eq!(
+            Privilege::from_name("SeFuturePrivilege"),
+            Privilege::Other(OsString::from("SeFuturePrivilege"))
+        );
+
+        let privileges = [Privilege::Tcb, Privilege::Other("SeX".into())];
+        let names: Vec<&OsStr> = privileges.iter().map(Privilege::name).collect();
+        assert_eq!(
+            double_nul_terminated::from_slice(&names).unwrap(),
+            Some(U16String::from_str("SeTcbPrivilege\0SeX\0\0"))
+        );
+    }
 }
@@ -2,40 +2,89 @@ use std::ffi::OsStr;
 use std::io;
 use std::os::raw::c_void;
 use std::os::windows::io::{AsRawHandle, RawHandle};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::time::Duration;
 use widestring::WideCString;
+use windows_sys::core::BOOL;
 use windows_sys::Win32::{
     Foundation::{ERROR_CALL_NOT_IMPLEMENTED, NO_ERROR},
-    System::Services,
+    System::{Console, Services},
 };
 
-use crate::service::{ServiceControl, ServiceStatus};
-use crate::{Error, Result};
+#[cfg(feature = "async")]
+use crate::asynchronous::ControlStream;
+use crate::service::{
+    ControlPolicy, ServiceControl, ServiceControlAccept, ServiceExitCode, ServiceState,
+    ServiceStatus, ServiceType,
+};
+use crate::service_dispatcher::service_name_key;
+use crate::service_handler::{self, ServiceHandler};
+use crate::service_runtime;
+use crate::{lock, Error, Result};
 
 /// A struct that holds a unique token for updating the status of the corresponding service.
 #[derive(Debug, Clone, Copy)]
-pub struct ServiceStatusHandle(Services::SERVICE_STATUS_HANDLE);
+pub struct ServiceStatusHandle(StatusHandle);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StatusHandle {
+    /// Handle obtained from the service control manager.
+    System(Services::SERVICE_STATUS_HANDLE),
+    /// Service running in console mode, identified by its name. The status updates are written to
+    /// stderr.
+    Console(&'static str),
+}
 
 impl ServiceStatusHandle {
     fn from_handle(handle: Services::SERVICE_STATUS_HANDLE) -> Self {
-        ServiceStatusHandle(handle)
+        ServiceStatusHandle(StatusHandle::System(handle))
     }
 
     /// Report the new service status to the system.
+    ///
+    /// When the service runs in console mode, the status is printed to stderr instead.
     pub fn set_service_status(&self, service_status: ServiceStatus) -> crate::Result<()> {
-        let raw_service_status = service_status.to_raw();
-        let result = unsafe { Services::SetServiceStatus(self.0, &raw_service_status) };
-        if result == 0 {
-            Err(Error::Winapi(io::Error::last_os_error()))
-        } else {
-            Ok(())
+        match self.0 {
+            StatusHandle::System(handle) => {
+                let raw_service_status = service_status.to_raw();
+                let result = unsafe { Services::SetServiceStatus(handle, &raw_service_status) };
+                if result == 0 {
+                    Err(Error::Winapi(io::Error::last_os_error()))
+                } else {
+                    update_service_type(self, service_status.service_type);
+                    Ok(())
+                }
+            }
+            StatusHandle::Console(service_name) => {
+                update_service_type(self, service_status.service_type);
+                eprintln!(
+                    "{}: {:?} (controls accepted: {:?}, exit code: {:?}, checkpoint: {}, wait \
+                     hint: {} ms)",
+                    service_name,
+                    service_status.current_state,
+                    service_status.controls_accepted,
+                    service_status.exit_code,
+                    service_status.checkpoint,
+                    service_status.wait_hint.as_millis(),
+                );
+                Ok(())
+            }
         }
     }
 }
 
 impl AsRawHandle for ServiceStatusHandle {
     /// Get access to the raw handle to use in other Windows APIs
+    ///
+    /// Returns a null handle when the service runs in console mode.
     fn as_raw_handle(&self) -> RawHandle {
-        self.0 as _
+        match self.0 {
+            StatusHandle::System(handle) => handle as _,
+            StatusHandle::Console(_) => ptr::null_mut(),
+        }
     }
 }
 
@@ -107,17 +156,27 @@ pub fn register<F>(service_name: impl AsRef<OsStr>, event_handler: F) -> Result<
 where
     F: FnMut(ServiceControl) -> ServiceControlHandlerResult + 'static + Send,
 {
+    let service_name = service_name.as_ref();
+    if CONSOLE_MODE.load(Ordering::SeqCst) {
+        let status_handle = register_console(service_name, Box::new(event_handler));
+        add_registration(service_name_key(service_name), status_handle);
+        return Ok(status_handle);
+    }
+
     // Move closure to heap.
-    let heap_event_handler: Box<F> = Box::new(event_handler);
+    let heap_context = Box::new(HandlerContext {
+        service_name: service_name_key(service_name),
+        event_handler,
+    });
 
-    // Important: leak the Box<F> which will be released in `service_control_handler`.
-    let context: *mut F = Box::into_raw(heap_event_handler);
+    // Important: leak the Box which will be released in `service_control_handler`.
+    let context: *mut HandlerContext<F> = Box::into_raw(heap_context);
 
-    let service_name = WideCString::from_os_str(service_name)
+    let wide_service_name = WideCString::from_os_str(service_name)
         .map_err(|_| Error::ArgumentHasNulByte("service name"))?;
     let status_handle = unsafe {
         Services::RegisterServiceCtrlHandlerExW(
-            service_name.as_ptr(),
+            wide_service_name.as_ptr(),
             Some(service_control_handler::<F>),
             context as *mut c_void,
         )
@@ -125,13 +184,130 @@ where
 
     if status_handle.is_null() {
         // Release the `event_handler` in case of an error.
-        let _: Box<F> = unsafe { Box::from_raw(context) };
+        let _: Box<HandlerContext<F>> = unsafe { Box::from_raw(context) };
         Err(Error::Winapi(io::Error::last_os_error()))
     } else {
-        Ok(ServiceStatusHandle::from_handle(status_handle))
+        let status_handle = ServiceStatusHandle::from_handle(status_handle);
+        add_registration(service_name_key(service_name), status_handle);
+        Ok(status_handle)
     }
 }
 
+/// Register a channel for receiving service events.
+///
+/// Returns [`ServiceStatusHandle`] that can be used to report the service status back to the
+/// system, and the receiver of the control events delivered by the `policy`. The delivered events
+/// are acknowledged to the system right away, all other events are reported as not implemented.
+/// [`ServiceControl::Interrogate`] is handled automatically.
+///
+/// The sender is released together with the event handler after delivering
+/// [`ServiceControl::Stop`], [`ServiceControl::Shutdown`] or [`ServiceControl::Preshutdown`], at
+/// which point the receiver disconnects.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use std::ffi::OsString;
+/// use windows_service::service::{ControlPolicy, ServiceControl, ServiceControlAccept};
+/// use windows_service::service_control_handler;
+///
+/// fn run_service() -> windows_service::Result<()> {
+///     let policy = ControlPolicy::new(ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN);
+///     let (status_handle, controls) =
+///         service_control_handler::register_channel("my_service_name", policy)?;
+///
+///     // Report `policy.accepted()` as the accepted controls and do some work...
+///
+///     for control in controls {
+///         if let ServiceControl::Stop | ServiceControl::Shutdown = control {
+///             break;
+///         }
+///     }
+///     Ok(())
+/// }
+///
+/// # fn main() {}
+/// ```
+pub fn register_channel(
+    service_name: impl AsRef<OsStr>,
+    policy: ControlPolicy,
+) -> Result<(ServiceStatusHandle, mpsc::Receiver<ServiceControl>)> {
+    let (sender, receiver) = mpsc::channel();
+    let status_handle = register(
+        service_name,
+        channel_event_handler(policy, move |control| {
+            // The receiver may be gone already, the event is acknowledged regardless.
+            let _ = sender.send(control);
+        }),
+    )?;
+    Ok((status_handle, receiver))
+}
+
+/// Register a stream for receiving service events.
+///
+/// Same as [`register_channel`], with the control events delivered as an async stream.
+#[cfg(feature = "async")]
+pub fn register_stream(
+    service_name: impl AsRef<OsStr>,
+    policy: ControlPolicy,
+) -> Result<(ServiceStatusHandle, ControlStream)> {
+    let (sender, stream) = ControlStream::new();
+    let status_handle = register(
+        service_name,
+        channel_event_handler(policy, move |control| sender.send(control)),
+    )?;
+    Ok((status_handle, stream))
+}
+
+/// Register a [`ServiceHandler`] for receiving service events.
+///
+/// Returns [`ServiceStatusHandle`] that can be used to report the service status back to the
+/// system. The events accepted by the handler are delivered to its callbacks and acknowledged, all
+/// other events are reported as not implemented. [`ServiceControl::Interrogate`] is handled
+/// automatically.
+///
+/// Same as with [`register`], the handler is dropped after handling [`ServiceControl::Stop`],
+/// [`ServiceControl::Shutdown`] or [`ServiceControl::Preshutdown`].
+pub fn register_handler<H>(
+    service_name: impl AsRef<OsStr>,
+    mut handler: H,
+) -> Result<ServiceStatusHandle>
+where
+    H: ServiceHandler + 'static + Send,
+{
+    register(service_name, move |control| {
+        if service_handler::dispatch(&mut handler, control) {
+            ServiceControlHandlerResult::NoError
+        } else {
+            ServiceControlHandlerResult::NotImplemented
+        }
+    })
+}
+
+/// Event handler delivering the control events allowed by the `policy`.
+fn channel_event_handler(
+    policy: ControlPolicy,
+    deliver: impl Fn(ServiceControl) + Send + 'static,
+) -> impl FnMut(ServiceControl) -> ServiceControlHandlerResult + Send + 'static {
+    move |control| {
+        if control == ServiceControl::Interrogate {
+            ServiceControlHandlerResult::NoError
+        } else if policy.delivers(&control) {
+            deliver(control);
+            ServiceControlHandlerResult::NoError
+        } else {
+            ServiceControlHandlerResult::NotImplemented
+        }
+    }
+}
+
+/// The context passed to the static service control handler.
+struct HandlerContext<F> {
+    /// Lowercase service name, used to report the service stopped if the event handler panics.
+    service_name: String,
+    event_handler: F,
+}
+
 /// Static service control handler
 #[allow(dead_code)]
 extern "system" fn service_control_handler<F>(
@@ -143,8 +319,9 @@ extern "system" fn service_control_handler<F>(
 where
     F: FnMut(ServiceControl) -> ServiceControlHandlerResult,
 {
-    // Important: cast context to &mut F without taking ownership.
-    let event_handler: &mut F = unsafe { &mut *(context as *mut F) };
+    // Important: cast context to &mut HandlerContext<F> without taking ownership.
+    let context = context as *mut HandlerContext<F>;
+    let handler_context: &mut HandlerContext<F> = unsafe { &mut *context };
 
     match unsafe { ServiceControl::from_raw(control, event_type, event_data) } {
         Ok(service_control) => {
@@ -153,12 +330,22 @@ where
                 ServiceControl::Stop | ServiceControl::Shutdown | ServiceControl::Preshutdown,
             );
 
-            let return_code = event_handler(service_control).to_raw();
+            // Important: never unwind into the system. A panicking event handler is not released
+            // since it may be left in an inconsistent state.
+            let event_handler = &mut handler_context.event_handler;
+            let return_code =
+                match panic::catch_unwind(AssertUnwindSafe(|| event_handler(service_control))) {
+                    Ok(result) => result.to_raw(),
+                    Err(_) => {
+                        report_panic(&handler_context.service_name);
+                        return ServiceControlHandlerResult::NotImplemented.to_raw();
+                    }
+                };
 
             // Important: release context upon Stop, Shutdown or Preshutdown at the end of the
             // service lifecycle.
             if need_release {
-                let _: Box<F> = unsafe { Box::from_raw(context as *mut F) };
+                let _: Box<HandlerContext<F>> = unsafe { Box::from_raw(context) };
             }
 
             return_code
@@ -168,3 +355,219 @@ where
         Err(_) => ServiceControlHandlerResult::NotImplemented.to_raw(),
     }
 }
+
+/// A service registered in this process, along with the type it last reported and the exit code
+/// reported if it panics, when it differs from the one set with [`set_panic_exit_code`].
+struct Registration {
+    service_name: String,
+    status_handle: ServiceStatusHandle,
+    service_type: ServiceType,
+    panic_exit_code: Option<u32>,
+}
+
+/// Services registered in this process, keyed by lowercase service name.
+static REGISTRATIONS: Mutex<Vec<Registration>> = Mutex::new(Vec::new());
+
+/// The service specific exit code reported when a service panics.
+static PANIC_EXIT_CODE: AtomicU32 = AtomicU32::new(service_runtime::PANIC_EXIT_CODE);
+
+/// Set the service specific exit code reported when the service entry point or the event handler
+/// of a service in this process panics. Defaults to [`service_runtime::PANIC_EXIT_CODE`].
+///
+/// The panic is caught and the service is reported as [`ServiceState::Stopped`] with
+/// [`ServiceExitCode::ServiceSpecific`] and this code, so that the system can tell the failure
+/// apart from the service vanishing and run its failure actions.
+///
+/// Services run by a [`ServiceRuntime`] report the code set with
+/// [`ServiceRuntime::panic_exit_code`] instead.
+///
+/// [`ServiceState::Stopped`]: crate::service::ServiceState::Stopped
+/// [`ServiceRuntime`]: service_runtime::ServiceRuntime
+/// [`ServiceRuntime::panic_exit_code`]: service_runtime::ServiceRuntime::panic_exit_code
+pub fn set_panic_exit_code(exit_code: u32) {
+    PANIC_EXIT_CODE.store(exit_code, Ordering::SeqCst);
+}
+
+fn add_registration(service_name: String, status_handle: ServiceStatusHandle) {
+    let mut registrations = lock(&REGISTRATIONS);
+    registrations.retain(|registration| registration.service_name != service_name);
+    registrations.push(Registration {
+        service_name,
+        status_handle,
+        service_type: ServiceType::OWN_PROCESS,
+        panic_exit_code: None,
+    });
+}
+
+fn update_service_type(status_handle: &ServiceStatusHandle, service_type: ServiceType) {
+    if let Some(registration) = lock(&REGISTRATIONS)
+        .iter_mut()
+        .find(|registration| registration.status_handle.0 == status_handle.0)
+    {
+        registration.service_type = service_type;
+    }
+}
+
+/// Override the exit code reported if the registered service panics.
+pub(crate) fn set_service_panic_exit_code(status_handle: &ServiceStatusHandle, exit_code: u32) {
+    if let Some(registration) = lock(&REGISTRATIONS)
+        .iter_mut()
+        .find(|registration| registration.status_handle.0 == status_handle.0)
+    {
+        registration.panic_exit_code = Some(exit_code);
+    }
+}
+
+/// Report the service stopped after it panicked. Does nothing if the service has not registered
+/// its event handler yet, since there is no way to report its status.
+pub(crate) fn report_panic(service_name: &str) {
+    let registration = lock(&REGISTRATIONS)
+        .iter()
+        .find(|registration| registration.service_name == service_name)
+        .map(|registration| {
+            (
+                registration.status_handle,
+                registration.service_type,
+                registration.panic_exit_code,
+            )
+        });
+
+    if let Some((status_handle, service_type, panic_exit_code)) = registration {
+        let panic_exit_code =
+            panic_exit_code.unwrap_or_else(|| PANIC_EXIT_CODE.load(Ordering::SeqCst));
+        let _ = status_handle.set_service_status(ServiceStatus {
+            service_type,
+            current_state: ServiceState::Stopped,
+            controls_accepted: ServiceControlAccept::empty(),
+            exit_code: ServiceExitCode::ServiceSpecific(panic_exit_code),
+            checkpoint: 0,
+            wait_hint: Duration::ZERO,
+            process_id: None,
+        });
+    }
+}
+
+/// A boxed event handler of a service running in console mode.
+type BoxedEventHandler = Box<dyn FnMut(ServiceControl) -> ServiceControlHandlerResult + Send>;
+
+/// Whether the services in this process run in console mode, without the service control manager.
+static CONSOLE_MODE: AtomicBool = AtomicBool::new(false);
+
+/// Event handlers registered in console mode, keyed by lowercase service name.
+static CONSOLE_EVENT_HANDLERS: Mutex<Vec<(String, BoxedEventHandler)>> = Mutex::new(Vec::new());
+
+/// Switch the process to console mode.
+///
+/// From now on [`register`] keeps the event handlers in process instead of registering them with
+/// the system, and Ctrl+C or Ctrl+Break pressed in the console are delivered to them as
+/// [`ServiceControl::Stop`].
+pub(crate) fn enable_console_mode() -> Result<()> {
+    if !CONSOLE_MODE.swap(true, Ordering::SeqCst) {
+        let result = unsafe { Console::SetConsoleCtrlHandler(Some(console_ctrl_handler), 1) };
+        if result == 0 {
+            CONSOLE_MODE.store(false, Ordering::SeqCst);
+            return Err(Error::Winapi(io::Error::last_os_error()));
+        }
+    }
+    Ok(())
+}
+
+fn register_console(service_name: &OsStr, event_handler: BoxedEventHandler) -> ServiceStatusHandle {
+    let key = service_name_key(service_name);
+    {
+        let mut event_handlers = lock(&CONSOLE_EVENT_HANDLERS);
+        event_handlers.retain(|(name, _)| *name != key);
+        event_handlers.push((key, event_handler));
+    }
+
+    // The service name has to outlive the copyable status handle. Services are registered once
+    // per run, so leaking the name is fine for this debugging facility.
+    let service_name: &'static str = Box::leak(service_name.to_string_lossy().into_owned().into());
+    ServiceStatusHandle(StatusHandle::Console(service_name))
+}
+
+/// Deliver the control event to all services running in console mode.
+///
+/// Returns `false` if there are no event handlers registered.
+fn dispatch_console_control(service_control: ServiceControl) -> bool {
+    let mut event_handlers = lock(&CONSOLE_EVENT_HANDLERS);
+    if event_handlers.is_empty() {
+        return false;
+    }
+
+    for (_, event_handler) in event_handlers.iter_mut() {
+        event_handler(service_control);
+    }
+
+    // Release the event handlers at the end of the service lifecycle, same as the system does.
+    if matches!(
+        service_control,
+        ServiceControl::Stop | ServiceControl::Shutdown | ServiceControl::Preshutdown,
+    ) {
+        event_handlers.clear();
+    }
+    true
+}
+
+/// Console control handler routing Ctrl+C and Ctrl+Break to the services running in console
+/// mode.
+extern "system" fn console_ctrl_handler(ctrl_type: u32) -> BOOL {
+    match ctrl_type {
+        // Fall back to the default handler that terminates the process once there are no
+        // services left to stop.
+        // Important: never unwind into the system. Let the default handler terminate the
+        // process if an event handler panics.
+        Console::CTRL_C_EVENT | Console::CTRL_BREAK_EVENT => {
+            panic::catch_unwind(|| dispatch_console_control(ServiceControl::Stop)).unwrap_or(false)
+                as BOOL
+        }
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_console_stop_releases_event_handler() {
+        let (tx, rx) = mpsc::channel();
+        let status_handle = register_console(
+            OsStr::new("console_service"),
+            Box::new(move |control| {
+                tx.send(control).unwrap();
+                ServiceControlHandlerResult::NoError
+            }),
+        );
+        assert!(status_handle.as_raw_handle().is_null());
+
+        assert_eq!(console_ctrl_handler(Console::CTRL_C_EVENT), 1);
+        assert_eq!(rx.try_recv().unwrap(), ServiceControl::Stop);
+
+        // No services left to stop, so the default handler should terminate the process.
+        assert_eq!(console_ctrl_handler(Console::CTRL_BREAK_EVENT), 0);
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_channel_event_handler() {
+        let (tx, rx) = mpsc::channel();
+        let mut event_handler = channel_event_handler(ControlPolicy::default(), move |control| {
+            tx.send(control).unwrap()
+        });
+
+        assert_eq!(
+            event_handler(ServiceControl::Interrogate).to_raw(),
+            NO_ERROR
+        );
+        assert_eq!(
+            event_handler(ServiceControl::Pause).to_raw(),
+            ERROR_CALL_NOT_IMPLEMENTED
+        );
+        assert_eq!(event_handler(ServiceControl::Stop).to_raw(), NO_ERROR);
+        assert_eq!(
+            rx.try_iter().collect::<Vec<_>>(),
+            vec![ServiceControl::Stop]
+        );
+    }
+}
@@ -1,10 +1,20 @@
 use std::ffi::{OsStr, OsString};
-use std::{io, ptr};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Mutex;
+use std::time::Duration;
+use std::{io, ptr, thread};
 
 use widestring::{WideCStr, WideCString};
-use windows_sys::Win32::System::Services;
+use windows_sys::Win32::{
+    Foundation::{ERROR_FAILED_SERVICE_CONTROLLER_CONNECT, ERROR_SERVICE_NOT_IN_EXE},
+    System::Services,
+};
 
-use crate::{Error, Result};
+use crate::service::{
+    ServiceControl, ServiceControlAccept, ServiceExitCode, ServiceState, ServiceStatus, ServiceType,
+};
+use crate::service_control_handler::{self, ServiceControlHandlerResult};
+use crate::{lock, Error, Result};
 
 /// A macro to generate an entry point function (aka "service_main") for Windows service.
 ///
@@ -18,6 +28,9 @@ use crate::{Error, Result};
 /// responsibility is to create a `ServiceControlHandler`, start processing control events and
 /// report the service status to the system.
 ///
+/// Several pairs separated by `;` can be passed at once to generate one entry point per service
+/// when hosting multiple services in a single process (see [`ServiceTable`]).
+///
 /// # Example
 ///
 /// ```rust,no_run
@@ -34,24 +47,45 @@ use crate::{Error, Result};
 ///
 /// # fn main() {}
 /// ```
+///
+/// Generating entry points for several services sharing the same process:
+///
+/// ```rust,no_run
+/// #[macro_use]
+/// extern crate windows_service;
+///
+/// use std::ffi::OsString;
+///
+/// define_windows_service!(
+///     ffi_first_service_main, first_service_main;
+///     ffi_second_service_main, second_service_main;
+/// );
+///
+/// fn first_service_main(arguments: Vec<OsString>) {}
+///
+/// fn second_service_main(arguments: Vec<OsString>) {}
+///
+/// # fn main() {}
+/// ```
 #[macro_export]
 macro_rules! define_windows_service {
-    ($function_name:ident, $service_main_handler:ident) => {
-        /// Static callback used by the system to bootstrap the service.
-        /// Do not call it directly.
-        extern "system" fn $function_name(
-            num_service_arguments: u32,
-            service_arguments: *mut *mut u16,
-        ) {
-            let arguments = unsafe {
-                $crate::service_dispatcher::parse_service_arguments(
-                    num_service_arguments,
-                    service_arguments,
-                )
-            };
-
-            $service_main_handler(arguments);
-        }
+    ($($function_name:ident, $service_main_handler:ident);+ $(;)?) => {
+        $(
+            /// Static callback used by the system to bootstrap the service.
+            /// Do not call it directly.
+            extern "system" fn $function_name(
+                num_service_arguments: u32,
+                service_arguments: *mut *mut u16,
+            ) {
+                unsafe {
+                    $crate::service_dispatcher::run_service_main(
+                        num_service_arguments,
+                        service_arguments,
+                        $service_main_handler,
+                    );
+                }
+            }
+        )+
     };
 }
 
@@ -62,7 +96,9 @@ macro_rules! define_windows_service {
 ///
 /// Upon successful initialization, system calls the `service_main` on background thread.
 ///
-/// On failure: immediately returns an error, no threads are spawned.
+/// On failure: immediately returns an error, no threads are spawned. When the program is launched
+/// from a terminal rather than by the system, the error is `ERROR_FAILED_SERVICE_CONTROLLER_CONNECT`,
+/// see [`start_or_run_in_console`] for running the service in the foreground instead.
 ///
 /// # Example
 ///
@@ -91,26 +127,451 @@ pub fn start(
     service_name: impl AsRef<OsStr>,
     service_main: extern "system" fn(u32, *mut *mut u16),
 ) -> Result<()> {
-    let service_name = WideCString::from_os_str(service_name)
-        .map_err(|_| Error::ArgumentHasNulByte("service name"))?;
-    let service_table: &[Services::SERVICE_TABLE_ENTRYW] = &[
-        Services::SERVICE_TABLE_ENTRYW {
-            lpServiceName: service_name.as_ptr() as _,
-            lpServiceProc: Some(service_main),
-        },
-        // the last item has to be { null, null }
-        Services::SERVICE_TABLE_ENTRYW {
-            lpServiceName: ptr::null_mut(),
-            lpServiceProc: None,
-        },
-    ];
-
-    let result = unsafe { Services::StartServiceCtrlDispatcherW(service_table.as_ptr()) };
-    if result == 0 {
-        Err(Error::Winapi(io::Error::last_os_error()))
-    } else {
+    ServiceTable::new()
+        .add_service(service_name, service_main)?
+        .start()
+}
+
+/// Start service control dispatcher, or run the service in console mode when the program was not
+/// started by the service control manager.
+///
+/// This works the same way as [`start`] combined with [`ServiceTable::console_fallback`], see
+/// there for why the fallback is not the default.
+pub fn start_or_run_in_console(
+    service_name: impl AsRef<OsStr>,
+    service_main: extern "system" fn(u32, *mut *mut u16),
+) -> Result<()> {
+    ServiceTable::new()
+        .add_service(service_name, service_main)?
+        .console_fallback(true)
+        .start()
+}
+
+/// Start service control dispatcher with a closure as the service entry point.
+///
+/// This works the same way as [`start`], except that the service entry point does not have to be
+/// generated with [`define_windows_service!`]. The closure can capture any state prepared in
+/// `main`, such as configuration loaded from disk, and is called once on a background thread
+/// when the system starts the service.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use std::ffi::OsString;
+/// use windows_service::service_dispatcher;
+///
+/// fn main() -> windows_service::Result<()> {
+///     let config = String::from("loaded in main");
+///
+///     service_dispatcher::start_with("myservice", move |arguments: Vec<OsString>| {
+///         // The entry point where execution will start on a background thread, with access
+///         // to `config`.
+///     })
+/// }
+/// ```
+pub fn start_with<F>(service_name: impl AsRef<OsStr>, service_main: F) -> Result<()>
+where
+    F: FnOnce(Vec<OsString>) + Send + 'static,
+{
+    ServiceTable::new()
+        .add_service_fn(service_name, service_main)?
+        .start()
+}
+
+/// Start service control dispatcher with a closure as the service entry point, or run the service
+/// in console mode when the program was not started by the service control manager.
+///
+/// This works the same way as [`start_with`] combined with [`ServiceTable::console_fallback`], see
+/// there for why the fallback is not the default.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use std::ffi::OsString;
+/// use windows_service::service_dispatcher;
+///
+/// fn main() -> windows_service::Result<()> {
+///     // Runs in the foreground, stopped with Ctrl+C, when launched from a terminal.
+///     service_dispatcher::start_with_or_run_in_console("myservice", |arguments: Vec<OsString>| {
+///         // The entry point, called by the system or directly in console mode.
+///     })
+/// }
+/// ```
+pub fn start_with_or_run_in_console<F>(
+    service_name: impl AsRef<OsStr>,
+    service_main: F,
+) -> Result<()>
+where
+    F: FnOnce(Vec<OsString>) + Send + 'static,
+{
+    ServiceTable::new()
+        .add_service_fn(service_name, service_main)?
+        .console_fallback(true)
+        .start()
+}
+
+/// A boxed closure used as a service entry point.
+type BoxedServiceMain = Box<dyn FnOnce(Vec<OsString>) + Send + 'static>;
+
+/// Closures registered with [`ServiceTable::add_service_fn`], keyed by lowercase service name.
+///
+/// The system does not pass any user context to the service entry point, so the closures are
+/// stashed here for the time the dispatcher is running and picked up by
+/// [`closure_service_main`] using the service name that is always passed as the first argument.
+static SERVICE_MAINS: Mutex<Vec<(String, BoxedServiceMain)>> = Mutex::new(Vec::new());
+
+fn register_service_main(service_name: String, service_main: BoxedServiceMain) {
+    let mut service_mains = lock(&SERVICE_MAINS);
+    service_mains.retain(|(name, _)| *name != service_name);
+    service_mains.push((service_name, service_main));
+}
+
+fn take_service_main(service_name: &str) -> Option<BoxedServiceMain> {
+    let mut service_mains = lock(&SERVICE_MAINS);
+    let index = service_mains
+        .iter()
+        .position(|(name, _)| name == service_name)?;
+    Some(service_mains.swap_remove(index).1)
+}
+
+/// Normalize the service name for lookups, since the system treats service names
+/// case-insensitively.
+pub(crate) fn service_name_key(service_name: &OsStr) -> String {
+    service_name.to_string_lossy().to_lowercase()
+}
+
+/// Static callback used by the system to bootstrap the services registered with a closure.
+extern "system" fn closure_service_main(
+    num_service_arguments: u32,
+    service_arguments: *mut *mut u16,
+) {
+    unsafe {
+        run_service_main(num_service_arguments, service_arguments, |arguments| {
+            // The first argument is always the name of the service being started.
+            let service_main = arguments
+                .first()
+                .and_then(|service_name| take_service_main(&service_name_key(service_name)));
+
+            match service_main {
+                Some(service_main) => service_main(arguments),
+                None => {
+                    if let Some(service_name) = arguments.first() {
+                        report_service_not_in_exe(service_name);
+                    }
+                }
+            }
+        });
+    }
+}
+
+/// Report the service stopped with `ERROR_SERVICE_NOT_IN_EXE` when there is no closure to run,
+/// so that the system does not wait for the start to time out.
+fn report_service_not_in_exe(service_name: &OsStr) {
+    let event_handler = |control| match control {
+        ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+        _ => ServiceControlHandlerResult::NotImplemented,
+    };
+    if let Ok(status_handle) = service_control_handler::register(service_name, event_handler) {
+        let _ = status_handle.set_service_status(ServiceStatus {
+            // Services dispatched from a table usually share the process, and the system does
+            // not check the type against the configuration.
+            service_type: ServiceType::SHARE_PROCESS,
+            current_state: ServiceState::Stopped,
+            controls_accepted: ServiceControlAccept::empty(),
+            exit_code: ServiceExitCode::Win32(ERROR_SERVICE_NOT_IN_EXE),
+            checkpoint: 0,
+            wait_hint: Duration::ZERO,
+            process_id: None,
+        });
+    }
+}
+
+/// A table of services hosted by the current process.
+///
+/// A process that runs several services of [`ServiceType::SHARE_PROCESS`] type has to register all
+/// of them with a single call to the service control dispatcher. The system then invokes the
+/// entry point associated with the service being started, passing the service name as the first
+/// argument.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// #[macro_use]
+/// extern crate windows_service;
+///
+/// use std::ffi::OsString;
+/// use windows_service::service_dispatcher::ServiceTable;
+///
+/// define_windows_service!(
+///     ffi_first_service_main, first_service_main;
+///     ffi_second_service_main, second_service_main;
+/// );
+///
+/// fn first_service_main(arguments: Vec<OsString>) {}
+///
+/// fn second_service_main(arguments: Vec<OsString>) {}
+///
+/// fn main() -> windows_service::Result<()> {
+///     ServiceTable::new()
+///         .add_service("first_service", ffi_first_service_main)?
+///         .add_service("second_service", ffi_second_service_main)?
+///         .start()
+/// }
+/// ```
+///
+/// [`ServiceType::SHARE_PROCESS`]: crate::service::ServiceType::SHARE_PROCESS
+#[derive(Default)]
+pub struct ServiceTable {
+    entries: Vec<ServiceTableEntry>,
+    console_fallback: bool,
+}
+
+/// A single service registered in [`ServiceTable`].
+struct ServiceTableEntry {
+    name: WideCString,
+    service_main: ServiceMain,
+}
+
+impl ServiceTableEntry {
+    /// Call the service entry point directly, the same way the system would.
+    fn run_in_console(self) {
+        match self.service_main {
+            ServiceMain::Ffi(service_main) => {
+                let mut arguments = [self.name.as_ptr() as *mut u16];
+                service_main(arguments.len() as u32, arguments.as_mut_ptr());
+            }
+            ServiceMain::Closure(Some(service_main)) => {
+                service_main(vec![self.name.to_os_string()]);
+            }
+            ServiceMain::Closure(None) => (),
+        }
+    }
+}
+
+/// The entry point of the service in [`ServiceTable`].
+enum ServiceMain {
+    /// Entry point generated with [`define_windows_service!`].
+    Ffi(extern "system" fn(u32, *mut *mut u16)),
+    /// Closure dispatched through [`closure_service_main`]. It's taken out of the entry once the
+    /// dispatcher is started.
+    Closure(Option<BoxedServiceMain>),
+}
+
+impl ServiceTable {
+    /// Create an empty service table.
+    pub fn new() -> Self {
+        ServiceTable::default()
+    }
+
+    /// Add a service entry point to the table.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the service name contains a nul byte or if a service with the same
+    /// name was already added. Service names are compared case-insensitively, the same way the
+    /// system does.
+    pub fn add_service(
+        self,
+        service_name: impl AsRef<OsStr>,
+        service_main: extern "system" fn(u32, *mut *mut u16),
+    ) -> Result<Self> {
+        self.add_entry(service_name.as_ref(), ServiceMain::Ffi(service_main))
+    }
+
+    /// Add a closure as the service entry point to the table.
+    ///
+    /// The closure is called once on a background thread when the system starts the service. If
+    /// the same service is started again while the process is still running, the entry point is
+    /// not invoked for the second time and the service is reported stopped with
+    /// `ERROR_SERVICE_NOT_IN_EXE`, so the services hosted this way are expected to exit the
+    /// process once stopped.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`ServiceTable::add_service`].
+    pub fn add_service_fn<F>(self, service_name: impl AsRef<OsStr>, service_main: F) -> Result<Self>
+    where
+        F: FnOnce(Vec<OsString>) + Send + 'static,
+    {
+        self.add_entry(
+            service_name.as_ref(),
+            ServiceMain::Closure(Some(Box::new(service_main))),
+        )
+    }
+
+    fn add_entry(mut self, service_name: &OsStr, service_main: ServiceMain) -> Result<Self> {
+        let name = WideCString::from_os_str(service_name)
+            .map_err(|_| Error::ArgumentHasNulByte("service name"))?;
+        if self.contains(service_name) {
+            return Err(Error::DuplicateServiceName(service_name.to_os_string()));
+        }
+        self.entries.push(ServiceTableEntry { name, service_main });
+        Ok(self)
+    }
+
+    /// Returns `true` if the table contains a service with the given name.
+    pub fn contains(&self, service_name: impl AsRef<OsStr>) -> bool {
+        let service_name = service_name_key(service_name.as_ref());
+        self.entries
+            .iter()
+            .any(|entry| service_name_key(&entry.name.to_os_string()) == service_name)
+    }
+
+    /// Returns the number of services in the table.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if the table contains no services.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Fall back to running the services in console mode when the program was not started by
+    /// the service control manager, i.e when it's launched from a terminal.
+    ///
+    /// See [`ServiceTable::run_in_console`] for more info. Disabled by default, so that a service
+    /// binary launched by mistake does not silently run in the foreground, and programs handling
+    /// `ERROR_FAILED_SERVICE_CONTROLLER_CONNECT` themselves, e.g. to install the service when
+    /// launched from a terminal, keep working. [`start_or_run_in_console`] and
+    /// [`start_with_or_run_in_console`] enable it for a single service.
+    pub fn console_fallback(mut self, enabled: bool) -> Self {
+        self.console_fallback = enabled;
+        self
+    }
+
+    /// Start service control dispatcher for all services in the table.
+    ///
+    /// Blocks the current thread execution until all services are stopped. See [`start`] for
+    /// more info.
+    pub fn start(mut self) -> Result<()> {
+        self.register_closures();
+
+        let service_table = self.to_raw();
+        let result = unsafe { Services::StartServiceCtrlDispatcherW(service_table.as_ptr()) };
+        let error = if result == 0 {
+            Some(io::Error::last_os_error())
+        } else {
+            None
+        };
+
+        // Take back the closures of services that were never started.
+        self.reclaim_closures();
+
+        match error {
+            None => Ok(()),
+            Some(error)
+                if self.console_fallback
+                    && error.raw_os_error()
+                        == Some(ERROR_FAILED_SERVICE_CONTROLLER_CONNECT as i32) =>
+            {
+                self.run_in_console()
+            }
+            Some(error) => Err(Error::Winapi(error)),
+        }
+    }
+
+    /// Run all services in the table in the foreground, emulating the service control manager.
+    ///
+    /// This is meant for debugging services from a terminal. Each service entry point is called
+    /// on its own thread with the service name as the only argument. Calls to
+    /// [`service_control_handler::register`] keep the event handler in process, Ctrl+C and
+    /// Ctrl+Break are delivered to it as [`ServiceControl::Stop`], and the statuses reported with
+    /// [`ServiceStatusHandle::set_service_status`] are printed to stderr.
+    ///
+    /// Blocks the current thread execution until all service entry points return.
+    ///
+    /// [`ServiceControl::Stop`]: crate::service::ServiceControl::Stop
+    /// [`ServiceStatusHandle::set_service_status`]: service_control_handler::ServiceStatusHandle::set_service_status
+    pub fn run_in_console(self) -> Result<()> {
+        service_control_handler::enable_console_mode()?;
+
+        let threads = self
+            .entries
+            .into_iter()
+            .map(|entry| {
+                thread::Builder::new()
+                    .name(entry.name.to_string_lossy())
+                    .spawn(move || entry.run_in_console())
+                    .map_err(Error::Winapi)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        for thread in threads {
+            if let Err(panic_payload) = thread.join() {
+                panic::resume_unwind(panic_payload);
+            }
+        }
         Ok(())
     }
+
+    /// Move the closure entry points to the global registry read by [`closure_service_main`].
+    fn register_closures(&mut self) {
+        for entry in &mut self.entries {
+            if let ServiceMain::Closure(ref mut service_main) = entry.service_main {
+                if let Some(service_main) = service_main.take() {
+                    register_service_main(
+                        service_name_key(&entry.name.to_os_string()),
+                        service_main,
+                    );
+                }
+            }
+        }
+    }
+
+    /// Move the closure entry points that were not consumed back from the global registry.
+    fn reclaim_closures(&mut self) {
+        for entry in &mut self.entries {
+            if let ServiceMain::Closure(ref mut service_main) = entry.service_main {
+                *service_main = take_service_main(&service_name_key(&entry.name.to_os_string()));
+            }
+        }
+    }
+
+    /// Build the raw table passed to `StartServiceCtrlDispatcherW`.
+    ///
+    /// The returned entries borrow the service names owned by `self`.
+    fn to_raw(&self) -> Vec<Services::SERVICE_TABLE_ENTRYW> {
+        self.entries
+            .iter()
+            .map(|entry| Services::SERVICE_TABLE_ENTRYW {
+                lpServiceName: entry.name.as_ptr() as _,
+                lpServiceProc: Some(match entry.service_main {
+                    ServiceMain::Ffi(service_main) => service_main,
+                    ServiceMain::Closure(_) => closure_service_main,
+                }),
+            })
+            // the last item has to be { null, null }
+            .chain(std::iter::once(Services::SERVICE_TABLE_ENTRYW {
+                lpServiceName: ptr::null_mut(),
+                lpServiceProc: None,
+            }))
+            .collect()
+    }
+}
+
+/// Parse raw arguments received in `service_main` and call the `service_main` handler with them.
+///
+/// A panic in the handler does not unwind into the system. Instead, the service is reported as
+/// stopped with the exit code set with [`service_control_handler::set_panic_exit_code`], provided
+/// it has registered its event handler.
+///
+/// This is an implementation detail and *should not* be called directly!
+#[doc(hidden)]
+pub unsafe fn run_service_main(
+    argc: u32,
+    argv: *mut *mut u16,
+    service_main: impl FnOnce(Vec<OsString>),
+) {
+    let arguments = parse_service_arguments(argc, argv);
+    // The first argument is always the name of the service being started.
+    let service_name = arguments.first().map(|name| service_name_key(name));
+
+    if panic::catch_unwind(AssertUnwindSafe(|| service_main(arguments))).is_err() {
+        if let Some(service_name) = service_name {
+            service_control_handler::report_panic(&service_name);
+        }
+    }
 }
 
 /// Parse raw arguments received in `service_main` into `Vec<OsString>`.
@@ -125,3 +586,155 @@ pub unsafe fn parse_service_arguments(argc: u32, argv: *mut *mut u16) -> Vec<OsS
         })
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    thread_local! {
+        static CALLS: RefCell<Vec<(&'static str, Vec<OsString>)>> = const { RefCell::new(Vec::new()) };
+    }
+
+    define_windows_service!(
+        ffi_first_service_main, first_service_main;
+        ffi_second_service_main, second_service_main;
+    );
+
+    fn first_service_main(arguments: Vec<OsString>) {
+        CALLS.with(|calls| calls.borrow_mut().push(("first", arguments)));
+    }
+
+    fn second_service_main(arguments: Vec<OsString>) {
+        CALLS.with(|calls| calls.borrow_mut().push(("second", arguments)));
+    }
+
+    fn invoke(entry: &Services::SERVICE_TABLE_ENTRYW, arguments: &[&str]) {
+        let wide_arguments: Vec<WideCString> = arguments
+            .iter()
+            .map(|s| WideCString::from_str(s).unwrap())
+            .collect();
+        let mut raw_arguments: Vec<*mut u16> = wide_arguments
+            .iter()
+            .map(|s| s.as_ptr() as *mut u16)
+            .collect();
+        let service_main = entry.lpServiceProc.unwrap();
+        unsafe { service_main(raw_arguments.len() as u32, raw_arguments.as_mut_ptr()) };
+    }
+
+    #[test]
+    fn test_raw_table_is_nul_terminated() {
+        let table = ServiceTable::new()
+            .add_service("first", ffi_first_service_main)
+            .unwrap()
+            .add_service("second", ffi_second_service_main)
+            .unwrap();
+        let raw_table = table.to_raw();
+
+        assert_eq!(raw_table.len(), 3);
+        let names: Vec<String> = raw_table[..2]
+            .iter()
+            .map(|entry| unsafe { WideCStr::from_ptr_str(entry.lpServiceName) }.to_string_lossy())
+            .collect();
+        assert_eq!(names, ["first", "second"]);
+        assert!(raw_table[2].lpServiceName.is_null());
+        assert!(raw_table[2].lpServiceProc.is_none());
+    }
+
+    #[test]
+    fn test_closure_entry_receives_arguments() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut table = ServiceTable::new()
+            .add_service_fn("closure_service", move |arguments| {
+                tx.send(arguments).unwrap();
+            })
+            .unwrap();
+        table.register_closures();
+        let raw_table = table.to_raw();
+
+        invoke(&raw_table[0], &["Closure_Service", "--verbose"]);
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            vec![
+                OsString::from("Closure_Service"),
+                OsString::from("--verbose")
+            ]
+        );
+
+        // The closure is consumed by the first invocation.
+        invoke(&raw_table[0], &["closure_service"]);
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_closure_entry_reclaimed() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut table = ServiceTable::new()
+            .add_service_fn("reclaimed_service", move |arguments| {
+                tx.send(arguments).unwrap();
+            })
+            .unwrap();
+        table.register_closures();
+        table.reclaim_closures();
+
+        invoke(&table.to_raw()[0], &["reclaimed_service"]);
+        assert!(rx.try_recv().is_err());
+
+        table.entries.pop().unwrap().run_in_console();
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            vec![OsString::from("reclaimed_service")]
+        );
+    }
+
+    #[test]
+    fn test_ffi_entry_run_in_console() {
+        let mut table = ServiceTable::new()
+            .add_service("first", ffi_first_service_main)
+            .unwrap();
+        table.entries.pop().unwrap().run_in_console();
+
+        let calls = CALLS.with(|calls| calls.borrow_mut().split_off(0));
+        assert_eq!(calls, vec![("first", vec![OsString::from("first")])]);
+    }
+
+    #[test]
+    fn test_duplicate_service_name() {
+        let result = ServiceTable::new()
+            .add_service("first", ffi_first_service_main)
+            .unwrap()
+            .add_service("FIRST", ffi_second_service_main);
+        assert!(matches!(result, Err(Error::DuplicateServiceName(_))));
+    }
+
+    #[test]
+    fn test_service_name_with_nul() {
+        let result = ServiceTable::new().add_service("fir\0st", ffi_first_service_main);
+        assert!(matches!(result, Err(Error::ArgumentHasNulByte(_))));
+    }
+
+    #[test]
+    fn test_entries_route_to_handlers() {
+        let table = ServiceTable::new()
+            .add_service("first", ffi_first_service_main)
+            .unwrap()
+            .add_service("second", ffi_second_service_main)
+            .unwrap();
+        let raw_table = table.to_raw();
+
+        invoke(&raw_table[1], &["second", "--verbose"]);
+        invoke(&raw_table[0], &["first"]);
+
+        let calls = CALLS.with(|calls| calls.borrow_mut().split_off(0));
+        assert_eq!(
+            calls,
+            vec![
+                (
+                    "second",
+                    vec![OsString::from("second"), OsString::from("--verbose")]
+                ),
+                ("first", vec![OsString::from("first")]),
+            ]
+        );
+    }
+}
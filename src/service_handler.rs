@@ -0,0 +1,332 @@
+//! A trait with one callback per service control event.
+//!
+//! Implement [`ServiceHandler`] inside the [`service_handler!`] macro, which derives the
+//! [`ControlPolicy`] of the handler from the callbacks it defines. A service that defines
+//! `on_stop` and `on_pause` accepts [`ServiceControlAccept::STOP`] and
+//! [`ServiceControlAccept::PAUSE_CONTINUE`], so there is no need to keep the accepted controls in
+//! sync with the implementation by hand.
+//!
+//! Register the handler with [`service_control_handler::register_handler`] and report
+//! [`ControlPolicy::accepted`] in [`ServiceStatus::controls_accepted`].
+//!
+#![cfg_attr(windows, doc = "```rust,no_run")]
+#![cfg_attr(not(windows), doc = "```rust,ignore")]
+//! use std::sync::mpsc;
+//! use windows_service::service::PowerEventParam;
+//! use windows_service::service_handler;
+//! use windows_service::service_handler::ServiceHandler;
+//!
+//! enum Event {
+//!     Stop,
+//!     Suspend,
+//! }
+//!
+//! struct MyHandler {
+//!     events: mpsc::Sender<Event>,
+//! }
+//!
+//! service_handler! {
+//!     impl ServiceHandler for MyHandler {
+//!         fn on_stop(&mut self) {
+//!             let _ = self.events.send(Event::Stop);
+//!         }
+//!
+//!         fn on_power_event(&mut self, param: PowerEventParam) {
+//!             if param == PowerEventParam::Suspend {
+//!                 let _ = self.events.send(Event::Suspend);
+//!             }
+//!         }
+//!     }
+//! }
+//!
+//! fn run_service() -> windows_service::Result<()> {
+//!     let (events, receiver) = mpsc::channel();
+//!     let handler = MyHandler { events };
+//!     let controls_accepted = handler.control_policy().accepted();
+//!     let status_handle = windows_service::service_control_handler::register_handler(
+//!         "my_service_name",
+//!         handler,
+//!     )?;
+//!     // Report `controls_accepted` along with the Running state and handle the events...
+//!     Ok(())
+//! }
+//! ```
+//!
+//! [`service_control_handler::register_handler`]: crate::service_control_handler::register_handler
+//! [`ServiceStatus::controls_accepted`]: crate::service::ServiceStatus::controls_accepted
+//! [`service_handler!`]: crate::service_handler!
+
+use crate::service::{
+    ControlPolicy, HardwareProfileChangeParam, PowerEventParam, ServiceControl,
+    ServiceControlAccept, SessionChangeParam, UserEventCode,
+};
+
+/// Callbacks for the service control events.
+///
+/// Each callback is only invoked for the controls allowed by [`ServiceHandler::control_policy`],
+/// which [`service_handler!`] implements from the callbacks defined. The callbacks run on the
+/// thread the system uses to deliver the events, so they should hand the work off to the service
+/// and return quickly.
+///
+/// [`service_handler!`]: crate::service_handler!
+pub trait ServiceHandler {
+    /// The controls delivered to this handler.
+    fn control_policy(&self) -> ControlPolicy;
+
+    /// Called for [`ServiceControl::Stop`].
+    fn on_stop(&mut self) {}
+
+    /// Called for [`ServiceControl::Pause`].
+    fn on_pause(&mut self) {}
+
+    /// Called for [`ServiceControl::Continue`].
+    fn on_continue(&mut self) {}
+
+    /// Called for [`ServiceControl::Shutdown`].
+    fn on_shutdown(&mut self) {}
+
+    /// Called for [`ServiceControl::Preshutdown`].
+    fn on_preshutdown(&mut self) {}
+
+    /// Called for [`ServiceControl::ParamChange`].
+    fn on_param_change(&mut self) {}
+
+    /// Called for [`ServiceControl::NetBindAdd`].
+    fn on_netbind_add(&mut self) {}
+
+    /// Called for [`ServiceControl::NetBindRemove`].
+    fn on_netbind_remove(&mut self) {}
+
+    /// Called for [`ServiceControl::NetBindEnable`].
+    fn on_netbind_enable(&mut self) {}
+
+    /// Called for [`ServiceControl::NetBindDisable`].
+    fn on_netbind_disable(&mut self) {}
+
+    /// Called for [`ServiceControl::HardwareProfileChange`].
+    fn on_hardware_profile_change(&mut self, _param: HardwareProfileChangeParam) {}
+
+    /// Called for [`ServiceControl::PowerEvent`].
+    fn on_power_event(&mut self, _param: PowerEventParam) {}
+
+    /// Called for [`ServiceControl::SessionChange`].
+    fn on_session_change(&mut self, _param: SessionChangeParam) {}
+
+    /// Called for [`ServiceControl::TimeChange`].
+    fn on_time_change(&mut self) {}
+
+    /// Called for [`ServiceControl::TriggerEvent`].
+    fn on_trigger_event(&mut self) {}
+
+    /// Called for [`ServiceControl::UserEvent`].
+    fn on_user_event(&mut self, _code: UserEventCode) {}
+}
+
+/// Deliver the control to the matching callback of the handler.
+///
+/// Returns `false` if the handler does not accept the control, in which case the system should be
+/// told that the control is not implemented. [`ServiceControl::Interrogate`] is always accepted
+/// without invoking any callback.
+pub fn dispatch(handler: &mut impl ServiceHandler, control: ServiceControl) -> bool {
+    if control == ServiceControl::Interrogate {
+        return true;
+    }
+    if !handler.control_policy().delivers(&control) {
+        return false;
+    }
+    match control {
+        ServiceControl::Continue => handler.on_continue(),
+        ServiceControl::Interrogate => (),
+        ServiceControl::NetBindAdd => handler.on_netbind_add(),
+        ServiceControl::NetBindDisable => handler.on_netbind_disable(),
+        ServiceControl::NetBindEnable => handler.on_netbind_enable(),
+        ServiceControl::NetBindRemove => handler.on_netbind_remove(),
+        ServiceControl::ParamChange => handler.on_param_change(),
+        ServiceControl::Pause => handler.on_pause(),
+        ServiceControl::Preshutdown => handler.on_preshutdown(),
+        ServiceControl::Shutdown => handler.on_shutdown(),
+        ServiceControl::Stop => handler.on_stop(),
+        ServiceControl::HardwareProfileChange(param) => handler.on_hardware_profile_change(param),
+        ServiceControl::PowerEvent(param) => handler.on_power_event(param),
+        ServiceControl::SessionChange(param) => handler.on_session_change(param),
+        ServiceControl::TimeChange => handler.on_time_change(),
+        ServiceControl::TriggerEvent => handler.on_trigger_event(),
+        ServiceControl::UserEvent(code) => handler.on_user_event(code),
+    }
+    true
+}
+
+/// The policy delivering the controls of the callbacks, given as the accepted controls and
+/// whether user events are delivered for each of them. Used by [`service_handler!`].
+///
+/// [`service_handler!`]: crate::service_handler!
+#[doc(hidden)]
+pub fn policy_for_callbacks(callbacks: &[(ServiceControlAccept, bool)]) -> ControlPolicy {
+    let (accepted, user_events) = callbacks.iter().fold(
+        (ServiceControlAccept::empty(), false),
+        |(accepted, user_events), &(callback_accepted, callback_user_events)| {
+            (
+                accepted | callback_accepted,
+                user_events || callback_user_events,
+            )
+        },
+    );
+    ControlPolicy::new(accepted).user_events(user_events)
+}
+
+/// A macro to implement [`ServiceHandler`] with the [`ControlPolicy`] derived from the callbacks
+/// defined in the impl block.
+///
+/// See the [module documentation](mod@crate::service_handler) for an example.
+#[macro_export]
+macro_rules! service_handler {
+    (
+        $(#[$impl_meta:meta])*
+        impl ServiceHandler for $handler:ty {
+            $(
+                $(#[$meta:meta])*
+                fn $callback:ident $parameters:tt $body:block
+            )*
+        }
+    ) => {
+        $(#[$impl_meta])*
+        impl $crate::service_handler::ServiceHandler for $handler {
+            fn control_policy(&self) -> $crate::service::ControlPolicy {
+                $crate::service_handler::policy_for_callbacks(&[
+                    $($crate::__service_handler_callback!($callback)),*
+                ])
+            }
+
+            $(
+                $(#[$meta])*
+                fn $callback $parameters $body
+            )*
+        }
+    };
+}
+
+/// The accepted controls of a callback and whether it receives user events. A callback without
+/// an arm here fails to compile.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __service_handler_callback {
+    (on_stop) => {
+        ($crate::service::ServiceControlAccept::STOP, false)
+    };
+    (on_pause) => {
+        ($crate::service::ServiceControlAccept::PAUSE_CONTINUE, false)
+    };
+    (on_continue) => {
+        ($crate::service::ServiceControlAccept::PAUSE_CONTINUE, false)
+    };
+    (on_shutdown) => {
+        ($crate::service::ServiceControlAccept::SHUTDOWN, false)
+    };
+    (on_preshutdown) => {
+        ($crate::service::ServiceControlAccept::PRESHUTDOWN, false)
+    };
+    (on_param_change) => {
+        ($crate::service::ServiceControlAccept::PARAM_CHANGE, false)
+    };
+    (on_netbind_add) => {
+        ($crate::service::ServiceControlAccept::NETBIND_CHANGE, false)
+    };
+    (on_netbind_remove) => {
+        ($crate::service::ServiceControlAccept::NETBIND_CHANGE, false)
+    };
+    (on_netbind_enable) => {
+        ($crate::service::ServiceControlAccept::NETBIND_CHANGE, false)
+    };
+    (on_netbind_disable) => {
+        ($crate::service::ServiceControlAccept::NETBIND_CHANGE, false)
+    };
+    (on_hardware_profile_change) => {
+        (
+            $crate::service::ServiceControlAccept::HARDWARE_PROFILE_CHANGE,
+            false,
+        )
+    };
+    (on_power_event) => {
+        ($crate::service::ServiceControlAccept::POWER_EVENT, false)
+    };
+    (on_session_change) => {
+        ($crate::service::ServiceControlAccept::SESSION_CHANGE, false)
+    };
+    (on_time_change) => {
+        ($crate::service::ServiceControlAccept::TIME_CHANGE, false)
+    };
+    (on_trigger_event) => {
+        ($crate::service::ServiceControlAccept::TRIGGER_EVENT, false)
+    };
+    (on_user_event) => {
+        ($crate::service::ServiceControlAccept::empty(), true)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct RecordingHandler {
+        calls: Vec<String>,
+    }
+
+    service_handler! {
+        impl ServiceHandler for RecordingHandler {
+            fn on_stop(&mut self) {
+                self.calls.push("stop".to_owned());
+            }
+
+            /// Pausing implies continuing.
+            fn on_pause(&mut self) {
+                self.calls.push("pause".to_owned());
+            }
+
+            fn on_power_event(&mut self, param: PowerEventParam) {
+                self.calls.push(format!("power {:?}", param));
+            }
+
+            fn on_user_event(&mut self, code: UserEventCode) {
+                self.calls.push(format!("user {}", code.to_raw()));
+            }
+        }
+    }
+
+    #[test]
+    fn test_control_policy_from_callbacks() {
+        let policy = RecordingHandler::default().control_policy();
+        assert_eq!(
+            policy.accepted(),
+            ServiceControlAccept::STOP
+                | ServiceControlAccept::PAUSE_CONTINUE
+                | ServiceControlAccept::POWER_EVENT
+        );
+        assert!(policy.delivers(&ServiceControl::UserEvent(
+            UserEventCode::from_raw(200).unwrap()
+        )));
+    }
+
+    #[test]
+    fn test_dispatch() {
+        let mut handler = RecordingHandler::default();
+        assert!(dispatch(&mut handler, ServiceControl::Interrogate));
+        assert!(dispatch(&mut handler, ServiceControl::Pause));
+        // Accepted along with pause, but not implemented by the handler.
+        assert!(dispatch(&mut handler, ServiceControl::Continue));
+        assert!(!dispatch(&mut handler, ServiceControl::Shutdown));
+        assert!(dispatch(
+            &mut handler,
+            ServiceControl::PowerEvent(PowerEventParam::Suspend)
+        ));
+        assert!(dispatch(
+            &mut handler,
+            ServiceControl::UserEvent(UserEventCode::from_raw(130).unwrap())
+        ));
+        assert!(dispatch(&mut handler, ServiceControl::Stop));
+        assert_eq!(
+            handler.calls,
+            ["pause", "power Suspend", "user 130", "stop"]
+        );
+    }
+}
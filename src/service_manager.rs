@@ -1,12 +1,26 @@
+#[cfg(windows)]
 use std::ffi::{OsStr, OsString};
+#[cfg(windows)]
 use std::os::windows::ffi::OsStringExt;
+#[cfg(windows)]
 use std::{io, ptr};
 
+#[cfg(windows)]
 use widestring::{U16CString, WideCString};
-use windows_sys::Win32::System::Services::{self, ENUM_SERVICE_STATUSW};
 
+#[cfg(windows)]
+use crate::dependency_graph::DependencyGraph;
+#[cfg(windows)]
+use crate::notify::{NotificationReceiver, NotifyHandle, ServiceNotifications, ServiceNotifyMask};
+#[cfg(windows)]
 use crate::sc_handle::ScHandle;
-use crate::service::{to_wide, RawServiceInfo, Service, ServiceAccess, ServiceInfo, ServiceStatus};
+use crate::service::ServiceStatus;
+#[cfg(windows)]
+use crate::service::{to_wide, RawServiceInfo, Service, ServiceAccess, ServiceInfo};
+use crate::sys::Services;
+#[cfg(windows)]
+use crate::sys::Services::ENUM_SERVICE_STATUSW;
+#[cfg(windows)]
 use crate::{Error, Result};
 
 bitflags::bitflags! {
@@ -47,14 +61,16 @@ bitflags::bitflags! {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ServiceEntry {
     pub name: String,
     pub display_name: String,
     pub status: ServiceStatus,
 }
 
+#[cfg(windows)]
 impl ServiceEntry {
-    fn from_raw(raw: ENUM_SERVICE_STATUSW) -> Result<Self> {
+    pub(crate) fn from_raw(raw: ENUM_SERVICE_STATUSW) -> Result<Self> {
         unsafe {
             Ok(Self {
                 name: U16CString::from_ptr_str(raw.lpServiceName).to_string_lossy(),
@@ -67,10 +83,12 @@ impl ServiceEntry {
 }
 
 /// Service manager.
+#[cfg(windows)]
 pub struct ServiceManager {
     manager_handle: ScHandle,
 }
 
+#[cfg(windows)]
 impl ServiceManager {
     /// Private initializer.
     ///
@@ -344,4 +362,72 @@ impl ServiceManager {
             .map(ServiceEntry::from_raw)
             .collect()
     }
+
+    /// Turn the service manager handle into a blocking iterator over notifications about
+    /// services being created or deleted, as selected by `mask`. See the [`notify`] module for how
+    /// notifications are delivered.
+    ///
+    /// Required permission: [`ServiceManagerAccess::ENUMERATE_SERVICE`].
+    ///
+    /// [`notify`]: crate::notify
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use windows_service::notify::{ServiceNotification, ServiceNotifyMask};
+    /// use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+    ///
+    /// # fn main() -> windows_service::Result<()> {
+    /// let manager =
+    ///     ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::ENUMERATE_SERVICE)?;
+    /// let mask = ServiceNotifyMask::CREATED | ServiceNotifyMask::DELETED;
+    /// for notification in manager.into_notifications(mask) {
+    ///     match notification? {
+    ///         ServiceNotification::Created(name) => println!("created {}", name.to_string_lossy()),
+    ///         ServiceNotification::Deleted(name) => println!("deleted {}", name.to_string_lossy()),
+    ///         _ => (),
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn into_notifications(self, mask: ServiceNotifyMask) -> ServiceNotifications {
+        ServiceNotifications::new(NotifyHandle::Manager(self), mask)
+    }
+
+    /// Turn the service manager handle into a channel receiving notifications about services
+    /// being created or deleted, as selected by `mask`, from a background thread.
+    ///
+    /// Required permission: [`ServiceManagerAccess::ENUMERATE_SERVICE`].
+    pub fn into_notification_channel(self, mask: ServiceNotifyMask) -> NotificationReceiver {
+        NotificationReceiver::spawn(NotifyHandle::Manager(self), mask)
+    }
+
+    /// Provides access to the underlying service control manager handle.
+    pub(crate) fn raw_handle(&self) -> Services::SC_HANDLE {
+        self.manager_handle.raw_handle()
+    }
+
+    /// Build the dependency graph of all services and drivers registered with the system.
+    ///
+    /// Required permission: [`ServiceManagerAccess::ENUMERATE_SERVICE`].
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+    ///
+    /// # fn main() -> windows_service::Result<()> {
+    /// let manager =
+    ///     ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::ENUMERATE_SERVICE)?;
+    /// let graph = manager.dependency_graph()?;
+    /// for name in graph.stop_order_for("my_service")? {
+    ///     println!("{}", name.to_string_lossy());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn dependency_graph(&self) -> Result<DependencyGraph> {
+        DependencyGraph::query(self)
+    }
 }
@@ -0,0 +1,528 @@
+//! Running a service through its whole lifecycle.
+//!
+//! Most services go through the same steps: register the control handler, report the start
+//! pending state while initializing, report running, wait for the stop event, report the stop
+//! pending state while cleaning up and finally report stopped with an exit code. Implement the
+//! hooks of [`ServiceLifecycle`] and [`ServiceRuntime`] takes care of the rest:
+//!
+//! * The pending states are kept alive while [`ServiceLifecycle::init`] and
+//!   [`ServiceLifecycle::shutdown`] run, see [`StatusReporter::pending`].
+//! * [`ServiceControl::Stop`] and [`ServiceControl::Shutdown`] raise the [`StopSignal`] passed to
+//!   [`ServiceLifecycle::run`].
+//! * A hook that fails stops the service with the exit code returned by
+//!   [`ServiceLifecycle::exit_code`], which defaults to the mapping of [`ToExitCode`]. A hook that
+//!   panics stops it with [`ServiceExitCode::ServiceSpecific`] and [`PANIC_EXIT_CODE`], see
+//!   [`ServiceRuntime::panic_exit_code`].
+//!
+#![cfg_attr(windows, doc = "```rust,no_run")]
+#![cfg_attr(not(windows), doc = "```rust,ignore")]
+//! use std::ffi::OsString;
+//! use std::io;
+//! use std::time::Duration;
+//! use windows_service::service::ServiceType;
+//! use windows_service::service_runtime::{ServiceLifecycle, ServiceRuntime, StopSignal};
+//!
+//! #[derive(Default)]
+//! struct MyService;
+//!
+//! impl ServiceLifecycle for MyService {
+//!     type Error = io::Error;
+//!
+//!     fn init(&mut self, _arguments: Vec<OsString>) -> io::Result<()> {
+//!         // Open files, bind sockets...
+//!         Ok(())
+//!     }
+//!
+//!     fn run(&mut self, stop: &StopSignal) -> io::Result<()> {
+//!         while !stop.wait_timeout(Duration::from_secs(1)) {
+//!             // Do some work...
+//!         }
+//!         Ok(())
+//!     }
+//! }
+//!
+//! fn main() -> windows_service::Result<()> {
+//!     // Blocks until the service is stopped.
+//!     ServiceRuntime::new(ServiceType::OWN_PROCESS).dispatch("my_service", MyService::default())
+//! }
+//! ```
+//!
+//! [`StatusReporter::pending`]: crate::status_reporter::StatusReporter::pending
+
+use std::ffi::OsString;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+use crate::backend::StatusBackend;
+#[cfg(windows)]
+use crate::service::ServiceControl;
+use crate::service::{ServiceControlAccept, ServiceExitCode, ServiceState, ServiceType};
+#[cfg(windows)]
+use crate::service_control_handler::{self, ServiceControlHandlerResult};
+#[cfg(windows)]
+use crate::service_dispatcher;
+use crate::status_reporter::StatusReporter;
+use crate::{lock, Error, Result};
+
+/// The service specific exit code reported when a hook of [`ServiceLifecycle`] panics.
+pub const PANIC_EXIT_CODE: u32 = 101;
+
+/// The service specific exit code reported by default when a hook of [`ServiceLifecycle`] fails.
+pub const ERROR_EXIT_CODE: u32 = 1;
+
+/// Mapping of an error onto the exit code reported when a service stops because of it.
+pub trait ToExitCode {
+    /// The exit code to report when the service stops because of this error.
+    fn to_exit_code(&self) -> ServiceExitCode;
+}
+
+impl ToExitCode for ServiceExitCode {
+    fn to_exit_code(&self) -> ServiceExitCode {
+        *self
+    }
+}
+
+/// Errors carrying an OS error code map to [`ServiceExitCode::Win32`], all other errors to
+/// [`ServiceExitCode::ServiceSpecific`] with [`ERROR_EXIT_CODE`].
+impl ToExitCode for io::Error {
+    fn to_exit_code(&self) -> ServiceExitCode {
+        match self.raw_os_error() {
+            Some(code) => ServiceExitCode::Win32(code as u32),
+            None => ServiceExitCode::ServiceSpecific(ERROR_EXIT_CODE),
+        }
+    }
+}
+
+/// Winapi errors map the same way as [`io::Error`], and a service that failed to reach a state
+/// maps to the exit code it stopped with. All other errors map to
+/// [`ServiceExitCode::ServiceSpecific`] with [`ERROR_EXIT_CODE`].
+impl ToExitCode for Error {
+    fn to_exit_code(&self) -> ServiceExitCode {
+        match self {
+            Error::Winapi(e) => e.to_exit_code(),
+            Error::Wait(e) => e
+                .exit_code()
+                .unwrap_or(ServiceExitCode::ServiceSpecific(ERROR_EXIT_CODE)),
+            _ => ServiceExitCode::ServiceSpecific(ERROR_EXIT_CODE),
+        }
+    }
+}
+
+/// Looks for an [`io::Error`] or [`Error`] in the chain of sources, falling back to
+/// [`ServiceExitCode::ServiceSpecific`] with [`ERROR_EXIT_CODE`].
+impl ToExitCode for Box<dyn std::error::Error + Send + Sync> {
+    fn to_exit_code(&self) -> ServiceExitCode {
+        let mut source: Option<&(dyn std::error::Error + 'static)> = Some(self.as_ref());
+        while let Some(error) = source {
+            if let Some(e) = error.downcast_ref::<Error>() {
+                return e.to_exit_code();
+            }
+            if let Some(e) = error.downcast_ref::<io::Error>() {
+                return e.to_exit_code();
+            }
+            source = error.source();
+        }
+        ServiceExitCode::ServiceSpecific(ERROR_EXIT_CODE)
+    }
+}
+
+/// The hooks called by [`ServiceRuntime`] over the lifecycle of a service.
+pub trait ServiceLifecycle {
+    /// The error returned by the hooks, mapped onto the exit code of the service.
+    type Error: ToExitCode;
+
+    /// Initialize the service, while the start pending state is reported.
+    ///
+    /// The service does not run if initialization fails.
+    fn init(&mut self, arguments: Vec<OsString>) -> std::result::Result<(), Self::Error>;
+
+    /// Run the service, while the running state is reported, until `stop` is raised.
+    ///
+    /// The service stops when this returns, whether `stop` was raised or not.
+    fn run(&mut self, stop: &StopSignal) -> std::result::Result<(), Self::Error>;
+
+    /// Clean up after running, while the stop pending state is reported.
+    ///
+    /// Called after [`ServiceLifecycle::run`] returned, failed or panicked, but not when
+    /// [`ServiceLifecycle::init`] failed.
+    fn shutdown(&mut self) -> std::result::Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// The exit code to report when a hook fails with the `error`. Defaults to the mapping of
+    /// [`ToExitCode`].
+    fn exit_code(&self, error: &Self::Error) -> ServiceExitCode {
+        error.to_exit_code()
+    }
+}
+
+/// A signal telling a running service to stop.
+#[derive(Debug, Clone, Default)]
+pub struct StopSignal {
+    inner: Arc<(Mutex<bool>, Condvar)>,
+}
+
+impl StopSignal {
+    /// Create a signal that is not raised.
+    pub fn new() -> Self {
+        StopSignal::default()
+    }
+
+    /// Raise the signal, waking up all waiting threads.
+    pub fn stop(&self) {
+        *lock(&self.inner.0) = true;
+        self.inner.1.notify_all();
+    }
+
+    /// Whether the signal was raised.
+    pub fn is_stopped(&self) -> bool {
+        *lock(&self.inner.0)
+    }
+
+    /// Block the current thread until the signal is raised.
+    pub fn wait(&self) {
+        let mut stopped = lock(&self.inner.0);
+        while !*stopped {
+            stopped = self
+                .inner
+                .1
+                .wait(stopped)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    /// Block the current thread until the signal is raised or the `timeout` elapses. Returns
+    /// whether the signal was raised.
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        let stopped = lock(&self.inner.0);
+        let (stopped, _) = self
+            .inner
+            .1
+            .wait_timeout_while(stopped, timeout, |stopped| !*stopped)
+            .unwrap_or_else(|e| e.into_inner());
+        *stopped
+    }
+}
+
+/// Runs a [`ServiceLifecycle`], reporting the service status along the way.
+#[derive(Debug, Clone)]
+pub struct ServiceRuntime {
+    service_type: ServiceType,
+    start_wait_hint: Duration,
+    stop_wait_hint: Duration,
+    panic_exit_code: u32,
+    console_fallback: bool,
+}
+
+impl ServiceRuntime {
+    /// Create a runtime for a service of the given type, with 30 seconds of wait hint for both
+    /// starting and stopping.
+    pub fn new(service_type: ServiceType) -> Self {
+        ServiceRuntime {
+            service_type,
+            start_wait_hint: Duration::from_secs(30),
+            stop_wait_hint: Duration::from_secs(30),
+            panic_exit_code: PANIC_EXIT_CODE,
+            console_fallback: false,
+        }
+    }
+
+    /// The wait hint reported while initializing.
+    pub fn start_wait_hint(mut self, wait_hint: Duration) -> Self {
+        self.start_wait_hint = wait_hint;
+        self
+    }
+
+    /// The wait hint reported while shutting down.
+    pub fn stop_wait_hint(mut self, wait_hint: Duration) -> Self {
+        self.stop_wait_hint = wait_hint;
+        self
+    }
+
+    /// The service specific exit code reported when a hook panics. Defaults to
+    /// [`PANIC_EXIT_CODE`].
+    ///
+    /// Services run with [`ServiceRuntime::run_service`] also report this code when the control
+    /// handler it registers panics, instead of the code set with
+    /// [`service_control_handler::set_panic_exit_code`].
+    ///
+    /// [`service_control_handler::set_panic_exit_code`]: crate::service_control_handler::set_panic_exit_code
+    pub fn panic_exit_code(mut self, exit_code: u32) -> Self {
+        self.panic_exit_code = exit_code;
+        self
+    }
+
+    /// Run the service in console mode from [`ServiceRuntime::dispatch`] when the program was not
+    /// started by the service control manager. Disabled by default, see
+    /// [`ServiceTable::console_fallback`].
+    ///
+    /// [`ServiceTable::console_fallback`]: crate::service_dispatcher::ServiceTable::console_fallback
+    pub fn console_fallback(mut self, enabled: bool) -> Self {
+        self.console_fallback = enabled;
+        self
+    }
+
+    /// The controls accepted by the running service.
+    pub fn controls_accepted(&self) -> ServiceControlAccept {
+        ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN
+    }
+
+    /// Register the service with the service control dispatcher and run the `lifecycle` when the
+    /// system starts it.
+    ///
+    /// Blocks the current thread until the service is stopped, see [`service_dispatcher::start`].
+    /// With [`ServiceRuntime::console_fallback`], the service runs in the foreground when the
+    /// program is launched from a terminal.
+    #[cfg(windows)]
+    pub fn dispatch<L>(self, service_name: impl AsRef<std::ffi::OsStr>, lifecycle: L) -> Result<()>
+    where
+        L: ServiceLifecycle + Send + 'static,
+    {
+        let name = service_name.as_ref().to_os_string();
+        let console_fallback = self.console_fallback;
+        service_dispatcher::ServiceTable::new()
+            .add_service_fn(service_name, move |arguments| {
+                // There is no one to report the error to if the status cannot be reported.
+                let _ = self.run_service(name, arguments, lifecycle);
+            })?
+            .console_fallback(console_fallback)
+            .start()
+    }
+
+    /// Run the `lifecycle` from the service entry point, registering the control handler for the
+    /// service. Returns the exit code reported.
+    #[cfg(windows)]
+    pub fn run_service<L: ServiceLifecycle>(
+        &self,
+        service_name: impl AsRef<std::ffi::OsStr>,
+        arguments: Vec<OsString>,
+        lifecycle: L,
+    ) -> Result<ServiceExitCode> {
+        let stop = StopSignal::new();
+        let status_handle = service_control_handler::register(service_name, {
+            let stop = stop.clone();
+            move |control| match control {
+                ServiceControl::Stop | ServiceControl::Shutdown => {
+                    stop.stop();
+                    ServiceControlHandlerResult::NoError
+                }
+                ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+                _ => ServiceControlHandlerResult::NotImplemented,
+            }
+        })?;
+        service_control_handler::set_service_panic_exit_code(&status_handle, self.panic_exit_code);
+        self.run(lifecycle, arguments, status_handle, &stop)
+    }
+
+    /// Run the `lifecycle`, reporting the status through `status_handle` and stopping when `stop`
+    /// is raised. Returns the exit code reported.
+    ///
+    /// This is the part of [`ServiceRuntime::run_service`] that does not talk to the system, so
+    /// it can be used with a fake status handle.
+    pub fn run<L, H>(
+        &self,
+        mut lifecycle: L,
+        arguments: Vec<OsString>,
+        status_handle: H,
+        stop: &StopSignal,
+    ) -> Result<ServiceExitCode>
+    where
+        L: ServiceLifecycle,
+        H: StatusBackend + Clone + Send + 'static,
+    {
+        let mut reporter =
+            StatusReporter::new(status_handle, self.service_type, self.controls_accepted());
+
+        let starting = reporter.pending(ServiceState::StartPending, self.start_wait_hint)?;
+        if let Err(exit_code) =
+            self.call_hook(&mut lifecycle, |lifecycle| lifecycle.init(arguments))
+        {
+            drop(starting);
+            reporter.stopped(exit_code)?;
+            return Ok(exit_code);
+        }
+        starting.finish()?;
+
+        let run_result = self.call_hook(&mut lifecycle, |lifecycle| lifecycle.run(stop));
+
+        let stopping = reporter.pending(ServiceState::StopPending, self.stop_wait_hint)?;
+        let shutdown_result = self.call_hook(&mut lifecycle, |lifecycle| lifecycle.shutdown());
+        drop(stopping);
+
+        // The failure to run is the root cause of any failure to shut down.
+        let exit_code = match run_result.and(shutdown_result) {
+            Ok(()) => ServiceExitCode::NO_ERROR,
+            Err(exit_code) => exit_code,
+        };
+        reporter.stopped(exit_code)?;
+        Ok(exit_code)
+    }
+
+    /// Call the hook, turning its error or panic into the exit code to report.
+    fn call_hook<L: ServiceLifecycle>(
+        &self,
+        lifecycle: &mut L,
+        hook: impl FnOnce(&mut L) -> std::result::Result<(), L::Error>,
+    ) -> std::result::Result<(), ServiceExitCode> {
+        match panic::catch_unwind(AssertUnwindSafe(|| hook(lifecycle))) {
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(error)) => Err(lifecycle.exit_code(&error)),
+            Err(_) => Err(ServiceExitCode::ServiceSpecific(self.panic_exit_code)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::backend::fake::status_handle;
+
+    /// A lifecycle recording the hooks called, failing or panicking in the configured hook.
+    #[derive(Default)]
+    struct TestLifecycle {
+        calls: Vec<&'static str>,
+        fail_in: Option<&'static str>,
+        panic_in: Option<&'static str>,
+    }
+
+    #[derive(Debug)]
+    struct TestError(u32);
+
+    impl ToExitCode for TestError {
+        fn to_exit_code(&self) -> ServiceExitCode {
+            ServiceExitCode::ServiceSpecific(self.0)
+        }
+    }
+
+    impl TestLifecycle {
+        fn call(&mut self, hook: &'static str) -> std::result::Result<(), TestError> {
+            self.calls.push(hook);
+            if self.panic_in == Some(hook) {
+                panic!("{} panicked", hook);
+            }
+            match self.fail_in {
+                Some(failing) if failing == hook => Err(TestError(7)),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    impl ServiceLifecycle for &mut TestLifecycle {
+        type Error = TestError;
+
+        fn init(&mut self, arguments: Vec<OsString>) -> std::result::Result<(), TestError> {
+            assert_eq!(arguments, ["svc"]);
+            self.call("init")
+        }
+
+        fn run(&mut self, stop: &StopSignal) -> std::result::Result<(), TestError> {
+            self.call("run")?;
+            stop.wait();
+            Ok(())
+        }
+
+        fn shutdown(&mut self) -> std::result::Result<(), TestError> {
+            self.call("shutdown")
+        }
+    }
+
+    /// Run the lifecycle, raising the stop signal from another thread. Returns the exit code and
+    /// the states reported, without the repeated pending states.
+    fn run(lifecycle: &mut TestLifecycle) -> (ServiceExitCode, Vec<ServiceState>) {
+        let handle = status_handle();
+        let stop = StopSignal::new();
+        let stopper = thread::spawn({
+            let stop = stop.clone();
+            move || {
+                thread::sleep(Duration::from_millis(20));
+                stop.stop();
+            }
+        });
+        let exit_code = ServiceRuntime::new(ServiceType::OWN_PROCESS)
+            .panic_exit_code(0xdead)
+            .run(lifecycle, vec!["svc".into()], handle.clone(), &stop)
+            .unwrap();
+        stopper.join().unwrap();
+
+        let mut states: Vec<ServiceState> = handle
+            .reported_statuses()
+            .iter()
+            .map(|status| status.current_state)
+            .collect();
+        states.dedup();
+        (exit_code, states)
+    }
+
+    #[test]
+    fn test_run() {
+        let mut lifecycle = TestLifecycle::default();
+        let (exit_code, states) = run(&mut lifecycle);
+        assert_eq!(exit_code, ServiceExitCode::NO_ERROR);
+        assert_eq!(
+            states,
+            [
+                ServiceState::StartPending,
+                ServiceState::Running,
+                ServiceState::StopPending,
+                ServiceState::Stopped
+            ]
+        );
+        assert_eq!(lifecycle.calls, ["init", "run", "shutdown"]);
+    }
+
+    #[test]
+    fn test_run_init_fails() {
+        let mut lifecycle = TestLifecycle {
+            fail_in: Some("init"),
+            ..TestLifecycle::default()
+        };
+        let (exit_code, states) = run(&mut lifecycle);
+        assert_eq!(exit_code, ServiceExitCode::ServiceSpecific(7));
+        assert_eq!(states, [ServiceState::StartPending, ServiceState::Stopped]);
+        assert_eq!(lifecycle.calls, ["init"]);
+    }
+
+    #[test]
+    fn test_run_panics() {
+        let mut lifecycle = TestLifecycle {
+            panic_in: Some("run"),
+            fail_in: Some("shutdown"),
+            ..TestLifecycle::default()
+        };
+        let (exit_code, states) = run(&mut lifecycle);
+        assert_eq!(exit_code, ServiceExitCode::ServiceSpecific(0xdead));
+        assert_eq!(
+            states,
+            [
+                ServiceState::StartPending,
+                ServiceState::Running,
+                ServiceState::StopPending,
+                ServiceState::Stopped
+            ]
+        );
+        assert_eq!(lifecycle.calls, ["init", "run", "shutdown"]);
+    }
+
+    #[test]
+    fn test_to_exit_code() {
+        let access_denied = io::Error::from_raw_os_error(5);
+        assert_eq!(access_denied.to_exit_code(), ServiceExitCode::Win32(5));
+        assert_eq!(
+            io::Error::new(io::ErrorKind::Other, "failed").to_exit_code(),
+            ServiceExitCode::ServiceSpecific(ERROR_EXIT_CODE)
+        );
+
+        let error: Box<dyn std::error::Error + Send + Sync> =
+            Box::new(Error::Winapi(access_denied));
+        assert_eq!(error.to_exit_code(), ServiceExitCode::Win32(5));
+        let error: Box<dyn std::error::Error + Send + Sync> = "failed".into();
+        assert_eq!(
+            error.to_exit_code(),
+            ServiceExitCode::ServiceSpecific(ERROR_EXIT_CODE)
+        );
+    }
+}
@@ -0,0 +1,862 @@
+//! Trigger events that start or stop a service.
+//!
+//! A service configured with triggers is started or stopped by the system when the event occurs,
+//! for instance when a device of a given interface class arrives or when the computer joins a
+//! domain. Configure the triggers with [`Service::set_triggers`] and read them back with
+//! [`Service::get_triggers`]. A running service is notified of the trigger events it is registered
+//! for with [`ServiceControl::TriggerEvent`] when it accepts
+//! [`ServiceControlAccept::TRIGGER_EVENT`].
+//!
+#![cfg_attr(windows, doc = "```rust,no_run")]
+#![cfg_attr(not(windows), doc = "```rust,ignore")]
+//! use windows_service::service::ServiceAccess;
+//! use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+//! use windows_service::service_trigger::{
+//!     FirewallPortEvent, ServiceTrigger, ServiceTriggerAction, ServiceTriggerData,
+//!     ServiceTriggerType,
+//! };
+//!
+//! fn main() -> windows_service::Result<()> {
+//!     let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)?;
+//!     let service = manager.open_service("my_service", ServiceAccess::CHANGE_CONFIG)?;
+//!
+//!     service.set_triggers(&[ServiceTrigger {
+//!         trigger_type: ServiceTriggerType::FirewallPort(FirewallPortEvent::Open),
+//!         action: ServiceTriggerAction::Start,
+//!         data_items: vec![ServiceTriggerData::Strings(vec![
+//!             "5985".into(),
+//!             "TCP".into(),
+//!         ])],
+//!     }])
+//! }
+//! ```
+//!
+//! [`Service::set_triggers`]: crate::service::Service::set_triggers
+//! [`Service::get_triggers`]: crate::service::Service::get_triggers
+//! [`ServiceControl::TriggerEvent`]: crate::service::ServiceControl::TriggerEvent
+//! [`ServiceControlAccept::TRIGGER_EVENT`]: crate::service::ServiceControlAccept::TRIGGER_EVENT
+
+use std::ffi::OsString;
+use std::{fmt, mem};
+
+use widestring::{U16CString, U16Str};
+
+use crate::service::ParseRawError;
+use crate::sys::Services;
+use crate::Error;
+
+/// A globally unique identifier, used for the trigger subtypes, device interface classes and
+/// event providers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Guid(u128);
+
+impl Guid {
+    /// Create a GUID from its value as written in the canonical form, i.e.
+    /// `0x4f27f2de_14e2_430b_a549_7cd48cbc8245`.
+    pub const fn from_u128(value: u128) -> Self {
+        Guid(value)
+    }
+
+    pub const fn to_u128(self) -> u128 {
+        self.0
+    }
+
+    /// The in-memory representation of the system `GUID` struct, whose first three fields are
+    /// little endian integers.
+    fn to_bytes(self) -> [u8; 16] {
+        let value = self.0;
+        let mut bytes = [0u8; 16];
+        bytes[0..4].copy_from_slice(&((value >> 96) as u32).to_le_bytes());
+        bytes[4..6].copy_from_slice(&((value >> 80) as u16).to_le_bytes());
+        bytes[6..8].copy_from_slice(&((value >> 64) as u16).to_le_bytes());
+        bytes[8..16].copy_from_slice(&(value as u64).to_be_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: [u8; 16]) -> Self {
+        let data1 = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        let data2 = u16::from_le_bytes([bytes[4], bytes[5]]);
+        let data3 = u16::from_le_bytes([bytes[6], bytes[7]]);
+        let mut data4 = [0u8; 8];
+        data4.copy_from_slice(&bytes[8..16]);
+        Guid(
+            (u128::from(data1) << 96)
+                | (u128::from(data2) << 80)
+                | (u128::from(data3) << 64)
+                | u128::from(u64::from_be_bytes(data4)),
+        )
+    }
+}
+
+impl fmt::Display for Guid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = self.0;
+        write!(
+            f,
+            "{:08X}-{:04X}-{:04X}-{:04X}-{:012X}",
+            value >> 96,
+            (value >> 80) & 0xffff,
+            (value >> 64) & 0xffff,
+            (value >> 48) & 0xffff,
+            value & 0xffff_ffff_ffff
+        )
+    }
+}
+
+const NETWORK_MANAGER_FIRST_IP_ADDRESS_ARRIVAL_GUID: Guid =
+    Guid::from_u128(0x4f27f2de_14e2_430b_a549_7cd48cbc8245);
+const NETWORK_MANAGER_LAST_IP_ADDRESS_REMOVAL_GUID: Guid =
+    Guid::from_u128(0xcc4ba62a_162e_4648_847a_b6bdf993e335);
+const DOMAIN_JOIN_GUID: Guid = Guid::from_u128(0x1ce20aba_9851_4421_9430_1ddeb766e809);
+const DOMAIN_LEAVE_GUID: Guid = Guid::from_u128(0xddaf516e_58c2_4866_9574_c3b615d42ea1);
+const FIREWALL_PORT_OPEN_GUID: Guid = Guid::from_u128(0xb7569e07_8421_4ee0_ad10_86915afdad09);
+const FIREWALL_PORT_CLOSE_GUID: Guid = Guid::from_u128(0xa144ed38_8e12_4de4_9d96_e64740b1a524);
+const MACHINE_POLICY_PRESENT_GUID: Guid = Guid::from_u128(0x659fcae6_5bdb_4da9_b1ff_ca2a178d46e0);
+const USER_POLICY_PRESENT_GUID: Guid = Guid::from_u128(0x54fb46c8_f089_464c_b1fd_59d1b62c3b50);
+const RPC_INTERFACE_EVENT_GUID: Guid = Guid::from_u128(0xbc90d167_9470_4139_a9ba_be0bbbf5b74d);
+const NAMED_PIPE_EVENT_GUID: Guid = Guid::from_u128(0x1f81d131_3fac_4537_9e0c_7e7b0c2f4b55);
+
+/// The IP address availability events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IpAddressEvent {
+    /// The first IP address on the TCP/IP networking stack becomes available.
+    FirstArrival,
+    /// The last IP address on the TCP/IP networking stack becomes unavailable.
+    LastRemoval,
+}
+
+/// The domain membership events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DomainEvent {
+    Join,
+    Leave,
+}
+
+/// The firewall port events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FirewallPortEvent {
+    Open,
+    Close,
+}
+
+/// The group policy change events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GroupPolicyEvent {
+    /// The machine policy changed, or is present at startup.
+    Machine,
+    /// The user policy changed, or is present at startup.
+    User,
+}
+
+/// The network endpoint events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NetworkEndpointEvent {
+    /// A request arrives for the RPC interface given in the data items.
+    RpcInterface,
+    /// A request arrives for the named pipe given in the data items.
+    NamedPipe,
+}
+
+/// The event of a service trigger.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ServiceTriggerType {
+    /// A device of the interface class arrives, or is present at startup. The data items narrow
+    /// the devices down to hardware or compatible IDs.
+    DeviceInterfaceArrival {
+        interface_class: Guid,
+    },
+    IpAddressAvailability(IpAddressEvent),
+    DomainJoin(DomainEvent),
+    /// The data items give the port, the protocol and optionally the executable and the user of
+    /// the listening process.
+    FirewallPort(FirewallPortEvent),
+    GroupPolicy(GroupPolicyEvent),
+    /// The data items give the RPC interface identifier or the named pipe name.
+    NetworkEndpoint(NetworkEndpointEvent),
+    /// An event is written by the ETW provider. The data items filter the events by keyword and
+    /// level.
+    Custom {
+        provider: Guid,
+    },
+    /// A trigger type, or a subtype of a known trigger type, not modeled by this crate, kept as is.
+    Other {
+        trigger_type: u32,
+        subtype: Option<Guid>,
+    },
+}
+
+impl ServiceTriggerType {
+    fn to_raw(&self) -> (u32, Option<Guid>) {
+        match *self {
+            ServiceTriggerType::DeviceInterfaceArrival { interface_class } => (
+                Services::SERVICE_TRIGGER_TYPE_DEVICE_INTERFACE_ARRIVAL,
+                Some(interface_class),
+            ),
+            ServiceTriggerType::IpAddressAvailability(event) => (
+                Services::SERVICE_TRIGGER_TYPE_IP_ADDRESS_AVAILABILITY,
+                Some(match event {
+                    IpAddressEvent::FirstArrival => NETWORK_MANAGER_FIRST_IP_ADDRESS_ARRIVAL_GUID,
+                    IpAddressEvent::LastRemoval => NETWORK_MANAGER_LAST_IP_ADDRESS_REMOVAL_GUID,
+                }),
+            ),
+            ServiceTriggerType::DomainJoin(event) => (
+                Services::SERVICE_TRIGGER_TYPE_DOMAIN_JOIN,
+                Some(match event {
+                    DomainEvent::Join => DOMAIN_JOIN_GUID,
+                    DomainEvent::Leave => DOMAIN_LEAVE_GUID,
+                }),
+            ),
+            ServiceTriggerType::FirewallPort(event) => (
+                Services::SERVICE_TRIGGER_TYPE_FIREWALL_PORT_EVENT,
+                Some(match event {
+                    FirewallPortEvent::Open => FIREWALL_PORT_OPEN_GUID,
+                    FirewallPortEvent::Close => FIREWALL_PORT_CLOSE_GUID,
+                }),
+            ),
+            ServiceTriggerType::GroupPolicy(event) => (
+                Services::SERVICE_TRIGGER_TYPE_GROUP_POLICY,
+                Some(match event {
+                    GroupPolicyEvent::Machine => MACHINE_POLICY_PRESENT_GUID,
+                    GroupPolicyEvent::User => USER_POLICY_PRESENT_GUID,
+                }),
+            ),
+            ServiceTriggerType::NetworkEndpoint(event) => (
+                Services::SERVICE_TRIGGER_TYPE_NETWORK_ENDPOINT,
+                Some(match event {
+                    NetworkEndpointEvent::RpcInterface => RPC_INTERFACE_EVENT_GUID,
+                    NetworkEndpointEvent::NamedPipe => NAMED_PIPE_EVENT_GUID,
+                }),
+            ),
+            ServiceTriggerType::Custom { provider } => {
+                (Services::SERVICE_TRIGGER_TYPE_CUSTOM, Some(provider))
+            }
+            ServiceTriggerType::Other {
+                trigger_type,
+                subtype,
+            } => (trigger_type, subtype),
+        }
+    }
+
+    /// Unknown trigger types and unknown subtypes of known trigger types are kept as
+    /// [`ServiceTriggerType::Other`], so that they survive being read and written back.
+    fn from_raw(trigger_type: u32, subtype: Option<Guid>) -> Self {
+        let other = ServiceTriggerType::Other {
+            trigger_type,
+            subtype,
+        };
+        let Some(guid) = subtype else {
+            return other;
+        };
+        match (trigger_type, guid) {
+            (Services::SERVICE_TRIGGER_TYPE_DEVICE_INTERFACE_ARRIVAL, _) => {
+                ServiceTriggerType::DeviceInterfaceArrival {
+                    interface_class: guid,
+                }
+            }
+            (
+                Services::SERVICE_TRIGGER_TYPE_IP_ADDRESS_AVAILABILITY,
+                NETWORK_MANAGER_FIRST_IP_ADDRESS_ARRIVAL_GUID,
+            ) => ServiceTriggerType::IpAddressAvailability(IpAddressEvent::FirstArrival),
+            (
+                Services::SERVICE_TRIGGER_TYPE_IP_ADDRESS_AVAILABILITY,
+                NETWORK_MANAGER_LAST_IP_ADDRESS_REMOVAL_GUID,
+            ) => ServiceTriggerType::IpAddressAvailability(IpAddressEvent::LastRemoval),
+            (Services::SERVICE_TRIGGER_TYPE_DOMAIN_JOIN, DOMAIN_JOIN_GUID) => {
+                ServiceTriggerType::DomainJoin(DomainEvent::Join)
+            }
+            (Services::SERVICE_TRIGGER_TYPE_DOMAIN_JOIN, DOMAIN_LEAVE_GUID) => {
+                ServiceTriggerType::DomainJoin(DomainEvent::Leave)
+            }
+            (Services::SERVICE_TRIGGER_TYPE_FIREWALL_PORT_EVENT, FIREWALL_PORT_OPEN_GUID) => {
+                ServiceTriggerType::FirewallPort(FirewallPortEvent::Open)
+            }
+            (Services::SERVICE_TRIGGER_TYPE_FIREWALL_PORT_EVENT, FIREWALL_PORT_CLOSE_GUID) => {
+                ServiceTriggerType::FirewallPort(FirewallPortEvent::Close)
+            }
+            (Services::SERVICE_TRIGGER_TYPE_GROUP_POLICY, MACHINE_POLICY_PRESENT_GUID) => {
+                ServiceTriggerType::GroupPolicy(GroupPolicyEvent::Machine)
+            }
+            (Services::SERVICE_TRIGGER_TYPE_GROUP_POLICY, USER_POLICY_PRESENT_GUID) => {
+                ServiceTriggerType::GroupPolicy(GroupPolicyEvent::User)
+            }
+            (Services::SERVICE_TRIGGER_TYPE_NETWORK_ENDPOINT, RPC_INTERFACE_EVENT_GUID) => {
+                ServiceTriggerType::NetworkEndpoint(NetworkEndpointEvent::RpcInterface)
+            }
+            (Services::SERVICE_TRIGGER_TYPE_NETWORK_ENDPOINT, NAMED_PIPE_EVENT_GUID) => {
+                ServiceTriggerType::NetworkEndpoint(NetworkEndpointEvent::NamedPipe)
+            }
+            (Services::SERVICE_TRIGGER_TYPE_CUSTOM, _) => {
+                ServiceTriggerType::Custom { provider: guid }
+            }
+            _ => other,
+        }
+    }
+}
+
+/// The action taken by the system when the trigger event occurs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u32)]
+pub enum ServiceTriggerAction {
+    Start = Services::SERVICE_TRIGGER_ACTION_SERVICE_START,
+    Stop = Services::SERVICE_TRIGGER_ACTION_SERVICE_STOP,
+}
+
+impl ServiceTriggerAction {
+    pub fn to_raw(&self) -> u32 {
+        *self as u32
+    }
+
+    pub fn from_raw(raw: u32) -> Result<Self, ParseRawError> {
+        match raw {
+            x if x == ServiceTriggerAction::Start.to_raw() => Ok(ServiceTriggerAction::Start),
+            x if x == ServiceTriggerAction::Stop.to_raw() => Ok(ServiceTriggerAction::Stop),
+            _ => Err(ParseRawError::InvalidInteger(raw)),
+        }
+    }
+}
+
+/// A data item narrowing down the events of a trigger.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ServiceTriggerData {
+    Binary(Vec<u8>),
+    /// A string, or a sequence of strings stored as a nul-separated list.
+    Strings(Vec<OsString>),
+    /// The maximum level of the events of an ETW provider.
+    Level(u8),
+    /// Matches the events of an ETW provider with any of the keyword bits set.
+    KeywordAny(u64),
+    /// Matches the events of an ETW provider with all of the keyword bits set.
+    KeywordAll(u64),
+}
+
+impl ServiceTriggerData {
+    /// The data type and the data as stored in the trigger buffer.
+    fn to_raw(&self) -> crate::Result<(u32, Vec<u8>)> {
+        Ok(match self {
+            ServiceTriggerData::Binary(data) => {
+                (Services::SERVICE_TRIGGER_DATA_TYPE_BINARY, data.clone())
+            }
+            ServiceTriggerData::Strings(strings) => {
+                let mut wide = Vec::new();
+                for (i, s) in strings.iter().enumerate() {
+                    let s = U16CString::from_os_str(s).map_err(|_| {
+                        Error::ArgumentArrayElementHasNulByte("service trigger string", i)
+                    })?;
+                    wide.extend_from_slice(s.as_slice_with_nul());
+                }
+                wide.push(0);
+                let data = wide.iter().flat_map(|c| c.to_le_bytes()).collect();
+                (Services::SERVICE_TRIGGER_DATA_TYPE_STRING, data)
+            }
+            ServiceTriggerData::Level(level) => {
+                (Services::SERVICE_TRIGGER_DATA_TYPE_LEVEL, vec![*level])
+            }
+            ServiceTriggerData::KeywordAny(keyword) => (
+                Services::SERVICE_TRIGGER_DATA_TYPE_KEYWORD_ANY,
+                keyword.to_le_bytes().to_vec(),
+            ),
+            ServiceTriggerData::KeywordAll(keyword) => (
+                Services::SERVICE_TRIGGER_DATA_TYPE_KEYWORD_ALL,
+                keyword.to_le_bytes().to_vec(),
+            ),
+        })
+    }
+
+    fn from_raw(data_type: u32, data: &[u8]) -> crate::Result<Self> {
+        let invalid_size = || Error::InvalidBufferSize("service trigger data", data.len());
+        Ok(match data_type {
+            Services::SERVICE_TRIGGER_DATA_TYPE_BINARY => ServiceTriggerData::Binary(data.to_vec()),
+            Services::SERVICE_TRIGGER_DATA_TYPE_STRING => {
+                let wide: Vec<u16> = data
+                    .chunks_exact(2)
+                    .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                    .collect();
+                ServiceTriggerData::Strings(split_strings(&wide))
+            }
+            Services::SERVICE_TRIGGER_DATA_TYPE_LEVEL => match data {
+                [level] => ServiceTriggerData::Level(*level),
+                _ => return Err(invalid_size()),
+            },
+            Services::SERVICE_TRIGGER_DATA_TYPE_KEYWORD_ANY
+            | Services::SERVICE_TRIGGER_DATA_TYPE_KEYWORD_ALL => {
+                let keyword =
+                    u64::from_le_bytes(<[u8; 8]>::try_from(data).map_err(|_| invalid_size())?);
+                if data_type == Services::SERVICE_TRIGGER_DATA_TYPE_KEYWORD_ANY {
+                    ServiceTriggerData::KeywordAny(keyword)
+                } else {
+                    ServiceTriggerData::KeywordAll(keyword)
+                }
+            }
+            _ => {
+                return Err(Error::ParseValue(
+                    "service trigger data type",
+                    ParseRawError::InvalidInteger(data_type),
+                ))
+            }
+        })
+    }
+}
+
+/// Split the strings stored by [`ServiceTriggerData::to_raw`], each followed by a nul, with one
+/// more nul at the end. Unlike a regular multi-string, the end is given by the data size and not
+/// by the first empty string, so empty strings are kept. A single string with one nul at the end
+/// is accepted as well.
+fn split_strings(wide: &[u16]) -> Vec<OsString> {
+    let terminated = match wide {
+        [] | [0] => return Vec::new(),
+        [terminated @ .., 0, 0] => terminated,
+        [string @ .., 0] | string => return vec![U16Str::from_slice(string).to_os_string()],
+    };
+    terminated
+        .split(|c| *c == 0)
+        .map(|s| U16Str::from_slice(s).to_os_string())
+        .collect()
+}
+
+/// A trigger event and the action taken when it occurs.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ServiceTrigger {
+    pub trigger_type: ServiceTriggerType,
+    pub action: ServiceTriggerAction,
+    pub data_items: Vec<ServiceTriggerData>,
+}
+
+const POINTER_SIZE: usize = mem::size_of::<usize>();
+
+/// Size of `SERVICE_TRIGGER_INFO`: the trigger count followed by the trigger array and reserved
+/// pointers.
+const TRIGGER_INFO_SIZE: usize = 3 * POINTER_SIZE;
+const TRIGGER_INFO_TRIGGERS: usize = POINTER_SIZE;
+
+/// Size of `SERVICE_TRIGGER`: the type, the action, the subtype pointer, the data item count and
+/// the data item array pointer.
+const TRIGGER_SIZE: usize = TRIGGER_DATA_ITEMS + POINTER_SIZE;
+const TRIGGER_ACTION: usize = 4;
+const TRIGGER_SUBTYPE: usize = 8;
+const TRIGGER_DATA_ITEM_COUNT: usize = TRIGGER_SUBTYPE + POINTER_SIZE;
+const TRIGGER_DATA_ITEMS: usize = align_up(TRIGGER_DATA_ITEM_COUNT + 4, POINTER_SIZE);
+
+/// Size of `SERVICE_TRIGGER_SPECIFIC_DATA_ITEM`: the data type, the data size and the data
+/// pointer.
+const DATA_ITEM_SIZE: usize = DATA_ITEM_DATA + POINTER_SIZE;
+const DATA_ITEM_SIZE_FIELD: usize = 4;
+const DATA_ITEM_DATA: usize = 8;
+
+const GUID_SIZE: usize = 16;
+
+const fn align_up(offset: usize, align: usize) -> usize {
+    (offset + align - 1) / align * align
+}
+
+/// Writes the self-relative buffer passed to the system, where the pointers are the offsets of
+/// the referenced data plus the address the buffer is going to be stored at.
+struct Encoder {
+    bytes: Vec<u8>,
+    base: usize,
+}
+
+impl Encoder {
+    fn reserve(&mut self, len: usize, align: usize) -> usize {
+        let offset = align_up(self.bytes.len(), align);
+        self.bytes.resize(offset + len, 0);
+        offset
+    }
+
+    fn put_u32(&mut self, offset: usize, value: u32) {
+        self.bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn put_pointer(&mut self, offset: usize, target: Option<usize>) {
+        let address = target.map_or(0, |target| self.base + target);
+        self.bytes[offset..offset + POINTER_SIZE].copy_from_slice(&address.to_le_bytes());
+    }
+
+    fn put_bytes(&mut self, offset: usize, data: &[u8]) {
+        self.bytes[offset..offset + data.len()].copy_from_slice(data);
+    }
+}
+
+/// Encode the triggers as a `SERVICE_TRIGGER_INFO` buffer to be stored at `base`.
+///
+/// The buffer starts with the `SERVICE_TRIGGER_INFO` struct and contains the trigger array, the
+/// subtypes, the data item arrays and the data they point to. The length of the buffer does not
+/// depend on `base`.
+#[cfg_attr(not(windows), allow(dead_code))]
+pub(crate) fn encode_trigger_info(
+    triggers: &[ServiceTrigger],
+    base: usize,
+) -> crate::Result<Vec<u8>> {
+    let mut encoder = Encoder {
+        bytes: Vec::new(),
+        base,
+    };
+    let info = encoder.reserve(TRIGGER_INFO_SIZE, POINTER_SIZE);
+    encoder.put_u32(info, triggers.len() as u32);
+    if triggers.is_empty() {
+        return Ok(encoder.bytes);
+    }
+
+    let trigger_array = encoder.reserve(triggers.len() * TRIGGER_SIZE, POINTER_SIZE);
+    encoder.put_pointer(info + TRIGGER_INFO_TRIGGERS, Some(trigger_array));
+
+    for (i, trigger) in triggers.iter().enumerate() {
+        let raw_trigger = trigger_array + i * TRIGGER_SIZE;
+        let (trigger_type, subtype) = trigger.trigger_type.to_raw();
+        encoder.put_u32(raw_trigger, trigger_type);
+        encoder.put_u32(raw_trigger + TRIGGER_ACTION, trigger.action.to_raw());
+
+        if let Some(subtype) = subtype {
+            let raw_subtype = encoder.reserve(GUID_SIZE, 4);
+            encoder.put_bytes(raw_subtype, &subtype.to_bytes());
+            encoder.put_pointer(raw_trigger + TRIGGER_SUBTYPE, Some(raw_subtype));
+        }
+
+        encoder.put_u32(
+            raw_trigger + TRIGGER_DATA_ITEM_COUNT,
+            trigger.data_items.len() as u32,
+        );
+        if trigger.data_items.is_empty() {
+            continue;
+        }
+        let item_array = encoder.reserve(trigger.data_items.len() * DATA_ITEM_SIZE, POINTER_SIZE);
+        encoder.put_pointer(raw_trigger + TRIGGER_DATA_ITEMS, Some(item_array));
+
+        for (j, item) in trigger.data_items.iter().enumerate() {
+            let raw_item = item_array + j * DATA_ITEM_SIZE;
+            let (data_type, data) = item.to_raw()?;
+            encoder.put_u32(raw_item, data_type);
+            encoder.put_u32(raw_item + DATA_ITEM_SIZE_FIELD, data.len() as u32);
+            if !data.is_empty() {
+                let raw_data = encoder.reserve(data.len(), POINTER_SIZE);
+                encoder.put_bytes(raw_data, &data);
+                encoder.put_pointer(raw_item + DATA_ITEM_DATA, Some(raw_data));
+            }
+        }
+    }
+
+    Ok(encoder.bytes)
+}
+
+/// Reads the self-relative buffer returned by the system, stored at `base`.
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    base: usize,
+}
+
+impl Decoder<'_> {
+    fn slice(&self, offset: usize, len: usize) -> crate::Result<&[u8]> {
+        offset
+            .checked_add(len)
+            .and_then(|end| self.bytes.get(offset..end))
+            .ok_or_else(|| self.invalid_size())
+    }
+
+    fn u32(&self, offset: usize) -> crate::Result<u32> {
+        let bytes = self.slice(offset, 4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// The offset in the buffer of the data the pointer at `offset` points to.
+    fn pointer(&self, offset: usize) -> crate::Result<Option<usize>> {
+        let mut address = [0u8; POINTER_SIZE];
+        address.copy_from_slice(self.slice(offset, POINTER_SIZE)?);
+        match usize::from_le_bytes(address) {
+            0 => Ok(None),
+            address => address
+                .checked_sub(self.base)
+                .map(Some)
+                .ok_or_else(|| self.invalid_size()),
+        }
+    }
+
+    /// The error for data referenced outside of the buffer.
+    fn invalid_size(&self) -> Error {
+        Error::InvalidBufferSize("service trigger buffer", self.bytes.len())
+    }
+
+    fn array(&self, offset: usize, count: u32, element_size: usize) -> crate::Result<usize> {
+        let offset = match self.pointer(offset)? {
+            Some(offset) => offset,
+            None if count == 0 => return Ok(0),
+            None => return Err(self.invalid_size()),
+        };
+        self.slice(offset, count as usize * element_size)?;
+        Ok(offset)
+    }
+}
+
+/// Decode the triggers of a `SERVICE_TRIGGER_INFO` buffer stored at `base`.
+#[cfg_attr(not(windows), allow(dead_code))]
+pub(crate) fn decode_trigger_info(bytes: &[u8], base: usize) -> crate::Result<Vec<ServiceTrigger>> {
+    let decoder = Decoder { bytes, base };
+    let trigger_count = decoder.u32(0)?;
+    let trigger_array = decoder.array(TRIGGER_INFO_TRIGGERS, trigger_count, TRIGGER_SIZE)?;
+
+    let mut triggers = Vec::with_capacity(trigger_count as usize);
+    for i in 0..trigger_count as usize {
+        let raw_trigger = trigger_array + i * TRIGGER_SIZE;
+        let subtype = match decoder.pointer(raw_trigger + TRIGGER_SUBTYPE)? {
+            Some(offset) => {
+                let mut guid = [0u8; GUID_SIZE];
+                guid.copy_from_slice(decoder.slice(offset, GUID_SIZE)?);
+                Some(Guid::from_bytes(guid))
+            }
+            None => None,
+        };
+        let trigger_type = ServiceTriggerType::from_raw(decoder.u32(raw_trigger)?, subtype);
+        let action = ServiceTriggerAction::from_raw(decoder.u32(raw_trigger + TRIGGER_ACTION)?)
+            .map_err(|e| Error::ParseValue("service trigger action", e))?;
+
+        let item_count = decoder.u32(raw_trigger + TRIGGER_DATA_ITEM_COUNT)?;
+        let item_array =
+            decoder.array(raw_trigger + TRIGGER_DATA_ITEMS, item_count, DATA_ITEM_SIZE)?;
+        let mut data_items = Vec::with_capacity(item_count as usize);
+        for j in 0..item_count as usize {
+            let raw_item = item_array + j * DATA_ITEM_SIZE;
+            let data_size = decoder.u32(raw_item + DATA_ITEM_SIZE_FIELD)?;
+            let data = match decoder.pointer(raw_item + DATA_ITEM_DATA)? {
+                Some(offset) => decoder.slice(offset, data_size as usize)?,
+                None if data_size == 0 => &[],
+                None => {
+                    return Err(Error::InvalidBufferSize(
+                        "service trigger data",
+                        data_size as usize,
+                    ))
+                }
+            };
+            let item = ServiceTriggerData::from_raw(decoder.u32(raw_item)?, data)?;
+            data_items.push(item);
+        }
+
+        triggers.push(ServiceTrigger {
+            trigger_type,
+            action,
+            data_items,
+        });
+    }
+    Ok(triggers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_guid() {
+        let guid = Guid::from_u128(0x4f27f2de_14e2_430b_a549_7cd48cbc8245);
+        assert_eq!(guid.to_string(), "4F27F2DE-14E2-430B-A549-7CD48CBC8245");
+        assert_eq!(
+            guid.to_bytes(),
+            [
+                0xde, 0xf2, 0x27, 0x4f, 0xe2, 0x14, 0x0b, 0x43, 0xa5, 0x49, 0x7c, 0xd4, 0x8c, 0xbc,
+                0x82, 0x45
+            ]
+        );
+        assert_eq!(Guid::from_bytes(guid.to_bytes()), guid);
+    }
+
+    #[test]
+    fn test_strings_round_trip() {
+        let cases: [&[&str]; 5] = [&[], &[""], &["", ""], &["a", ""], &["80", "TCP"]];
+        for strings in cases {
+            let data = ServiceTriggerData::Strings(strings.iter().map(OsString::from).collect());
+            let (data_type, raw) = data.to_raw().unwrap();
+            assert_eq!(ServiceTriggerData::from_raw(data_type, &raw).unwrap(), data);
+        }
+
+        // A single string may be stored with one nul at the end only.
+        assert_eq!(
+            ServiceTriggerData::from_raw(2, b"a\x00b\x00\x00\x00").unwrap(),
+            ServiceTriggerData::Strings(vec!["ab".into()])
+        );
+    }
+
+    #[test]
+    fn test_data_item_encoding() {
+        let (data_type, data) = ServiceTriggerData::Strings(vec!["80".into(), "TCP".into()])
+            .to_raw()
+            .unwrap();
+        assert_eq!(data_type, 2);
+        assert_eq!(data, b"8\x000\x00\x00\x00T\x00C\x00P\x00\x00\x00\x00\x00");
+        assert_eq!(
+            ServiceTriggerData::from_raw(data_type, &data).unwrap(),
+            ServiceTriggerData::Strings(vec!["80".into(), "TCP".into()])
+        );
+
+        assert_eq!(ServiceTriggerData::Level(4).to_raw().unwrap(), (3, vec![4]));
+        assert_eq!(
+            ServiceTriggerData::KeywordAll(0x8000_0000_0000_0010)
+                .to_raw()
+                .unwrap(),
+            (5, vec![0x10, 0, 0, 0, 0, 0, 0, 0x80])
+        );
+        assert_eq!(
+            ServiceTriggerData::from_raw(4, &[1, 0, 0, 0, 0, 0, 0, 0]).unwrap(),
+            ServiceTriggerData::KeywordAny(1)
+        );
+        assert!(matches!(
+            ServiceTriggerData::from_raw(3, &[1, 2]),
+            Err(Error::InvalidBufferSize("service trigger data", 2))
+        ));
+        assert!(matches!(
+            ServiceTriggerData::from_raw(9, &[]),
+            Err(Error::ParseValue(_, ParseRawError::InvalidInteger(9)))
+        ));
+        assert!(matches!(
+            ServiceTriggerData::Strings(vec!["a".into(), "b\0c".into()]).to_raw(),
+            Err(Error::ArgumentArrayElementHasNulByte(_, 1))
+        ));
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn test_encode_trigger_info() {
+        let triggers = [ServiceTrigger {
+            trigger_type: ServiceTriggerType::DomainJoin(DomainEvent::Leave),
+            action: ServiceTriggerAction::Stop,
+            data_items: vec![ServiceTriggerData::Level(5)],
+        }];
+        let bytes = encode_trigger_info(&triggers, 0x1000).unwrap();
+
+        let mut expected = Vec::new();
+        // SERVICE_TRIGGER_INFO: one trigger at 0x18, no reserved data.
+        expected.extend_from_slice(&[1, 0, 0, 0, 0, 0, 0, 0]);
+        expected.extend_from_slice(&0x1018usize.to_le_bytes());
+        expected.extend_from_slice(&[0; 8]);
+        // SERVICE_TRIGGER: domain join, stop, subtype at 0x38, one data item at 0x48.
+        expected.extend_from_slice(&[3, 0, 0, 0, 2, 0, 0, 0]);
+        expected.extend_from_slice(&0x1038usize.to_le_bytes());
+        expected.extend_from_slice(&[1, 0, 0, 0, 0, 0, 0, 0]);
+        expected.extend_from_slice(&0x1048usize.to_le_bytes());
+        // DOMAIN_LEAVE_GUID
+        expected.extend_from_slice(&DOMAIN_LEAVE_GUID.to_bytes());
+        // SERVICE_TRIGGER_SPECIFIC_DATA_ITEM: level, one byte at 0x58.
+        expected.extend_from_slice(&[3, 0, 0, 0, 1, 0, 0, 0]);
+        expected.extend_from_slice(&0x1058usize.to_le_bytes());
+        expected.push(5);
+        assert_eq!(bytes, expected);
+
+        assert_eq!(decode_trigger_info(&bytes, 0x1000).unwrap(), triggers);
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn test_layout_matches_windows_sys() {
+        assert_eq!(
+            TRIGGER_INFO_SIZE,
+            mem::size_of::<Services::SERVICE_TRIGGER_INFO>()
+        );
+        assert_eq!(TRIGGER_SIZE, mem::size_of::<Services::SERVICE_TRIGGER>());
+        assert_eq!(
+            DATA_ITEM_SIZE,
+            mem::size_of::<Services::SERVICE_TRIGGER_SPECIFIC_DATA_ITEM>()
+        );
+    }
+
+    #[test]
+    fn test_encode_no_triggers() {
+        let bytes = encode_trigger_info(&[], 0x1000).unwrap();
+        assert_eq!(bytes, vec![0; TRIGGER_INFO_SIZE]);
+        assert_eq!(decode_trigger_info(&bytes, 0x1000).unwrap(), []);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let triggers = vec![
+            ServiceTrigger {
+                trigger_type: ServiceTriggerType::DeviceInterfaceArrival {
+                    interface_class: Guid::from_u128(0x53f56307_b6bf_11d0_94f2_00a0c91efb8b),
+                },
+                action: ServiceTriggerAction::Start,
+                data_items: vec![ServiceTriggerData::Strings(vec!["USBSTOR\\GenDisk".into()])],
+            },
+            ServiceTrigger {
+                trigger_type: ServiceTriggerType::IpAddressAvailability(
+                    IpAddressEvent::FirstArrival,
+                ),
+                action: ServiceTriggerAction::Start,
+                data_items: vec![],
+            },
+            ServiceTrigger {
+                trigger_type: ServiceTriggerType::FirewallPort(FirewallPortEvent::Close),
+                action: ServiceTriggerAction::Stop,
+                data_items: vec![ServiceTriggerData::Strings(vec![
+                    "5985".into(),
+                    "TCP".into(),
+                ])],
+            },
+            ServiceTrigger {
+                trigger_type: ServiceTriggerType::GroupPolicy(GroupPolicyEvent::User),
+                action: ServiceTriggerAction::Start,
+                data_items: vec![],
+            },
+            ServiceTrigger {
+                trigger_type: ServiceTriggerType::NetworkEndpoint(NetworkEndpointEvent::NamedPipe),
+                action: ServiceTriggerAction::Start,
+                data_items: vec![ServiceTriggerData::Strings(vec!["my_pipe".into()])],
+            },
+            ServiceTrigger {
+                trigger_type: ServiceTriggerType::Custom {
+                    provider: Guid::from_u128(0x22fb2cd6_0e7b_422b_a0c7_2fad1fd0e716),
+                },
+                action: ServiceTriggerAction::Start,
+                data_items: vec![
+                    ServiceTriggerData::KeywordAny(0x10),
+                    ServiceTriggerData::Level(4),
+                    ServiceTriggerData::Binary(vec![1, 2, 3]),
+                    ServiceTriggerData::Binary(vec![]),
+                ],
+            },
+            ServiceTrigger {
+                trigger_type: ServiceTriggerType::Other {
+                    trigger_type: 30,
+                    subtype: None,
+                },
+                action: ServiceTriggerAction::Start,
+                data_items: vec![],
+            },
+        ];
+        let bytes = encode_trigger_info(&triggers, 0x10000).unwrap();
+        assert_eq!(decode_trigger_info(&bytes, 0x10000).unwrap(), triggers);
+        assert_eq!(
+            encode_trigger_info(&triggers, 0).unwrap().len(),
+            bytes.len()
+        );
+    }
+
+    #[test]
+    fn test_decode_invalid_buffer() {
+        let triggers = [ServiceTrigger {
+            trigger_type: ServiceTriggerType::DomainJoin(DomainEvent::Join),
+            action: ServiceTriggerAction::Start,
+            data_items: vec![],
+        }];
+        let mut bytes = encode_trigger_info(&triggers, 0x1000).unwrap();
+
+        // Pointers below the base address of the buffer.
+        assert!(matches!(
+            decode_trigger_info(&bytes, 0x2000),
+            Err(Error::InvalidBufferSize("service trigger buffer", _))
+        ));
+        // Truncated subtype.
+        assert!(matches!(
+            decode_trigger_info(&bytes[..bytes.len() - 1], 0x1000),
+            Err(Error::InvalidBufferSize("service trigger buffer", len)) if len == bytes.len() - 1
+        ));
+
+        // An unknown subtype of a known trigger type is kept as is.
+        let subtype = bytes.len() - GUID_SIZE;
+        bytes[subtype] ^= 0xff;
+        let decoded = decode_trigger_info(&bytes, 0x1000).unwrap();
+        let mut subtype_bytes = DOMAIN_JOIN_GUID.to_bytes();
+        subtype_bytes[0] ^= 0xff;
+        assert_eq!(
+            decoded[0].trigger_type,
+            ServiceTriggerType::Other {
+                trigger_type: Services::SERVICE_TRIGGER_TYPE_DOMAIN_JOIN,
+                subtype: Some(Guid::from_bytes(subtype_bytes)),
+            }
+        );
+        assert_eq!(encode_trigger_info(&decoded, 0x1000).unwrap(), bytes);
+    }
+}
@@ -1,7 +1,8 @@
 use std::borrow::Cow;
 use std::ffi::{OsStr, OsString};
-use std::iter::repeat;
-use std::os::windows::ffi::{OsStrExt, OsStringExt};
+use std::iter::{repeat, Peekable};
+
+use widestring::U16String;
 
 /// Common UTF-16 code points.
 mod utf16 {
@@ -25,15 +26,16 @@ pub fn escape(s: Cow<'_, OsStr>) -> Cow<'_, OsStr> {
         utf16::HTAB,
         utf16::VTAB,
     ];
-    let needs_escape = s.is_empty() || s.encode_wide().any(|ref c| ESCAPE_CHARS.contains(c));
+    let wide = U16String::from_os_str(&s);
+    let needs_escape = wide.is_empty() || wide.as_slice().iter().any(|c| ESCAPE_CHARS.contains(c));
     if !needs_escape {
         return s;
     }
 
-    let mut escaped_wide_string: Vec<u16> = Vec::with_capacity(s.len() + 2);
+    let mut escaped_wide_string: Vec<u16> = Vec::with_capacity(wide.len() + 2);
     escaped_wide_string.push(utf16::DOUBLEQUOTE);
 
-    let mut chars = s.encode_wide().peekable();
+    let mut chars = wide.as_slice().iter().copied().peekable();
     loop {
         let mut num_slashes = 0;
         while let Some(&utf16::BACKSLASH) = chars.peek() {
@@ -59,13 +61,115 @@ pub fn escape(s: Cow<'_, OsStr>) -> Cow<'_, OsStr> {
 
     escaped_wide_string.push(utf16::DOUBLEQUOTE);
 
-    Cow::Owned(OsString::from_wide(&escaped_wide_string))
+    Cow::Owned(U16String::from_vec(escaped_wide_string).to_os_string())
+}
+
+/// Split a command line into arguments the same way as `CommandLineToArgvW`, which is the
+/// inverse of joining [`escape`]d arguments with spaces.
+///
+/// The first argument is the program name, which is parsed with simpler rules: it ends at the
+/// first space or tab outside of quotes, and backslashes are taken literally.
+///
+/// See <https://learn.microsoft.com/en-us/cpp/c-language/parsing-c-command-line-arguments>
+pub fn split(command_line: &OsStr) -> Vec<OsString> {
+    let wide = U16String::from_os_str(command_line);
+    if wide.is_empty() {
+        return Vec::new();
+    }
+
+    let mut chars = wide.as_slice().iter().copied().peekable();
+    let mut program_name = Vec::new();
+    let mut in_quotes = false;
+    for c in chars.by_ref() {
+        match c {
+            utf16::DOUBLEQUOTE => in_quotes = !in_quotes,
+            utf16::SPACE | utf16::HTAB if !in_quotes => break,
+            _ => program_name.push(c),
+        }
+    }
+
+    let mut args = vec![U16String::from_vec(program_name).to_os_string()];
+    args.extend(split_chars(chars));
+    args
+}
+
+/// Split command line arguments that do not start with a program name. See [`split`].
+pub fn split_arguments(arguments: &OsStr) -> Vec<OsString> {
+    let wide = U16String::from_os_str(arguments);
+    split_chars(wide.as_slice().iter().copied().peekable())
+}
+
+fn split_chars(mut chars: Peekable<impl Iterator<Item = u16>>) -> Vec<OsString> {
+    let mut args = Vec::new();
+    // `None` between arguments, so that empty quoted arguments are kept.
+    let mut arg: Option<Vec<u16>> = None;
+    let mut in_quotes = false;
+
+    while let Some(c) = chars.next() {
+        match c {
+            utf16::SPACE | utf16::HTAB if !in_quotes => {
+                args.extend(
+                    arg.take()
+                        .map(|arg| U16String::from_vec(arg).to_os_string()),
+                );
+            }
+            utf16::BACKSLASH => {
+                let mut num_slashes = 1;
+                while let Some(&utf16::BACKSLASH) = chars.peek() {
+                    chars.next();
+                    num_slashes += 1;
+                }
+
+                let arg = arg.get_or_insert_with(Vec::new);
+                if let Some(&utf16::DOUBLEQUOTE) = chars.peek() {
+                    // Backslashes are only special before a quote, which is escaped if there
+                    // is an odd number of them.
+                    arg.extend(repeat(utf16::BACKSLASH).take(num_slashes / 2));
+                    if num_slashes % 2 == 1 {
+                        chars.next();
+                        arg.push(utf16::DOUBLEQUOTE);
+                    }
+                } else {
+                    arg.extend(repeat(utf16::BACKSLASH).take(num_slashes));
+                }
+            }
+            utf16::DOUBLEQUOTE => {
+                let arg = arg.get_or_insert_with(Vec::new);
+                if in_quotes && chars.peek() == Some(&utf16::DOUBLEQUOTE) {
+                    // Two quotes inside quotes are a literal quote.
+                    chars.next();
+                    arg.push(utf16::DOUBLEQUOTE);
+                } else {
+                    in_quotes = !in_quotes;
+                }
+            }
+            _ => arg.get_or_insert_with(Vec::new).push(c),
+        }
+    }
+    args.extend(arg.map(|arg| U16String::from_vec(arg).to_os_string()));
+
+    args
 }
 
 #[cfg(test)]
 mod tests {
+    use proptest::prelude::*;
+
     use super::*;
 
+    fn join(program_name: &str, args: &[String]) -> OsString {
+        let mut command_line = escape(Cow::Borrowed(OsStr::new(program_name))).into_owned();
+        for arg in args {
+            command_line.push(" ");
+            command_line.push(escape(Cow::Borrowed(OsStr::new(arg))));
+        }
+        command_line
+    }
+
+    fn split_str(command_line: &str) -> Vec<OsString> {
+        split(OsStr::new(command_line))
+    }
+
     #[test]
     fn test_no_escape() {
         assert_eq!(
@@ -116,4 +220,58 @@ mod tests {
             OsStr::new(r#""\some\directory with\spaces\\""#)
         );
     }
+
+    #[test]
+    fn test_split_program_name() {
+        assert_eq!(split_str(""), Vec::<OsString>::new());
+        assert_eq!(split_str("C:\\app.exe"), ["C:\\app.exe"]);
+        assert_eq!(
+            split_str(r#""C:\Program Files\app.exe" --arg"#),
+            ["C:\\Program Files\\app.exe", "--arg"]
+        );
+        // Backslashes before quotes are not escapes in the program name.
+        assert_eq!(split_str(r#""C:\dir\"app.exe"#), ["C:\\dir\\app.exe"]);
+        assert_eq!(
+            split_str("C:\\Program Files\\app.exe"),
+            ["C:\\Program", "Files\\app.exe"]
+        );
+    }
+
+    #[test]
+    fn test_split_arguments() {
+        assert_eq!(split_str("app.exe  a\tb  "), ["app.exe", "a", "b"]);
+        assert_eq!(split_str(r#"app.exe "" "a b""#), ["app.exe", "", "a b"]);
+        assert_eq!(
+            split_str(r#"app.exe a\\b a\\\"b a\\"b c" d"#),
+            ["app.exe", r#"a\\b"#, r#"a\"b"#, r#"a\b c"#, "d"]
+        );
+        assert_eq!(split_str(r#"app.exe "a""b" c"#), ["app.exe", r#"a"b"#, "c"]);
+        assert_eq!(
+            split_arguments(OsStr::new(" -k netsvcs")),
+            ["-k", "netsvcs"]
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn test_split_escaped_arguments(
+            program_name in r"[a-zA-Z]:(\\[a-zA-Z0-9 ._-]*[a-zA-Z0-9._-])+",
+            args in prop::collection::vec("[^\0]*", 0..8),
+        ) {
+            let mut expected = vec![OsString::from(&program_name)];
+            expected.extend(args.iter().map(OsString::from));
+
+            prop_assert_eq!(split(&join(&program_name, &args)), expected);
+        }
+
+        #[test]
+        fn test_split_escaped_special_characters(
+            args in prop::collection::vec(r#"[ \t\n"\\a]*"#, 0..8),
+        ) {
+            let mut expected = vec![OsString::from("app.exe")];
+            expected.extend(args.iter().map(OsString::from));
+
+            prop_assert_eq!(split(&join("app.exe", &args)), expected);
+        }
+    }
 }
@@ -0,0 +1,765 @@
+//! Reporting the service status through the legal state transitions only.
+//!
+//! [`ServiceStatus`] can describe statuses the system does not expect, for example a checkpoint
+//! outside of the pending states or an exit code while running. [`StatusReporter`] builds the
+//! statuses itself and only moves between the states the way the "Service State Transitions"
+//! article on MSDN describes:
+//!
+//! * The service starts in [`StartPending`] and reports [`Running`] once initialized, or
+//!   [`Stopped`] if it fails to start.
+//! * Pausing goes through [`PausePending`] to [`Paused`], continuing through [`ContinuePending`]
+//!   back to [`Running`].
+//! * Stopping goes through [`StopPending`] to [`Stopped`], from any state but [`Stopped`].
+//!
+//! The [`checkpoint`] is managed automatically: it starts at one when entering a pending state and
+//! is increased by [`StatusReporter::checkpoint`]. The [`wait_hint`] and the checkpoint are zero
+//! in the other states, no controls are accepted while starting or stopping, and an exit code can
+//! only be reported when stopped.
+//!
+//! During a lengthy operation, [`StatusReporter::pending`] keeps increasing the checkpoint from a
+//! background thread, so that the system does not consider the service hung.
+//!
+//! <https://msdn.microsoft.com/en-us/library/windows/desktop/ee126211(v=vs.85).aspx>
+//!
+//! # Example
+//!
+#![cfg_attr(windows, doc = "```rust,no_run")]
+#![cfg_attr(not(windows), doc = "```rust,ignore")]
+//! use std::time::Duration;
+//! use windows_service::service::{
+//!     ServiceControlAccept, ServiceExitCode, ServiceState, ServiceType,
+//! };
+//! use windows_service::service_control_handler::ServiceStatusHandle;
+//! use windows_service::status_reporter::StatusReporter;
+//!
+//! fn run_service(status_handle: ServiceStatusHandle) -> windows_service::Result<()> {
+//!     let mut status = StatusReporter::new(
+//!         status_handle,
+//!         ServiceType::OWN_PROCESS,
+//!         ServiceControlAccept::STOP,
+//!     );
+//!     let starting = status.pending(ServiceState::StartPending, Duration::from_secs(10))?;
+//!     // Initialize, calling `starting.progress()` after each step...
+//!     starting.finish()?;
+//!
+//!     // Wait for the stop event...
+//!
+//!     status.stop_pending(Duration::from_secs(5))?;
+//!     // Clean up...
+//!     status.stopped(ServiceExitCode::NO_ERROR)
+//! }
+//! ```
+//!
+//! [`StartPending`]: ServiceState::StartPending
+//! [`Running`]: ServiceState::Running
+//! [`Stopped`]: ServiceState::Stopped
+//! [`PausePending`]: ServiceState::PausePending
+//! [`Paused`]: ServiceState::Paused
+//! [`ContinuePending`]: ServiceState::ContinuePending
+//! [`StopPending`]: ServiceState::StopPending
+//! [`checkpoint`]: ServiceStatus::checkpoint
+//! [`wait_hint`]: ServiceStatus::wait_hint
+
+use std::fmt;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::backend::StatusBackend;
+use crate::service::{
+    ServiceControlAccept, ServiceExitCode, ServiceState, ServiceStatus, ServiceType,
+};
+use crate::{lock, Error, Result};
+
+/// The reason a service status was rejected.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum StatusError {
+    /// The service cannot move from one state to the other.
+    IllegalTransition {
+        from: ServiceState,
+        to: ServiceState,
+    },
+
+    /// A checkpoint was reported in a state that is not pending.
+    CheckpointOutsidePending(ServiceState),
+
+    /// A wait hint was reported in a state that is not pending.
+    WaitHintOutsidePending(ServiceState),
+
+    /// An exit code was reported in a state other than stopping or stopped.
+    ExitCodeOutsideStop(ServiceState, ServiceExitCode),
+
+    /// Controls were accepted while the service is starting.
+    ControlsAcceptedWhileStarting(ServiceControlAccept),
+}
+
+impl std::error::Error for StatusError {}
+
+impl fmt::Display for StatusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StatusError::IllegalTransition { from, to } => {
+                write!(
+                    f,
+                    "service cannot move from the {:?} to the {:?} state",
+                    from, to
+                )
+            }
+            StatusError::CheckpointOutsidePending(state) => {
+                write!(f, "checkpoint reported in the {:?} state", state)
+            }
+            StatusError::WaitHintOutsidePending(state) => {
+                write!(f, "wait hint reported in the {:?} state", state)
+            }
+            StatusError::ExitCodeOutsideStop(state, exit_code) => {
+                write!(
+                    f,
+                    "exit code {:?} reported in the {:?} state",
+                    exit_code, state
+                )
+            }
+            StatusError::ControlsAcceptedWhileStarting(controls_accepted) => write!(
+                f,
+                "controls {:?} accepted in the StartPending state",
+                controls_accepted
+            ),
+        }
+    }
+}
+
+/// Whether the state is one of the pending states, in which the service reports its progress.
+pub fn is_pending(state: ServiceState) -> bool {
+    matches!(
+        state,
+        ServiceState::StartPending
+            | ServiceState::StopPending
+            | ServiceState::PausePending
+            | ServiceState::ContinuePending
+    )
+}
+
+/// Whether a service in the `from` state may report the `to` state next.
+///
+/// Reporting the same state again is legal, except for [`ServiceState::Stopped`].
+pub fn is_legal_transition(from: ServiceState, to: ServiceState) -> bool {
+    use ServiceState::*;
+
+    match (from, to) {
+        (Stopped, StartPending) => true,
+        (Stopped, _) => false,
+        (_, StopPending) => true,
+        (StartPending, StartPending | Running | Stopped) => true,
+        (Running, Running | PausePending) => true,
+        (PausePending, PausePending | Paused) => true,
+        (Paused, Paused | ContinuePending) => true,
+        (ContinuePending, ContinuePending | Running) => true,
+        (StopPending, Stopped) => true,
+        _ => false,
+    }
+}
+
+/// Check the fields of the status against its state.
+pub fn validate_status(status: &ServiceStatus) -> std::result::Result<(), StatusError> {
+    let state = status.current_state;
+    if !is_pending(state) {
+        if status.checkpoint != 0 {
+            return Err(StatusError::CheckpointOutsidePending(state));
+        }
+        if !status.wait_hint.is_zero() {
+            return Err(StatusError::WaitHintOutsidePending(state));
+        }
+    }
+    if status.exit_code != ServiceExitCode::NO_ERROR
+        && !matches!(state, ServiceState::StopPending | ServiceState::Stopped)
+    {
+        return Err(StatusError::ExitCodeOutsideStop(state, status.exit_code));
+    }
+    if state == ServiceState::StartPending && !status.controls_accepted.is_empty() {
+        return Err(StatusError::ControlsAcceptedWhileStarting(
+            status.controls_accepted,
+        ));
+    }
+    Ok(())
+}
+
+/// Reports the status of a service, allowing the legal state transitions only.
+///
+/// The reporter starts in the [`ServiceState::StartPending`] state the system puts a launched
+/// service in. Rejected statuses are returned as [`Error::Status`] without being reported.
+#[derive(Debug)]
+pub struct StatusReporter<H> {
+    handle: H,
+    controls_accepted: ServiceControlAccept,
+    status: ServiceStatus,
+}
+
+impl<H: StatusBackend> StatusReporter<H> {
+    /// Create a reporter accepting the `controls_accepted` in the states that accept controls.
+    pub fn new(
+        handle: H,
+        service_type: ServiceType,
+        controls_accepted: ServiceControlAccept,
+    ) -> Self {
+        StatusReporter {
+            handle,
+            controls_accepted,
+            status: ServiceStatus {
+                service_type,
+                current_state: ServiceState::StartPending,
+                controls_accepted: ServiceControlAccept::empty(),
+                exit_code: ServiceExitCode::NO_ERROR,
+                checkpoint: 0,
+                wait_hint: Duration::ZERO,
+                process_id: None,
+            },
+        }
+    }
+
+    /// The current state of the service.
+    pub fn state(&self) -> ServiceState {
+        self.status.current_state
+    }
+
+    /// The last status reported.
+    pub fn status(&self) -> &ServiceStatus {
+        &self.status
+    }
+
+    /// The handle used to report the status.
+    pub fn handle(&self) -> &H {
+        &self.handle
+    }
+
+    /// Report the [`ServiceState::StartPending`] state, expecting the next update within
+    /// `wait_hint`.
+    pub fn start_pending(&mut self, wait_hint: Duration) -> Result<()> {
+        self.report(
+            ServiceState::StartPending,
+            wait_hint,
+            ServiceExitCode::NO_ERROR,
+        )
+    }
+
+    /// Report the [`ServiceState::Running`] state.
+    pub fn running(&mut self) -> Result<()> {
+        self.report(
+            ServiceState::Running,
+            Duration::ZERO,
+            ServiceExitCode::NO_ERROR,
+        )
+    }
+
+    /// Report the [`ServiceState::PausePending`] state, expecting the next update within
+    /// `wait_hint`.
+    pub fn pause_pending(&mut self, wait_hint: Duration) -> Result<()> {
+        self.report(
+            ServiceState::PausePending,
+            wait_hint,
+            ServiceExitCode::NO_ERROR,
+        )
+    }
+
+    /// Report the [`ServiceState::Paused`] state.
+    pub fn paused(&mut self) -> Result<()> {
+        self.report(
+            ServiceState::Paused,
+            Duration::ZERO,
+            ServiceExitCode::NO_ERROR,
+        )
+    }
+
+    /// Report the [`ServiceState::ContinuePending`] state, expecting the next update within
+    /// `wait_hint`.
+    pub fn continue_pending(&mut self, wait_hint: Duration) -> Result<()> {
+        self.report(
+            ServiceState::ContinuePending,
+            wait_hint,
+            ServiceExitCode::NO_ERROR,
+        )
+    }
+
+    /// Report the [`ServiceState::StopPending`] state, expecting the next update within
+    /// `wait_hint`.
+    pub fn stop_pending(&mut self, wait_hint: Duration) -> Result<()> {
+        self.report(
+            ServiceState::StopPending,
+            wait_hint,
+            ServiceExitCode::NO_ERROR,
+        )
+    }
+
+    /// Report the [`ServiceState::Stopped`] state with the exit code telling why the service
+    /// stopped.
+    pub fn stopped(&mut self, exit_code: ServiceExitCode) -> Result<()> {
+        self.report(ServiceState::Stopped, Duration::ZERO, exit_code)
+    }
+
+    /// Report progress in the current pending state by increasing the checkpoint.
+    pub fn checkpoint(&mut self) -> Result<()> {
+        let state = self.status.current_state;
+        if !is_pending(state) {
+            return Err(Error::Status(StatusError::CheckpointOutsidePending(state)));
+        }
+        let status = ServiceStatus {
+            checkpoint: self.status.checkpoint + 1,
+            ..self.status.clone()
+        };
+        self.send(status)
+    }
+
+    fn report(
+        &mut self,
+        state: ServiceState,
+        wait_hint: Duration,
+        exit_code: ServiceExitCode,
+    ) -> Result<()> {
+        let from = self.status.current_state;
+        if !is_legal_transition(from, state) {
+            return Err(Error::Status(StatusError::IllegalTransition {
+                from,
+                to: state,
+            }));
+        }
+        let checkpoint = match (is_pending(state), from == state) {
+            (true, true) => self.status.checkpoint + 1,
+            (true, false) => 1,
+            (false, _) => 0,
+        };
+        let controls_accepted = match state {
+            ServiceState::StartPending | ServiceState::StopPending | ServiceState::Stopped => {
+                ServiceControlAccept::empty()
+            }
+            _ => self.controls_accepted,
+        };
+        self.send(ServiceStatus {
+            service_type: self.status.service_type,
+            current_state: state,
+            controls_accepted,
+            exit_code,
+            checkpoint,
+            wait_hint,
+            process_id: None,
+        })
+    }
+
+    fn send(&mut self, status: ServiceStatus) -> Result<()> {
+        validate_status(&status).map_err(Error::Status)?;
+        self.handle.set_service_status(status.clone())?;
+        self.status = status;
+        Ok(())
+    }
+}
+
+impl<H: StatusBackend + Clone + Send + 'static> StatusReporter<H> {
+    /// Report the pending `state` and keep increasing its checkpoint from a background thread,
+    /// twice per `wait_hint` but at most once per second, until the returned guard is finished or
+    /// dropped.
+    ///
+    /// Dropping the guard leaves the service in the pending state, for example to report
+    /// [`ServiceState::Stopped`] with an exit code after a failed start.
+    pub fn pending(
+        &mut self,
+        state: ServiceState,
+        wait_hint: Duration,
+    ) -> Result<PendingGuard<'_, H>> {
+        self.pending_with_min_interval(state, wait_hint, MIN_HEARTBEAT_INTERVAL)
+    }
+
+    fn pending_with_min_interval(
+        &mut self,
+        state: ServiceState,
+        wait_hint: Duration,
+        min_interval: Duration,
+    ) -> Result<PendingGuard<'_, H>> {
+        if !is_pending(state) {
+            return Err(Error::Status(StatusError::IllegalTransition {
+                from: self.status.current_state,
+                to: state,
+            }));
+        }
+        self.report(state, wait_hint, ServiceExitCode::NO_ERROR)?;
+
+        let heartbeat = Arc::new(Heartbeat {
+            state: Mutex::new(HeartbeatState {
+                status: self.status.clone(),
+                progressed: false,
+                stopped: false,
+                error: None,
+            }),
+            wake_up: Condvar::new(),
+            min_interval,
+        });
+        let thread = thread::spawn({
+            let heartbeat = heartbeat.clone();
+            let handle = self.handle.clone();
+            move || heartbeat.run(&handle)
+        });
+        Ok(PendingGuard {
+            reporter: self,
+            heartbeat,
+            thread: Some(thread),
+        })
+    }
+}
+
+/// Keeps a service in a pending state alive by increasing the checkpoint periodically.
+///
+/// Created with [`StatusReporter::pending`]. Call [`PendingGuard::finish`] to report the state
+/// the pending state leads to.
+pub struct PendingGuard<'a, H: StatusBackend> {
+    reporter: &'a mut StatusReporter<H>,
+    heartbeat: Arc<Heartbeat>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+struct Heartbeat {
+    state: Mutex<HeartbeatState>,
+    wake_up: Condvar,
+    min_interval: Duration,
+}
+
+struct HeartbeatState {
+    status: ServiceStatus,
+    progressed: bool,
+    stopped: bool,
+    error: Option<Error>,
+}
+
+impl Heartbeat {
+    fn run(&self, handle: &impl StatusBackend) {
+        let mut state = lock(&self.state);
+        while !state.stopped {
+            let interval = (state.status.wait_hint / 2).max(self.min_interval);
+            state = self
+                .wake_up
+                .wait_timeout(state, interval)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+            if state.stopped {
+                break;
+            }
+            // The application reported progress itself, so start a new interval.
+            if std::mem::take(&mut state.progressed) {
+                continue;
+            }
+            if let Err(e) = state.advance(handle, None) {
+                state.error = Some(e);
+                break;
+            }
+        }
+    }
+}
+
+impl HeartbeatState {
+    fn advance(&mut self, handle: &impl StatusBackend, wait_hint: Option<Duration>) -> Result<()> {
+        let status = ServiceStatus {
+            checkpoint: self.status.checkpoint + 1,
+            wait_hint: wait_hint.unwrap_or(self.status.wait_hint),
+            ..self.status.clone()
+        };
+        handle.set_service_status(status.clone())?;
+        self.status = status;
+        Ok(())
+    }
+}
+
+/// The shortest interval between the checkpoints reported by [`PendingGuard`], which keeps a
+/// short or zero wait hint from flooding the service control manager with status updates.
+const MIN_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+impl<H: StatusBackend> PendingGuard<'_, H> {
+    /// The pending state reported.
+    pub fn state(&self) -> ServiceState {
+        self.reporter.status.current_state
+    }
+
+    /// Report progress right away, postponing the next automatic checkpoint.
+    pub fn progress(&self) -> Result<()> {
+        self.advance(None)
+    }
+
+    /// Report progress right away and expect the next update within `wait_hint` from now on.
+    pub fn progress_with_wait_hint(&self, wait_hint: Duration) -> Result<()> {
+        self.advance(Some(wait_hint))
+    }
+
+    /// Stop increasing the checkpoint and report the state the pending state leads to:
+    /// [`ServiceState::Running`] after starting or continuing, [`ServiceState::Paused`] after
+    /// pausing and [`ServiceState::Stopped`] after stopping.
+    ///
+    /// Fails if reporting a checkpoint failed in the background.
+    pub fn finish(mut self) -> Result<()> {
+        if let Some(e) = self.stop() {
+            return Err(e);
+        }
+        let target = match self.state() {
+            ServiceState::PausePending => ServiceState::Paused,
+            ServiceState::StopPending => ServiceState::Stopped,
+            _ => ServiceState::Running,
+        };
+        self.reporter
+            .report(target, Duration::ZERO, ServiceExitCode::NO_ERROR)
+    }
+
+    fn advance(&self, wait_hint: Option<Duration>) -> Result<()> {
+        let mut state = lock(&self.heartbeat.state);
+        state.advance(&self.reporter.handle, wait_hint)?;
+        state.progressed = true;
+        self.heartbeat.wake_up.notify_one();
+        Ok(())
+    }
+
+    /// Stop the background thread and take over the last reported status. Returns the error
+    /// that stopped the thread early, if any.
+    fn stop(&mut self) -> Option<Error> {
+        let thread = self.thread.take()?;
+        lock(&self.heartbeat.state).stopped = true;
+        self.heartbeat.wake_up.notify_one();
+        let _ = thread.join();
+
+        let mut state = lock(&self.heartbeat.state);
+        self.reporter.status = state.status.clone();
+        state.error.take()
+    }
+}
+
+impl<H: StatusBackend> Drop for PendingGuard<'_, H> {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::fake::status_handle;
+
+    const STATES: [ServiceState; 7] = [
+        ServiceState::Stopped,
+        ServiceState::StartPending,
+        ServiceState::StopPending,
+        ServiceState::Running,
+        ServiceState::ContinuePending,
+        ServiceState::PausePending,
+        ServiceState::Paused,
+    ];
+
+    #[test]
+    fn test_transition_table() {
+        use ServiceState::*;
+
+        let legal: Vec<(ServiceState, ServiceState)> = STATES
+            .iter()
+            .flat_map(|&from| STATES.iter().map(move |&to| (from, to)))
+            .filter(|&(from, to)| is_legal_transition(from, to))
+            .collect();
+        assert_eq!(
+            legal,
+            [
+                (Stopped, StartPending),
+                (StartPending, Stopped),
+                (StartPending, StartPending),
+                (StartPending, StopPending),
+                (StartPending, Running),
+                (StopPending, Stopped),
+                (StopPending, StopPending),
+                (Running, StopPending),
+                (Running, Running),
+                (Running, PausePending),
+                (ContinuePending, StopPending),
+                (ContinuePending, Running),
+                (ContinuePending, ContinuePending),
+                (PausePending, StopPending),
+                (PausePending, PausePending),
+                (PausePending, Paused),
+                (Paused, StopPending),
+                (Paused, ContinuePending),
+                (Paused, Paused),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_status() {
+        let running = ServiceStatus {
+            service_type: ServiceType::OWN_PROCESS,
+            current_state: ServiceState::Running,
+            controls_accepted: ServiceControlAccept::STOP,
+            exit_code: ServiceExitCode::NO_ERROR,
+            checkpoint: 0,
+            wait_hint: Duration::ZERO,
+            process_id: None,
+        };
+        assert_eq!(validate_status(&running), Ok(()));
+        assert_eq!(
+            validate_status(&ServiceStatus {
+                checkpoint: 1,
+                ..running.clone()
+            }),
+            Err(StatusError::CheckpointOutsidePending(ServiceState::Running))
+        );
+        assert_eq!(
+            validate_status(&ServiceStatus {
+                wait_hint: Duration::from_secs(1),
+                ..running.clone()
+            }),
+            Err(StatusError::WaitHintOutsidePending(ServiceState::Running))
+        );
+        assert_eq!(
+            validate_status(&ServiceStatus {
+                exit_code: ServiceExitCode::ServiceSpecific(1),
+                ..running.clone()
+            }),
+            Err(StatusError::ExitCodeOutsideStop(
+                ServiceState::Running,
+                ServiceExitCode::ServiceSpecific(1)
+            ))
+        );
+        assert_eq!(
+            validate_status(&ServiceStatus {
+                current_state: ServiceState::StartPending,
+                checkpoint: 1,
+                wait_hint: Duration::from_secs(1),
+                ..running
+            }),
+            Err(StatusError::ControlsAcceptedWhileStarting(
+                ServiceControlAccept::STOP
+            ))
+        );
+    }
+
+    #[test]
+    fn test_status_reporter() {
+        let handle = status_handle();
+        let mut reporter = StatusReporter::new(
+            handle.clone(),
+            ServiceType::OWN_PROCESS,
+            ServiceControlAccept::STOP | ServiceControlAccept::PAUSE_CONTINUE,
+        );
+        reporter.start_pending(Duration::from_secs(5)).unwrap();
+        reporter.checkpoint().unwrap();
+        reporter.running().unwrap();
+        assert!(matches!(
+            reporter.checkpoint(),
+            Err(Error::Status(StatusError::CheckpointOutsidePending(
+                ServiceState::Running
+            )))
+        ));
+        assert!(matches!(
+            reporter.stopped(ServiceExitCode::NO_ERROR),
+            Err(Error::Status(StatusError::IllegalTransition {
+                from: ServiceState::Running,
+                to: ServiceState::Stopped
+            }))
+        ));
+        reporter.stop_pending(Duration::from_secs(3)).unwrap();
+        reporter
+            .stopped(ServiceExitCode::ServiceSpecific(2))
+            .unwrap();
+
+        let reported: Vec<_> = handle
+            .reported_statuses()
+            .into_iter()
+            .map(|status| {
+                (
+                    status.current_state,
+                    status.controls_accepted,
+                    status.exit_code,
+                    status.checkpoint,
+                    status.wait_hint,
+                )
+            })
+            .collect();
+        let accepted = ServiceControlAccept::STOP | ServiceControlAccept::PAUSE_CONTINUE;
+        let none = ServiceControlAccept::empty();
+        let no_error = ServiceExitCode::NO_ERROR;
+        assert_eq!(
+            reported,
+            [
+                (
+                    ServiceState::StartPending,
+                    none,
+                    no_error,
+                    1,
+                    Duration::from_secs(5)
+                ),
+                (
+                    ServiceState::StartPending,
+                    none,
+                    no_error,
+                    2,
+                    Duration::from_secs(5)
+                ),
+                (ServiceState::Running, accepted, no_error, 0, Duration::ZERO),
+                (
+                    ServiceState::StopPending,
+                    none,
+                    no_error,
+                    1,
+                    Duration::from_secs(3)
+                ),
+                (
+                    ServiceState::Stopped,
+                    none,
+                    ServiceExitCode::ServiceSpecific(2),
+                    0,
+                    Duration::ZERO
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pending_guard() {
+        let handle = status_handle();
+        let mut reporter = StatusReporter::new(
+            handle.clone(),
+            ServiceType::OWN_PROCESS,
+            ServiceControlAccept::STOP,
+        );
+
+        let guard = reporter
+            .pending_with_min_interval(
+                ServiceState::StartPending,
+                Duration::from_millis(20),
+                Duration::from_millis(10),
+            )
+            .unwrap();
+        thread::sleep(Duration::from_millis(100));
+        guard
+            .progress_with_wait_hint(Duration::from_secs(60))
+            .unwrap();
+        guard.finish().unwrap();
+        assert_eq!(reporter.state(), ServiceState::Running);
+
+        let reported = handle.reported_statuses();
+        let (running, start_pending) = reported.split_last().unwrap();
+        assert_eq!(running.current_state, ServiceState::Running);
+        assert_eq!(running.controls_accepted, ServiceControlAccept::STOP);
+        // The checkpoint was increased in the background before the final progress report.
+        assert!(start_pending.len() > 2);
+        for (i, status) in start_pending.iter().enumerate() {
+            assert_eq!(status.current_state, ServiceState::StartPending);
+            assert_eq!(status.checkpoint, i as u32 + 1);
+        }
+        assert_eq!(
+            start_pending.last().unwrap().wait_hint,
+            Duration::from_secs(60)
+        );
+
+        // Dropping the guard stays in the pending state.
+        drop(
+            reporter
+                .pending(ServiceState::StopPending, Duration::from_secs(60))
+                .unwrap(),
+        );
+        assert_eq!(reporter.state(), ServiceState::StopPending);
+        reporter
+            .stopped(ServiceExitCode::ServiceSpecific(1))
+            .unwrap();
+        assert!(matches!(
+            reporter.pending(ServiceState::Running, Duration::from_secs(1)),
+            Err(Error::Status(StatusError::IllegalTransition { .. }))
+        ));
+    }
+}
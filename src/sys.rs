@@ -0,0 +1,251 @@
+//! Win32 constants used by the platform neutral data model.
+//!
+//! `windows-sys` is only available on Windows, so the constants that give the enums and bitflags
+//! their numeric values are defined here for every target. The modules mirror the layout of
+//! `windows-sys` and, on Windows, re-export the rest of the corresponding `windows-sys` module, so
+//! that FFI types and functions are reachable through the same path. The locally defined
+//! constants shadow the re-exported ones and are checked against them in tests.
+
+// Not all constants are used outside of Windows, and the names follow `windows-sys`.
+#![allow(dead_code, non_snake_case, non_upper_case_globals)]
+
+macro_rules! win32_modules {
+    ($(
+        $module:ident = $($windows_sys_path:ident)::+ {
+            $($name:ident: $type:ty = $value:expr;)*
+        }
+    )*) => {
+        $(
+            pub mod $module {
+                #[cfg(windows)]
+                #[allow(unused_imports)]
+                pub use windows_sys::$($windows_sys_path)::+::*;
+
+                $(pub const $name: $type = $value;)*
+
+                #[cfg(all(test, windows))]
+                mod tests {
+                    use windows_sys::$($windows_sys_path)::+ as windows_sys_module;
+
+                    #[test]
+                    fn test_constants_match_windows_sys() {
+                        $(assert_eq!(super::$name, windows_sys_module::$name, stringify!($name));)*
+                    }
+                }
+            }
+        )*
+    };
+}
+
+win32_modules! {
+    FileSystem = Win32::Storage::FileSystem {
+        DELETE: u32 = 0x0001_0000;
+        READ_CONTROL: u32 = 0x0002_0000;
+        WRITE_DAC: u32 = 0x0004_0000;
+        WRITE_OWNER: u32 = 0x0008_0000;
+    }
+
+    Foundation = Win32::Foundation {
+        NO_ERROR: u32 = 0;
+        ERROR_NOT_SUPPORTED: u32 = 50;
+        ERROR_INVALID_PARAMETER: u32 = 87;
+        ERROR_INVALID_IMAGE_HASH: u32 = 577;
+        ERROR_SERVICE_SPECIFIC_ERROR: u32 = 1066;
+    }
+
+    Power = Win32::System::Power {
+        PoAc: i32 = 0;
+        PoDc: i32 = 1;
+        PoHot: i32 = 2;
+        PowerUserPresent: i32 = 0;
+        PowerUserInactive: i32 = 2;
+    }
+
+    Services = Win32::System::Services {
+        SC_ACTION_NONE: i32 = 0;
+        SC_ACTION_RESTART: i32 = 1;
+        SC_ACTION_REBOOT: i32 = 2;
+        SC_ACTION_RUN_COMMAND: i32 = 3;
+
+        SC_MANAGER_CONNECT: u32 = 0x0001;
+        SC_MANAGER_CREATE_SERVICE: u32 = 0x0002;
+        SC_MANAGER_ENUMERATE_SERVICE: u32 = 0x0004;
+        SC_MANAGER_ALL_ACCESS: u32 = 0x000F_003F;
+
+        SERVICE_ACCEPT_STOP: u32 = 0x0001;
+        SERVICE_ACCEPT_PAUSE_CONTINUE: u32 = 0x0002;
+        SERVICE_ACCEPT_SHUTDOWN: u32 = 0x0004;
+        SERVICE_ACCEPT_PARAMCHANGE: u32 = 0x0008;
+        SERVICE_ACCEPT_NETBINDCHANGE: u32 = 0x0010;
+        SERVICE_ACCEPT_HARDWAREPROFILECHANGE: u32 = 0x0020;
+        SERVICE_ACCEPT_POWEREVENT: u32 = 0x0040;
+        SERVICE_ACCEPT_SESSIONCHANGE: u32 = 0x0080;
+        SERVICE_ACCEPT_PRESHUTDOWN: u32 = 0x0100;
+        SERVICE_ACCEPT_TIMECHANGE: u32 = 0x0200;
+        SERVICE_ACCEPT_TRIGGEREVENT: u32 = 0x0400;
+
+        SERVICE_ACTIVE: u32 = 1;
+        SERVICE_INACTIVE: u32 = 2;
+        SERVICE_STATE_ALL: u32 = 3;
+
+        SERVICE_QUERY_CONFIG: u32 = 0x0001;
+        SERVICE_CHANGE_CONFIG: u32 = 0x0002;
+        SERVICE_QUERY_STATUS: u32 = 0x0004;
+        SERVICE_ENUMERATE_DEPENDENTS: u32 = 0x0008;
+        SERVICE_START: u32 = 0x0010;
+        SERVICE_STOP: u32 = 0x0020;
+        SERVICE_PAUSE_CONTINUE: u32 = 0x0040;
+        SERVICE_INTERROGATE: u32 = 0x0080;
+        SERVICE_USER_DEFINED_CONTROL: u32 = 0x0100;
+        SERVICE_ALL_ACCESS: u32 = 0x000F_01FF;
+
+        SERVICE_CONFIG_TRIGGER_INFO: u32 = 8;
+
+        SERVICE_BOOT_START: u32 = 0;
+        SERVICE_SYSTEM_START: u32 = 1;
+        SERVICE_AUTO_START: u32 = 2;
+        SERVICE_DEMAND_START: u32 = 3;
+        SERVICE_DISABLED: u32 = 4;
+
+        SERVICE_CONTROL_STOP: u32 = 1;
+        SERVICE_CONTROL_PAUSE: u32 = 2;
+        SERVICE_CONTROL_CONTINUE: u32 = 3;
+        SERVICE_CONTROL_INTERROGATE: u32 = 4;
+        SERVICE_CONTROL_SHUTDOWN: u32 = 5;
+        SERVICE_CONTROL_PARAMCHANGE: u32 = 6;
+        SERVICE_CONTROL_NETBINDADD: u32 = 7;
+        SERVICE_CONTROL_NETBINDREMOVE: u32 = 8;
+        SERVICE_CONTROL_NETBINDENABLE: u32 = 9;
+        SERVICE_CONTROL_NETBINDDISABLE: u32 = 10;
+        SERVICE_CONTROL_HARDWAREPROFILECHANGE: u32 = 12;
+        SERVICE_CONTROL_POWEREVENT: u32 = 13;
+        SERVICE_CONTROL_SESSIONCHANGE: u32 = 14;
+        SERVICE_CONTROL_PRESHUTDOWN: u32 = 15;
+        SERVICE_CONTROL_TIMECHANGE: u32 = 16;
+        SERVICE_CONTROL_TRIGGEREVENT: u32 = 32;
+
+        SERVICE_ERROR_IGNORE: u32 = 0;
+        SERVICE_ERROR_NORMAL: u32 = 1;
+        SERVICE_ERROR_SEVERE: u32 = 2;
+        SERVICE_ERROR_CRITICAL: u32 = 3;
+
+        SERVICE_KERNEL_DRIVER: u32 = 0x0001;
+        SERVICE_FILE_SYSTEM_DRIVER: u32 = 0x0002;
+        SERVICE_DRIVER: u32 = 0x000B;
+        SERVICE_WIN32_OWN_PROCESS: u32 = 0x0010;
+        SERVICE_WIN32_SHARE_PROCESS: u32 = 0x0020;
+        SERVICE_WIN32: u32 = 0x0030;
+        SERVICE_USER_OWN_PROCESS: u32 = 0x0050;
+        SERVICE_USER_SHARE_PROCESS: u32 = 0x0060;
+
+        SERVICE_NOTIFY_STOPPED: u32 = 0x0001;
+        SERVICE_NOTIFY_START_PENDING: u32 = 0x0002;
+        SERVICE_NOTIFY_STOP_PENDING: u32 = 0x0004;
+        SERVICE_NOTIFY_RUNNING: u32 = 0x0008;
+        SERVICE_NOTIFY_CONTINUE_PENDING: u32 = 0x0010;
+        SERVICE_NOTIFY_PAUSE_PENDING: u32 = 0x0020;
+        SERVICE_NOTIFY_PAUSED: u32 = 0x0040;
+        SERVICE_NOTIFY_CREATED: u32 = 0x0080;
+        SERVICE_NOTIFY_DELETED: u32 = 0x0100;
+        SERVICE_NOTIFY_DELETE_PENDING: u32 = 0x0200;
+
+        SERVICE_STOPPED: u32 = 1;
+        SERVICE_START_PENDING: u32 = 2;
+        SERVICE_STOP_PENDING: u32 = 3;
+        SERVICE_RUNNING: u32 = 4;
+        SERVICE_CONTINUE_PENDING: u32 = 5;
+        SERVICE_PAUSE_PENDING: u32 = 6;
+        SERVICE_PAUSED: u32 = 7;
+
+        SERVICE_LAUNCH_PROTECTED_NONE: u32 = 0;
+        SERVICE_LAUNCH_PROTECTED_WINDOWS: u32 = 1;
+        SERVICE_LAUNCH_PROTECTED_WINDOWS_LIGHT: u32 = 2;
+        SERVICE_LAUNCH_PROTECTED_ANTIMALWARE_LIGHT: u32 = 3;
+
+        SERVICE_STOP_REASON_FLAG_UNPLANNED: u32 = 0x1000_0000;
+        SERVICE_STOP_REASON_FLAG_CUSTOM: u32 = 0x2000_0000;
+        SERVICE_STOP_REASON_FLAG_PLANNED: u32 = 0x4000_0000;
+
+        SERVICE_STOP_REASON_MAJOR_OTHER: u32 = 0x0001_0000;
+        SERVICE_STOP_REASON_MAJOR_HARDWARE: u32 = 0x0002_0000;
+        SERVICE_STOP_REASON_MAJOR_OPERATINGSYSTEM: u32 = 0x0003_0000;
+        SERVICE_STOP_REASON_MAJOR_SOFTWARE: u32 = 0x0004_0000;
+        SERVICE_STOP_REASON_MAJOR_APPLICATION: u32 = 0x0005_0000;
+        SERVICE_STOP_REASON_MAJOR_NONE: u32 = 0x0006_0000;
+        SERVICE_STOP_REASON_MAJOR_MIN_CUSTOM: u32 = 0x0040_0000;
+        SERVICE_STOP_REASON_MAJOR_MAX_CUSTOM: u32 = 0x00FF_0000;
+
+        SERVICE_STOP_REASON_MINOR_OTHER: u32 = 1;
+        SERVICE_STOP_REASON_MINOR_MAINTENANCE: u32 = 2;
+        SERVICE_STOP_REASON_MINOR_INSTALLATION: u32 = 3;
+        SERVICE_STOP_REASON_MINOR_UPGRADE: u32 = 4;
+        SERVICE_STOP_REASON_MINOR_RECONFIG: u32 = 5;
+        SERVICE_STOP_REASON_MINOR_HUNG: u32 = 6;
+        SERVICE_STOP_REASON_MINOR_UNSTABLE: u32 = 7;
+        SERVICE_STOP_REASON_MINOR_DISK: u32 = 8;
+        SERVICE_STOP_REASON_MINOR_NETWORKCARD: u32 = 9;
+        SERVICE_STOP_REASON_MINOR_ENVIRONMENT: u32 = 10;
+        SERVICE_STOP_REASON_MINOR_HARDWARE_DRIVER: u32 = 11;
+        SERVICE_STOP_REASON_MINOR_OTHERDRIVER: u32 = 12;
+        SERVICE_STOP_REASON_MINOR_SERVICEPACK: u32 = 13;
+        SERVICE_STOP_REASON_MINOR_SOFTWARE_UPDATE: u32 = 14;
+        SERVICE_STOP_REASON_MINOR_SECURITYFIX: u32 = 15;
+        SERVICE_STOP_REASON_MINOR_SECURITY: u32 = 16;
+        SERVICE_STOP_REASON_MINOR_NETWORK_CONNECTIVITY: u32 = 17;
+        SERVICE_STOP_REASON_MINOR_WMI: u32 = 18;
+        SERVICE_STOP_REASON_MINOR_SERVICEPACK_UNINSTALL: u32 = 19;
+        SERVICE_STOP_REASON_MINOR_SOFTWARE_UPDATE_UNINSTALL: u32 = 20;
+        SERVICE_STOP_REASON_MINOR_SECURITYFIX_UNINSTALL: u32 = 21;
+        SERVICE_STOP_REASON_MINOR_MMC: u32 = 22;
+        SERVICE_STOP_REASON_MINOR_NONE: u32 = 23;
+        SERVICE_STOP_REASON_MINOR_MEMOTYLIMIT: u32 = 24;
+        SERVICE_STOP_REASON_MINOR_MIN_CUSTOM: u32 = 0x0100;
+        SERVICE_STOP_REASON_MINOR_MAX_CUSTOM: u32 = 0xFFFF;
+
+        SERVICE_TRIGGER_TYPE_DEVICE_INTERFACE_ARRIVAL: u32 = 1;
+        SERVICE_TRIGGER_TYPE_IP_ADDRESS_AVAILABILITY: u32 = 2;
+        SERVICE_TRIGGER_TYPE_DOMAIN_JOIN: u32 = 3;
+        SERVICE_TRIGGER_TYPE_FIREWALL_PORT_EVENT: u32 = 4;
+        SERVICE_TRIGGER_TYPE_GROUP_POLICY: u32 = 5;
+        SERVICE_TRIGGER_TYPE_NETWORK_ENDPOINT: u32 = 6;
+        SERVICE_TRIGGER_TYPE_CUSTOM: u32 = 20;
+
+        SERVICE_TRIGGER_ACTION_SERVICE_START: u32 = 1;
+        SERVICE_TRIGGER_ACTION_SERVICE_STOP: u32 = 2;
+
+        SERVICE_TRIGGER_DATA_TYPE_BINARY: u32 = 1;
+        SERVICE_TRIGGER_DATA_TYPE_STRING: u32 = 2;
+        SERVICE_TRIGGER_DATA_TYPE_LEVEL: u32 = 3;
+        SERVICE_TRIGGER_DATA_TYPE_KEYWORD_ANY: u32 = 4;
+        SERVICE_TRIGGER_DATA_TYPE_KEYWORD_ALL: u32 = 5;
+    }
+
+    SystemServices = Win32::System::SystemServices {
+        SERVICE_INTERACTIVE_PROCESS: u32 = 0x0100;
+        PowerMonitorOff: i32 = 0;
+        PowerMonitorOn: i32 = 1;
+        PowerMonitorDim: i32 = 2;
+    }
+
+    Threading = Win32::System::Threading {
+        INFINITE: u32 = 0xFFFF_FFFF;
+    }
+
+    WindowsAndMessaging = Win32::UI::WindowsAndMessaging {
+        DBT_QUERYCHANGECONFIG: u32 = 0x0017;
+        DBT_CONFIGCHANGED: u32 = 0x0018;
+        DBT_CONFIGCHANGECANCELED: u32 = 0x0019;
+
+        WTS_CONSOLE_CONNECT: u32 = 0x1;
+        WTS_CONSOLE_DISCONNECT: u32 = 0x2;
+        WTS_REMOTE_CONNECT: u32 = 0x3;
+        WTS_REMOTE_DISCONNECT: u32 = 0x4;
+        WTS_SESSION_LOGON: u32 = 0x5;
+        WTS_SESSION_LOGOFF: u32 = 0x6;
+        WTS_SESSION_LOCK: u32 = 0x7;
+        WTS_SESSION_UNLOCK: u32 = 0x8;
+        WTS_SESSION_REMOTE_CONTROL: u32 = 0x9;
+        WTS_SESSION_CREATE: u32 = 0xA;
+        WTS_SESSION_TERMINATE: u32 = 0xB;
+    }
+}
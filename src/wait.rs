@@ -0,0 +1,1060 @@
+//! Waiting for a service to reach a state.
+//!
+//! Control requests return as soon as the service control manager accepts them, while the service
+//! moves through the pending states on its own. [`StateWaiter`] decides how to poll the status in
+//! the meantime, following the guidance in the documentation of `StartService` and
+//! `ControlService`:
+//!
+//! * The status is polled every tenth of the [`wait_hint`], but no more often than every second
+//!   and no less often than every ten seconds.
+//! * A change of state or an increase of the [`checkpoint`] counts as progress. A service that
+//!   makes no progress within its wait hint is considered hung.
+//!
+//! The waiter is a pure state machine that is fed statuses and the current time, and the polling
+//! loop in [`wait_for_state`] takes its time from a [`Clock`], so both can be tested without
+//! waiting for real.
+//!
+//! On top of that, [`StateDriver`] decides how to drive a service to the running or stopped state
+//! from whatever state it is in. It is used by [`start_and_wait`], [`stop_and_wait`] and
+//! [`restart`], and by their async counterparts. [`stop_dependents`] and
+//! [`restart_with_dependents`] take care of the services that depend on it.
+//!
+//! [`wait_hint`]: ServiceStatus::wait_hint
+//! [`checkpoint`]: ServiceStatus::checkpoint
+
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::backend::{ScmBackend, ServiceBackend};
+use crate::service::{ServiceAccess, ServiceControl, ServiceExitCode, ServiceState, ServiceStatus};
+use crate::service_manager::ServiceActiveState;
+use crate::{Error, Result};
+
+/// The shortest interval between status polls.
+const MIN_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The longest interval between status polls.
+const MAX_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// A source of time for the polling loop.
+pub trait Clock {
+    fn now(&self) -> Instant;
+
+    fn sleep(&self, duration: Duration);
+}
+
+/// The system clock, which sleeps the current thread.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration)
+    }
+}
+
+/// What to do after observing a status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WaitStep {
+    /// The service reached the target state.
+    Reached,
+
+    /// Sleep for the given duration and then observe the status again.
+    Sleep(Duration),
+}
+
+/// The reason a service did not reach the target state.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum WaitError {
+    /// The service settled in another state, for example it stopped while starting. The exit
+    /// code of the status tells why.
+    UnexpectedState {
+        target: ServiceState,
+        status: ServiceStatus,
+    },
+
+    /// The service did not advance its checkpoint within its wait hint.
+    Hung {
+        target: ServiceState,
+        status: ServiceStatus,
+    },
+
+    /// The service was still making progress when the timeout elapsed.
+    Timeout {
+        target: ServiceState,
+        status: ServiceStatus,
+    },
+}
+
+impl WaitError {
+    /// The state the service was expected to reach.
+    pub fn target(&self) -> ServiceState {
+        match self {
+            WaitError::UnexpectedState { target, .. }
+            | WaitError::Hung { target, .. }
+            | WaitError::Timeout { target, .. } => *target,
+        }
+    }
+
+    /// The last observed status of the service.
+    pub fn status(&self) -> &ServiceStatus {
+        match self {
+            WaitError::UnexpectedState { status, .. }
+            | WaitError::Hung { status, .. }
+            | WaitError::Timeout { status, .. } => status,
+        }
+    }
+
+    /// The exit code of a service that stopped with an error instead of reaching the target
+    /// state, for example because it failed to start.
+    pub fn exit_code(&self) -> Option<ServiceExitCode> {
+        match self {
+            WaitError::UnexpectedState { status, .. }
+                if status.current_state == ServiceState::Stopped
+                    && status.exit_code != ServiceExitCode::NO_ERROR =>
+            {
+                Some(status.exit_code)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl std::error::Error for WaitError {}
+
+impl fmt::Display for WaitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WaitError::UnexpectedState { target, status } => write!(
+                f,
+                "service entered the {:?} state instead of {:?} with exit code {:?}",
+                status.current_state, target, status.exit_code
+            ),
+            WaitError::Hung { target, status } => write!(
+                f,
+                "service made no progress in the {:?} state while waiting for {:?}",
+                status.current_state, target
+            ),
+            WaitError::Timeout { target, status } => write!(
+                f,
+                "timed out in the {:?} state while waiting for {:?}",
+                status.current_state, target
+            ),
+        }
+    }
+}
+
+/// A state machine deciding when to poll the status of a service that is expected to reach a
+/// target state.
+#[derive(Debug, Clone)]
+pub struct StateWaiter {
+    target: ServiceState,
+    deadline: Option<Instant>,
+    last_progress: Option<Progress>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Progress {
+    state: ServiceState,
+    checkpoint: u32,
+    observed_at: Instant,
+}
+
+impl StateWaiter {
+    /// Start waiting at `now` for the service to reach `target` within `timeout`.
+    ///
+    /// A timeout too long to be represented, such as `Duration::MAX`, never elapses.
+    pub fn new(target: ServiceState, timeout: Duration, now: Instant) -> Self {
+        Self::with_deadline(target, now.checked_add(timeout))
+    }
+
+    /// Wait for the service to reach `target` until the `deadline`, or forever if it is `None`.
+    fn with_deadline(target: ServiceState, deadline: Option<Instant>) -> Self {
+        StateWaiter {
+            target,
+            deadline,
+            last_progress: None,
+        }
+    }
+
+    /// The state the service is expected to reach.
+    pub fn target(&self) -> ServiceState {
+        self.target
+    }
+
+    /// Observe the status of the service at `now`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the service settles in a state other than the target, makes no
+    /// progress within its wait hint, or the timeout elapses. A wait hint of zero disables the
+    /// detection of hung services, leaving only the timeout.
+    pub fn observe(
+        &mut self,
+        status: &ServiceStatus,
+        now: Instant,
+    ) -> std::result::Result<WaitStep, WaitError> {
+        if status.current_state == self.target {
+            return Ok(WaitStep::Reached);
+        }
+        if !is_pending(status.current_state) {
+            return Err(WaitError::UnexpectedState {
+                target: self.target,
+                status: status.clone(),
+            });
+        }
+
+        match self.last_progress {
+            Some(progress)
+                if progress.state == status.current_state
+                    && progress.checkpoint >= status.checkpoint =>
+            {
+                if !status.wait_hint.is_zero()
+                    && now.saturating_duration_since(progress.observed_at) > status.wait_hint
+                {
+                    return Err(WaitError::Hung {
+                        target: self.target,
+                        status: status.clone(),
+                    });
+                }
+            }
+            _ => {
+                self.last_progress = Some(Progress {
+                    state: status.current_state,
+                    checkpoint: status.checkpoint,
+                    observed_at: now,
+                })
+            }
+        }
+
+        let remaining = self.deadline.map_or(Duration::MAX, |deadline| {
+            deadline.saturating_duration_since(now)
+        });
+        if remaining.is_zero() {
+            return Err(WaitError::Timeout {
+                target: self.target,
+                status: status.clone(),
+            });
+        }
+
+        let interval = (status.wait_hint / 10).clamp(MIN_POLL_INTERVAL, MAX_POLL_INTERVAL);
+        Ok(WaitStep::Sleep(interval.min(remaining)))
+    }
+}
+
+/// What to do after observing a status while driving a service to a state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DriveStep {
+    /// The service reached the target state.
+    Reached,
+
+    /// Start the service and then observe the status again.
+    Start,
+
+    /// Send the control to the service and then observe the status again.
+    Control(ServiceControl),
+
+    /// Sleep for the given duration and then observe the status again.
+    Sleep(Duration),
+}
+
+/// A state machine deciding how to drive a service to the running or stopped state.
+///
+/// A service in a pending state is given the chance to settle first. Once settled, a paused
+/// service is resumed instead of started, and a service in any other state is started or
+/// stopped. The waiting before and after that follows [`StateWaiter`], and `timeout` covers the
+/// whole operation.
+#[derive(Debug, Clone)]
+pub struct StateDriver {
+    target: ServiceState,
+    deadline: Option<Instant>,
+    waiter: Option<StateWaiter>,
+    controlled: bool,
+}
+
+impl StateDriver {
+    /// Start driving the service at `now` to `target`, which must be [`ServiceState::Running`]
+    /// or [`ServiceState::Stopped`], within `timeout`.
+    pub fn new(target: ServiceState, timeout: Duration, now: Instant) -> Self {
+        StateDriver {
+            target,
+            deadline: now.checked_add(timeout),
+            waiter: None,
+            controlled: false,
+        }
+    }
+
+    /// The state the service is driven to.
+    pub fn target(&self) -> ServiceState {
+        self.target
+    }
+
+    /// Observe the status of the service at `now`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the service does not settle from a pending state, or does not reach
+    /// the target state after it was started or controlled. See [`StateWaiter::observe`].
+    pub fn observe(
+        &mut self,
+        status: &ServiceStatus,
+        now: Instant,
+    ) -> std::result::Result<DriveStep, WaitError> {
+        if self.waiter.is_none() && !self.controlled {
+            if let Some(settled_state) = settled_state(status.current_state) {
+                self.waiter = Some(StateWaiter::with_deadline(settled_state, self.deadline));
+            }
+        }
+
+        if let Some(waiter) = &mut self.waiter {
+            match waiter.observe(status, now) {
+                Ok(WaitStep::Sleep(duration)) => return Ok(DriveStep::Sleep(duration)),
+                Ok(WaitStep::Reached) if self.controlled => return Ok(DriveStep::Reached),
+                Err(error) if self.controlled => return Err(error),
+                // The service settled, possibly in another state than it was heading for.
+                Ok(WaitStep::Reached) | Err(WaitError::UnexpectedState { .. }) => {}
+                Err(error) => return Err(error),
+            }
+        }
+
+        if status.current_state == self.target {
+            return Ok(DriveStep::Reached);
+        }
+        self.controlled = true;
+        self.waiter = Some(StateWaiter::with_deadline(self.target, self.deadline));
+        Ok(match (self.target, status.current_state) {
+            (ServiceState::Stopped, _) => DriveStep::Control(ServiceControl::Stop),
+            (_, ServiceState::Paused) => DriveStep::Control(ServiceControl::Continue),
+            _ => DriveStep::Start,
+        })
+    }
+}
+
+/// The state a service in a pending state settles in when all goes well.
+fn settled_state(state: ServiceState) -> Option<ServiceState> {
+    match state {
+        ServiceState::StartPending | ServiceState::ContinuePending => Some(ServiceState::Running),
+        ServiceState::StopPending => Some(ServiceState::Stopped),
+        ServiceState::PausePending => Some(ServiceState::Paused),
+        _ => None,
+    }
+}
+
+fn is_pending(state: ServiceState) -> bool {
+    matches!(
+        state,
+        ServiceState::StartPending
+            | ServiceState::StopPending
+            | ServiceState::ContinuePending
+            | ServiceState::PausePending
+    )
+}
+
+/// Poll the status of the service until it reaches the `target` state. Returns the final status.
+///
+/// See [`StateWaiter::observe`] for the conditions that end the wait early. The service must be
+/// open with the [`ServiceAccess::QUERY_STATUS`] access right.
+///
+/// [`ServiceAccess::QUERY_STATUS`]: crate::service::ServiceAccess::QUERY_STATUS
+pub fn wait_for_state(
+    service: &impl ServiceBackend,
+    target: ServiceState,
+    timeout: Duration,
+    clock: &impl Clock,
+) -> Result<ServiceStatus> {
+    let mut waiter = StateWaiter::new(target, timeout, clock.now());
+    loop {
+        let status = service.query_status()?;
+        match waiter.observe(&status, clock.now()).map_err(Error::Wait)? {
+            WaitStep::Reached => return Ok(status),
+            WaitStep::Sleep(duration) => clock.sleep(duration),
+        }
+    }
+}
+
+/// Start the service if needed and wait until it is running. Returns the final status.
+///
+/// A service in a pending state is given the chance to settle first, and a paused service is
+/// resumed instead of started. The `arguments` are passed to the service only when it is
+/// started. `timeout` covers the whole operation.
+///
+/// # Errors
+///
+/// Returns [`Error::Wait`] if the service does not reach the running state. When the service
+/// stops instead, [`WaitError::exit_code`] tells why it failed to start.
+///
+/// The service must be open with the [`ServiceAccess::START`] and [`ServiceAccess::QUERY_STATUS`]
+/// access rights, and [`ServiceAccess::PAUSE_CONTINUE`] to resume paused services.
+pub fn start_and_wait(
+    service: &impl ServiceBackend,
+    arguments: &[&OsStr],
+    timeout: Duration,
+    clock: &impl Clock,
+) -> Result<ServiceStatus> {
+    drive(service, ServiceState::Running, arguments, timeout, clock)
+}
+
+/// Stop the service if needed and wait until it is stopped. Returns the final status.
+///
+/// A service in a pending state is given the chance to settle first. `timeout` covers the whole
+/// operation. Stopping fails if other running services depend on the service, see
+/// [`stop_dependents`].
+///
+/// The service must be open with the [`ServiceAccess::STOP`] and [`ServiceAccess::QUERY_STATUS`]
+/// access rights.
+pub fn stop_and_wait(
+    service: &impl ServiceBackend,
+    timeout: Duration,
+    clock: &impl Clock,
+) -> Result<ServiceStatus> {
+    drive(service, ServiceState::Stopped, &[], timeout, clock)
+}
+
+fn drive(
+    service: &impl ServiceBackend,
+    target: ServiceState,
+    arguments: &[&OsStr],
+    timeout: Duration,
+    clock: &impl Clock,
+) -> Result<ServiceStatus> {
+    let mut driver = StateDriver::new(target, timeout, clock.now());
+    loop {
+        let status = service.query_status()?;
+        match driver.observe(&status, clock.now()).map_err(Error::Wait)? {
+            DriveStep::Reached => return Ok(status),
+            DriveStep::Start => service.start(arguments)?,
+            DriveStep::Control(control) => {
+                service.control(control)?;
+            }
+            DriveStep::Sleep(duration) => clock.sleep(duration),
+        }
+    }
+}
+
+/// Stop the service if it is running and start it again. Returns the final status.
+///
+/// `timeout` applies to stopping and starting separately. See [`stop_and_wait`] and
+/// [`start_and_wait`] for the required access rights.
+pub fn restart(
+    service: &impl ServiceBackend,
+    arguments: &[&OsStr],
+    timeout: Duration,
+    clock: &impl Clock,
+) -> Result<ServiceStatus> {
+    stop_and_wait(service, timeout, clock)?;
+    start_and_wait(service, arguments, timeout, clock)
+}
+
+/// Stop the active services that depend on the service `name`, directly or indirectly, and wait
+/// until they are stopped. Services depending on the dependents are stopped before them.
+///
+/// Returns the names of the stopped services in the order they were stopped. `timeout` applies
+/// to each service separately. If a dependent fails to stop, the ones stopped before it are left
+/// stopped, see [`restart_with_dependents`] for starting them again.
+///
+/// The service is opened with [`ServiceAccess::ENUMERATE_DEPENDENTS`], and its dependents with
+/// [`ServiceAccess::STOP`] and [`ServiceAccess::QUERY_STATUS`].
+pub fn stop_dependents(
+    manager: &impl ScmBackend,
+    name: &OsStr,
+    timeout: Duration,
+    clock: &impl Clock,
+) -> Result<Vec<OsString>> {
+    let (stopped, result) = stop_dependents_until_failure(manager, name, timeout, clock);
+    result.map(|()| stopped)
+}
+
+/// Same as [`stop_dependents`], but hands back the services stopped so far along with the error.
+fn stop_dependents_until_failure(
+    manager: &impl ScmBackend,
+    name: &OsStr,
+    timeout: Duration,
+    clock: &impl Clock,
+) -> (Vec<OsString>, Result<()>) {
+    let dependents = match manager
+        .open_service(name, ServiceAccess::ENUMERATE_DEPENDENTS)
+        .and_then(|service| service.dependent_services(ServiceActiveState::ACTIVE))
+    {
+        Ok(dependents) => dependents,
+        Err(error) => return (Vec::new(), Err(error)),
+    };
+
+    let mut stop_order = Vec::with_capacity(dependents.len());
+    for dependent in dependents {
+        let dependent = OsString::from(dependent.name);
+        let result = manager
+            .open_service(
+                &dependent,
+                ServiceAccess::STOP | ServiceAccess::QUERY_STATUS,
+            )
+            .and_then(|service| stop_and_wait(&service, timeout, clock));
+        if let Err(error) = result {
+            return (stop_order, Err(error));
+        }
+        stop_order.push(dependent);
+    }
+    (stop_order, Ok(()))
+}
+
+/// Restart the service `name` along with the services that depend on it.
+///
+/// The active dependents are stopped first as in [`stop_dependents`], then the service is
+/// restarted, and finally the dependents are started again in the reverse order. Returns the
+/// final status of the service. `timeout` applies to each step separately.
+///
+/// If a dependent fails to stop, the service is not restarted. Every dependent stopped so far is
+/// started again even if stopping, restarting or starting another dependent fails, and the first
+/// error is returned.
+///
+/// The service must be open with the access rights required by [`restart`].
+pub fn restart_with_dependents(
+    manager: &impl ScmBackend,
+    name: &OsStr,
+    service: &impl ServiceBackend,
+    arguments: &[&OsStr],
+    timeout: Duration,
+    clock: &impl Clock,
+) -> Result<ServiceStatus> {
+    let (stopped, stop_result) = stop_dependents_until_failure(manager, name, timeout, clock);
+    let restart_result = stop_result.and_then(|()| restart(service, arguments, timeout, clock));
+
+    let mut start_result = Ok(());
+    for dependent in stopped.iter().rev() {
+        let result = start_dependent(manager, dependent, timeout, clock);
+        if start_result.is_ok() {
+            start_result = result.map(drop);
+        }
+    }
+    let status = restart_result?;
+    start_result.map(|()| status)
+}
+
+fn start_dependent(
+    manager: &impl ScmBackend,
+    name: &OsStr,
+    timeout: Duration,
+    clock: &impl Clock,
+) -> Result<ServiceStatus> {
+    let service = manager.open_service(name, ServiceAccess::START | ServiceAccess::QUERY_STATUS)?;
+    start_and_wait(&service, &[], timeout, clock)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+    use crate::backend::fake::{service_info, FakeScm};
+    use crate::backend::{ScmBackend, StatusBackend};
+    use crate::service::{ServiceControlAccept, ServiceDependency, ServiceType};
+    use crate::service_manager::ServiceManagerAccess;
+
+    /// A clock that advances only when sleeping, and calls `on_sleep` with the time slept so far.
+    struct FakeClock<F> {
+        start: Instant,
+        elapsed: Cell<Duration>,
+        on_sleep: F,
+    }
+
+    impl<F: Fn(Duration)> FakeClock<F> {
+        fn new(on_sleep: F) -> Self {
+            FakeClock {
+                start: Instant::now(),
+                elapsed: Cell::new(Duration::ZERO),
+                on_sleep,
+            }
+        }
+    }
+
+    impl<F: Fn(Duration)> Clock for FakeClock<F> {
+        fn now(&self) -> Instant {
+            self.start + self.elapsed.get()
+        }
+
+        fn sleep(&self, duration: Duration) {
+            self.elapsed.set(self.elapsed.get() + duration);
+            (self.on_sleep)(self.elapsed.get());
+        }
+    }
+
+    fn status(current_state: ServiceState, checkpoint: u32, wait_hint_secs: u64) -> ServiceStatus {
+        ServiceStatus {
+            service_type: ServiceType::OWN_PROCESS,
+            current_state,
+            controls_accepted: ServiceControlAccept::empty(),
+            exit_code: ServiceExitCode::NO_ERROR,
+            checkpoint,
+            wait_hint: Duration::from_secs(wait_hint_secs),
+            process_id: None,
+        }
+    }
+
+    fn current_state(service: &impl ServiceBackend) -> ServiceState {
+        service.query_status().unwrap().current_state
+    }
+
+    #[test]
+    fn test_reached_and_unexpected_states() {
+        let start = Instant::now();
+        let mut waiter = StateWaiter::new(ServiceState::Running, Duration::from_secs(60), start);
+        assert_eq!(
+            waiter.observe(&status(ServiceState::Running, 0, 0), start),
+            Ok(WaitStep::Reached)
+        );
+
+        let mut stopped = status(ServiceState::Stopped, 0, 0);
+        stopped.exit_code = ServiceExitCode::ServiceSpecific(3);
+        assert_eq!(
+            waiter.observe(&stopped, start),
+            Err(WaitError::UnexpectedState {
+                target: ServiceState::Running,
+                status: stopped,
+            })
+        );
+    }
+
+    #[test]
+    fn test_poll_interval() {
+        let start = Instant::now();
+        let mut waiter = StateWaiter::new(ServiceState::Running, Duration::from_secs(60), start);
+        let mut poll = |wait_hint_secs, elapsed_secs| {
+            waiter.observe(
+                &status(
+                    ServiceState::StartPending,
+                    elapsed_secs as u32,
+                    wait_hint_secs,
+                ),
+                start + Duration::from_secs(elapsed_secs),
+            )
+        };
+
+        assert_eq!(poll(30, 0), Ok(WaitStep::Sleep(Duration::from_secs(3))));
+        assert_eq!(poll(5, 1), Ok(WaitStep::Sleep(Duration::from_secs(1))));
+        assert_eq!(poll(0, 2), Ok(WaitStep::Sleep(Duration::from_secs(1))));
+        assert_eq!(poll(300, 3), Ok(WaitStep::Sleep(Duration::from_secs(10))));
+        // The last sleep ends at the deadline.
+        assert_eq!(poll(300, 55), Ok(WaitStep::Sleep(Duration::from_secs(5))));
+    }
+
+    #[test]
+    fn test_unbounded_timeout() {
+        let start = Instant::now();
+        let mut waiter = StateWaiter::new(ServiceState::Running, Duration::MAX, start);
+        let pending = status(ServiceState::StartPending, 0, 0);
+        assert_eq!(
+            waiter.observe(&pending, start + Duration::from_secs(86400 * 365)),
+            Ok(WaitStep::Sleep(Duration::from_secs(1)))
+        );
+    }
+
+    #[test]
+    fn test_checkpoint_progress_and_hung_service() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut waiter = StateWaiter::new(ServiceState::Stopped, Duration::from_secs(60), start);
+
+        // Advancing the checkpoint or changing the state keeps the service alive past the wait
+        // hint.
+        assert!(waiter
+            .observe(&status(ServiceState::StopPending, 1, 5), at(0))
+            .is_ok());
+        assert!(waiter
+            .observe(&status(ServiceState::StopPending, 1, 5), at(5))
+            .is_ok());
+        assert!(waiter
+            .observe(&status(ServiceState::StopPending, 2, 5), at(6))
+            .is_ok());
+        assert!(waiter
+            .observe(&status(ServiceState::StopPending, 2, 5), at(11))
+            .is_ok());
+
+        let stalled = status(ServiceState::StopPending, 2, 5);
+        assert_eq!(
+            waiter.observe(&stalled, at(12)),
+            Err(WaitError::Hung {
+                target: ServiceState::Stopped,
+                status: stalled,
+            })
+        );
+    }
+
+    #[test]
+    fn test_timeout() {
+        let start = Instant::now();
+        let mut waiter = StateWaiter::new(ServiceState::Running, Duration::from_secs(10), start);
+
+        // Without a wait hint the service is never considered hung.
+        let pending = status(ServiceState::StartPending, 0, 0);
+        assert!(waiter.observe(&pending, start).is_ok());
+        assert!(waiter
+            .observe(&pending, start + Duration::from_secs(9))
+            .is_ok());
+        assert_eq!(
+            waiter.observe(&pending, start + Duration::from_secs(10)),
+            Err(WaitError::Timeout {
+                target: ServiceState::Running,
+                status: pending,
+            })
+        );
+    }
+
+    #[test]
+    fn test_state_driver() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        // A starting service settles first, and is stopped once it runs.
+        let mut driver = StateDriver::new(ServiceState::Stopped, Duration::from_secs(60), start);
+        assert_eq!(
+            driver.observe(&status(ServiceState::StartPending, 1, 5), at(0)),
+            Ok(DriveStep::Sleep(Duration::from_secs(1)))
+        );
+        assert_eq!(
+            driver.observe(&status(ServiceState::Running, 0, 0), at(1)),
+            Ok(DriveStep::Control(ServiceControl::Stop))
+        );
+        assert_eq!(
+            driver.observe(&status(ServiceState::StopPending, 1, 5), at(1)),
+            Ok(DriveStep::Sleep(Duration::from_secs(1)))
+        );
+        assert_eq!(
+            driver.observe(&status(ServiceState::Stopped, 0, 0), at(2)),
+            Ok(DriveStep::Reached)
+        );
+
+        // A stopping service that settles in the target state needs no control.
+        let mut driver = StateDriver::new(ServiceState::Stopped, Duration::MAX, start);
+        assert_eq!(
+            driver.observe(&status(ServiceState::StopPending, 1, 5), at(0)),
+            Ok(DriveStep::Sleep(Duration::from_secs(1)))
+        );
+        assert_eq!(
+            driver.observe(&status(ServiceState::Stopped, 0, 0), at(1)),
+            Ok(DriveStep::Reached)
+        );
+
+        // A paused service is resumed, a stopped one started.
+        let mut driver = StateDriver::new(ServiceState::Running, Duration::from_secs(60), start);
+        assert_eq!(
+            driver.observe(&status(ServiceState::Paused, 0, 0), at(0)),
+            Ok(DriveStep::Control(ServiceControl::Continue))
+        );
+        let mut driver = StateDriver::new(ServiceState::Running, Duration::from_secs(60), start);
+        assert_eq!(
+            driver.observe(&status(ServiceState::Stopped, 0, 0), at(0)),
+            Ok(DriveStep::Start)
+        );
+
+        // Stopping instead of starting is an error once the service was started.
+        let stopped = status(ServiceState::Stopped, 0, 0);
+        assert_eq!(
+            driver.observe(&stopped, at(1)),
+            Err(WaitError::UnexpectedState {
+                target: ServiceState::Running,
+                status: stopped,
+            })
+        );
+    }
+
+    #[test]
+    fn test_wait_for_state_with_fake_scm() {
+        let scm = FakeScm::new();
+        scm.set_instant_transitions(false);
+        let manager = scm.connect(ServiceManagerAccess::CREATE_SERVICE);
+        let service = manager
+            .create_service(
+                &service_info("svc", vec![]),
+                ServiceAccess::START | ServiceAccess::QUERY_STATUS,
+            )
+            .unwrap();
+        let status_handle = scm.status_handle("svc").unwrap();
+        service.start(&[]).unwrap();
+
+        // The service reports a new checkpoint every 2 seconds with a wait hint of 3 seconds,
+        // and is running after 20 seconds.
+        let clock = FakeClock::new(|elapsed: Duration| {
+            let next_status = if elapsed >= Duration::from_secs(20) {
+                status(ServiceState::Running, 0, 0)
+            } else {
+                status(ServiceState::StartPending, elapsed.as_secs() as u32 / 2, 3)
+            };
+            status_handle.set_service_status(next_status).unwrap();
+        });
+
+        let final_status = wait_for_state(
+            &service,
+            ServiceState::Running,
+            Duration::from_secs(30),
+            &clock,
+        )
+        .unwrap();
+        assert_eq!(final_status.current_state, ServiceState::Running);
+        assert_eq!(clock.elapsed.get(), Duration::from_secs(20));
+
+        // Stopping is not acknowledged by the service, so it hangs after the wait hint.
+        let clock = FakeClock::new(|_| {});
+        status_handle
+            .set_service_status(status(ServiceState::StopPending, 1, 3))
+            .unwrap();
+        let result = wait_for_state(
+            &service,
+            ServiceState::Stopped,
+            Duration::from_secs(30),
+            &clock,
+        );
+        assert!(matches!(result, Err(Error::Wait(WaitError::Hung { .. }))));
+        assert_eq!(clock.elapsed.get(), Duration::from_secs(4));
+    }
+
+    #[test]
+    fn test_start_and_wait_reports_exit_code() {
+        let scm = FakeScm::new();
+        scm.set_instant_transitions(false);
+        let manager = scm.connect(ServiceManagerAccess::CREATE_SERVICE);
+        let service = manager
+            .create_service(
+                &service_info("svc", vec![]),
+                ServiceAccess::START | ServiceAccess::STOP | ServiceAccess::QUERY_STATUS,
+            )
+            .unwrap();
+        let status_handle = scm.status_handle("svc").unwrap();
+
+        // The service fails its initialization after 5 seconds.
+        let clock = FakeClock::new(|elapsed: Duration| {
+            if elapsed >= Duration::from_secs(5) {
+                let mut stopped = status(ServiceState::Stopped, 0, 0);
+                stopped.exit_code = ServiceExitCode::ServiceSpecific(42);
+                status_handle.set_service_status(stopped).unwrap();
+            }
+        });
+        let error = match start_and_wait(&service, &[], Duration::from_secs(30), &clock) {
+            Err(Error::Wait(error)) => error,
+            result => panic!("unexpected result: {:?}", result),
+        };
+        assert_eq!(
+            error.exit_code(),
+            Some(ServiceExitCode::ServiceSpecific(42))
+        );
+
+        // Stopping a service that is still starting waits for the start to complete first.
+        service.start(&[]).unwrap();
+        let clock = FakeClock::new(|elapsed: Duration| {
+            let current_state = match current_state(&service) {
+                ServiceState::StartPending if elapsed >= Duration::from_secs(3) => {
+                    ServiceState::Running
+                }
+                ServiceState::StopPending => ServiceState::Stopped,
+                current_state => current_state,
+            };
+            let mut next_status = status(current_state, 0, 0);
+            next_status.controls_accepted = ServiceControlAccept::STOP;
+            status_handle.set_service_status(next_status).unwrap();
+        });
+        let final_status = stop_and_wait(&service, Duration::from_secs(30), &clock).unwrap();
+        assert_eq!(final_status.current_state, ServiceState::Stopped);
+        assert_eq!(final_status.exit_code, ServiceExitCode::NO_ERROR);
+        assert_eq!(clock.elapsed.get(), Duration::from_secs(4));
+    }
+
+    #[test]
+    fn test_restart_with_dependents() {
+        let scm = FakeScm::new();
+        let manager = scm.connect(ServiceManagerAccess::CREATE_SERVICE);
+        let service_access =
+            ServiceAccess::START | ServiceAccess::STOP | ServiceAccess::QUERY_STATUS;
+        let base = manager
+            .create_service(&service_info("base", vec![]), service_access)
+            .unwrap();
+        manager
+            .create_service(&service_info("grouped", vec![]), service_access)
+            .unwrap();
+        scm.set_load_order_group("grouped", Some("group".into()))
+            .unwrap();
+        let dependency = |name: &str| ServiceDependency::Service(name.into());
+        let services = [
+            ("middle", vec![dependency("base")]),
+            (
+                "top",
+                vec![
+                    dependency("middle"),
+                    ServiceDependency::Group("group".into()),
+                ],
+            ),
+            ("idle", vec![dependency("base")]),
+        ];
+        for (name, dependencies) in services {
+            manager
+                .create_service(&service_info(name, dependencies), service_access)
+                .unwrap();
+        }
+
+        // Starting "top" starts everything but "idle".
+        manager
+            .open_service(OsStr::new("top"), service_access)
+            .unwrap()
+            .start(&[])
+            .unwrap();
+        let process_id = |name: &str| {
+            manager
+                .open_service(OsStr::new(name), service_access)
+                .unwrap()
+                .query_status()
+                .unwrap()
+                .process_id
+        };
+        let before: Vec<_> = ["base", "middle", "top"].map(process_id).into();
+
+        let clock = FakeClock::new(|_| {});
+        let timeout = Duration::from_secs(30);
+        assert!(matches!(
+            restart(&base, &[], timeout, &clock),
+            Err(Error::Winapi(ref e)) if e.raw_os_error() == Some(1051)
+        ));
+
+        let status = restart_with_dependents(
+            &manager,
+            OsStr::new("base"),
+            &base,
+            &[OsStr::new("--fresh")],
+            timeout,
+            &clock,
+        )
+        .unwrap();
+        assert_eq!(status.current_state, ServiceState::Running);
+        assert_eq!(
+            scm.start_arguments("base"),
+            Some(vec![OsString::from("--fresh")])
+        );
+        for (name, before) in ["base", "middle", "top"].iter().zip(before) {
+            let after = process_id(name);
+            assert!(
+                after.is_some() && after != before,
+                "{} was not restarted",
+                name
+            );
+        }
+        assert_eq!(process_id("idle"), None);
+
+        // Stopping the group member takes down only the service depending on the group.
+        assert_eq!(
+            stop_dependents(&manager, OsStr::new("grouped"), timeout, &clock).unwrap(),
+            vec![OsString::from("top")]
+        );
+        assert_eq!(
+            stop_dependents(&manager, OsStr::new("base"), timeout, &clock).unwrap(),
+            vec![OsString::from("middle")]
+        );
+    }
+
+    #[test]
+    fn test_restart_with_dependents_restarts_dependents_on_failure() {
+        let scm = FakeScm::new();
+        let manager = scm.connect(ServiceManagerAccess::CREATE_SERVICE);
+        let service_access =
+            ServiceAccess::START | ServiceAccess::STOP | ServiceAccess::QUERY_STATUS;
+        manager
+            .create_service(&service_info("base", vec![]), service_access)
+            .unwrap();
+        let dependent = manager
+            .create_service(
+                &service_info("dependent", vec![ServiceDependency::Service("base".into())]),
+                service_access,
+            )
+            .unwrap();
+        dependent.start(&[]).unwrap();
+
+        // Without the start access right, the base service stops but fails to start again.
+        let base = manager
+            .open_service(
+                OsStr::new("base"),
+                ServiceAccess::STOP | ServiceAccess::QUERY_STATUS,
+            )
+            .unwrap();
+        let clock = FakeClock::new(|_| {});
+        let result = restart_with_dependents(
+            &manager,
+            OsStr::new("base"),
+            &base,
+            &[],
+            Duration::from_secs(30),
+            &clock,
+        );
+        assert!(matches!(
+            result,
+            Err(Error::Winapi(ref e)) if e.raw_os_error() == Some(5)
+        ));
+        assert_eq!(current_state(&dependent), ServiceState::Running);
+        assert_eq!(current_state(&base), ServiceState::Running);
+
+        // A timeout too long to be represented means waiting without a deadline.
+        let status = stop_and_wait(&dependent, Duration::MAX, &clock).unwrap();
+        assert_eq!(status.current_state, ServiceState::Stopped);
+    }
+
+    #[test]
+    fn test_restart_with_dependents_restarts_dependents_on_stop_failure() {
+        let scm = FakeScm::new();
+        let manager = scm.connect(ServiceManagerAccess::CREATE_SERVICE);
+        let service_access =
+            ServiceAccess::START | ServiceAccess::STOP | ServiceAccess::QUERY_STATUS;
+        let base = manager
+            .create_service(&service_info("base", vec![]), service_access)
+            .unwrap();
+        let dependency = |name: &str| vec![ServiceDependency::Service(name.into())];
+        for (name, dependencies) in [
+            ("bottom", dependency("base")),
+            ("middle", dependency("bottom")),
+            ("top", dependency("middle")),
+        ] {
+            manager
+                .create_service(&service_info(name, dependencies), service_access)
+                .unwrap();
+        }
+        // "top" is stopped first, then "middle" refuses to stop.
+        scm.set_controls_accepted("middle", ServiceControlAccept::empty())
+            .unwrap();
+        manager
+            .open_service(OsStr::new("top"), service_access)
+            .unwrap()
+            .start(&[])
+            .unwrap();
+        let service = |name: &str| {
+            manager
+                .open_service(OsStr::new(name), service_access)
+                .unwrap()
+        };
+        let process_id = |name: &str| service(name).query_status().unwrap().process_id;
+        let before: Vec<_> = ["top", "base"].map(process_id).into();
+
+        let clock = FakeClock::new(|_| {});
+        let result = restart_with_dependents(
+            &manager,
+            OsStr::new("base"),
+            &base,
+            &[],
+            Duration::from_secs(30),
+            &clock,
+        );
+        assert!(matches!(
+            result,
+            Err(Error::Winapi(ref e)) if e.raw_os_error() == Some(1052)
+        ));
+        for name in ["top", "middle", "bottom", "base"] {
+            assert_eq!(
+                current_state(&service(name)),
+                ServiceState::Running,
+                "{}",
+                name
+            );
+        }
+        // "top" was stopped and started again, but the service is not restarted when its
+        // dependents cannot be stopped.
+        assert_ne!(process_id("top"), before[0]);
+        assert_eq!(process_id("base"), before[1]);
+    }
+}